@@ -1,10 +1,30 @@
-use anyhow::Result;
-use quickjs_rusty::Context;
+use quickjs_rusty::module_loader::{ImportAttributes, ModuleLoader, ModuleResolver, ModuleSource};
+use quickjs_rusty::{Context, ModuleError};
 
 struct Custom {
     pub foo: i32,
 }
 
+impl ModuleLoader for Custom {
+    fn load(
+        &self,
+        module_name: &str,
+        _attributes: &ImportAttributes,
+    ) -> Result<ModuleSource, ModuleError> {
+        println!("module_loader: {:?}", module_name);
+        assert!(self.foo == 123);
+        Ok("export function add(a, b) { return a + b; }; console.log('module loaded.')".into())
+    }
+}
+
+impl ModuleResolver for Custom {
+    fn resolve(&self, module_base_name: &str, module_name: &str) -> Result<String, ModuleError> {
+        println!("module_normalize: {:?} {:?}", module_base_name, module_name);
+        assert!(self.foo == 123);
+        Ok(module_name.to_string())
+    }
+}
+
 pub fn main() {
     let context = Context::builder()
         .console(|level, args| {
@@ -13,13 +33,8 @@ pub fn main() {
         .build()
         .unwrap();
 
-    let custom = Custom { foo: 123 };
-
-    context.set_module_loader(
-        Box::new(module_loader),
-        Some(Box::new(module_normalize)),
-        &custom as *const _ as *mut _,
-    );
+    context.set_module_loader(Custom { foo: 123 });
+    context.set_module_resolver(Custom { foo: 123 });
 
     context.run_module("./m").unwrap();
 
@@ -31,21 +46,3 @@ pub fn main() {
         .unwrap();
     println!("js: 1 + 2 = {:?}", value);
 }
-
-fn module_loader(module_name: &str, opaque: *mut std::ffi::c_void) -> Result<String> {
-    println!("module_loader: {:?}", module_name);
-    let custom = unsafe { &*(opaque as *mut Custom) };
-    assert!(custom.foo == 123);
-    Ok("export function add(a, b) { return a + b; }; console.log('module loaded.')".to_string())
-}
-
-fn module_normalize(
-    module_base_name: &str,
-    module_name: &str,
-    opaque: *mut std::ffi::c_void,
-) -> Result<String> {
-    println!("module_normalize: {:?} {:?}", module_base_name, module_name);
-    let custom = unsafe { &*(opaque as *mut Custom) };
-    assert!(custom.foo == 123);
-    Ok(module_name.to_string())
-}
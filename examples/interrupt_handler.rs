@@ -1,7 +1,5 @@
-use std::ffi::c_void;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant};
 
-use libquickjs_ng_sys::JSRuntime;
 use quickjs_rusty::Context;
 
 const TIMEOUT_MS: u64 = 500;
@@ -17,10 +15,15 @@ pub fn main() {
         .build()
         .unwrap();
 
-    let timeout = Box::new(SystemTime::now() + Duration::from_millis(TIMEOUT_MS));
-    let ptr = Box::into_raw(timeout);
-
-    context.set_interrupt_handler(Some(interrupt_handler), ptr as *mut c_void);
+    let timeout = Instant::now() + Duration::from_millis(TIMEOUT_MS);
+    context.set_interrupt(move || {
+        if Instant::now() > timeout {
+            println!("timeout occurred");
+            true
+        } else {
+            false
+        }
+    });
 
     let value = context.eval("1 + 2", false).unwrap();
     println!("eval success: 1 + 2 = {:?}", value);
@@ -37,17 +40,11 @@ pub fn main() {
     if let Err(e) = ret {
         eprintln!("Error: {:?}", e.to_string());
     }
-}
 
-extern "C" fn interrupt_handler(
-    _rt: *mut JSRuntime,
-    opaque: *mut std::ffi::c_void,
-) -> ::std::os::raw::c_int {
-    let ts: SystemTime = unsafe { std::ptr::read(opaque as *const SystemTime) };
-    if SystemTime::now().gt(&ts) {
-        println!("timeout occurred");
-        1
-    } else {
-        0
+    // The same, with a timeout for a single evaluation.
+    context.runtime().remove_interrupt();
+    let ret = context.eval_with_timeout("for(;;) {}", Duration::from_millis(TIMEOUT_MS));
+    if let Err(e) = ret {
+        eprintln!("Error: {:?}", e.to_string());
     }
 }
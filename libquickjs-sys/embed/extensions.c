@@ -349,3 +349,208 @@ JSCFunctionListEntry JS_Ext_Alias_Base_Def(const char *name, const char *from, i
     JSCFunctionListEntry tmp = JS_ALIAS_BASE_DEF(name, from, base);
     return tmp;
 }
+
+// Iterate over the loaded modules of a context, starting with m = NULL.
+// Returns NULL after the last module.
+JSModuleDef *JS_Ext_GetNextModule(JSContext *ctx, JSModuleDef *m)
+{
+    struct list_head *el = m ? m->link.next : ctx->loaded_modules.next;
+    if (el == &ctx->loaded_modules)
+    {
+        return NULL;
+    }
+    return list_entry(el, JSModuleDef, link);
+}
+
+int JS_Ext_GetModuleRequestCount(JSModuleDef *m)
+{
+    return m->req_module_entries_count;
+}
+
+// The module imported by the request at index, NULL if it is not resolved.
+JSModuleDef *JS_Ext_GetModuleRequest(JSModuleDef *m, int index)
+{
+    if (index < 0 || index >= m->req_module_entries_count)
+    {
+        return NULL;
+    }
+    return m->req_module_entries[index].module;
+}
+
+// Remove a module from the loaded modules, so that importing it again loads
+// a new instance. The module must be relinked before the context is freed.
+void JS_Ext_UnlinkModule(JSModuleDef *m)
+{
+    list_del(&m->link);
+}
+
+void JS_Ext_RelinkModule(JSContext *ctx, JSModuleDef *m)
+{
+    list_add_tail(&m->link, &ctx->loaded_modules);
+}
+
+// Normalize a module name like an import does, with the module normalize
+// function of the runtime if one is set. The result must be freed with
+// js_free, NULL is returned with an exception on failure.
+char *JS_Ext_NormalizeModuleName(JSContext *ctx, const char *base_name, const char *name)
+{
+    JSRuntime *rt = ctx->rt;
+    if (rt->module_normalize_func)
+    {
+        return rt->module_normalize_func(ctx, base_name, name, rt->module_loader_opaque);
+    }
+    return js_default_module_normalize_name(ctx, base_name, name);
+}
+
+// The opaque passed to the module loader functions of the runtime.
+void *JS_Ext_GetModuleLoaderOpaque(JSContext *ctx)
+{
+    return ctx->rt->module_loader_opaque;
+}
+
+// An uncatchable "interrupted" error, like the one thrown when the interrupt
+// handler stops the execution.
+JSValue JS_Ext_NewInterruptedError(JSContext *ctx)
+{
+    JSValue error;
+
+    JS_ThrowInternalError(ctx, "interrupted");
+    error = JS_GetException(ctx);
+    if (JS_VALUE_GET_TAG(error) == JS_TAG_OBJECT)
+        JS_VALUE_GET_OBJ(error)->is_uncatchable_error = true;
+    return error;
+}
+
+// The interrupt counter of a context is decremented at each backward jump and
+// function call. When it reaches zero, it is reset to the initial value and
+// the interrupt handler is called.
+int JS_Ext_GetInterruptCounter(JSContext *ctx)
+{
+    return ctx->interrupt_counter;
+}
+
+void JS_Ext_SetInterruptCounter(JSContext *ctx, int counter)
+{
+    ctx->interrupt_counter = counter;
+}
+
+int JS_Ext_GetInterruptCounterInit(void)
+{
+    return JS_INTERRUPT_COUNTER_INIT;
+}
+
+// Functions like the ones of JS_NewCFunctionData, except that `func` gets
+// whether it is called as a constructor instead of the magic value. Called as
+// a constructor, `this_val` is new.target, like for JS_CFUNC_constructor_or_func
+// functions, which JS_NewCFunctionData can not tell apart from plain calls.
+
+typedef struct JSExtFunctionDataRecord
+{
+    JSCFunctionData *func;
+    int length;
+    int data_len;
+    JSValue data[];
+} JSExtFunctionDataRecord;
+
+static JSExtFunctionDataRecord *js_ext_function_data(JSValueConst val)
+{
+    return JS_VALUE_GET_OBJ(val)->u.opaque;
+}
+
+static void js_ext_function_data_finalizer(JSRuntime *rt, JSValueConst val)
+{
+    JSExtFunctionDataRecord *s = js_ext_function_data(val);
+    int i;
+
+    if (s)
+    {
+        for (i = 0; i < s->data_len; i++)
+            JS_FreeValueRT(rt, s->data[i]);
+        js_free_rt(rt, s);
+    }
+}
+
+static void js_ext_function_data_mark(JSRuntime *rt, JSValueConst val,
+                                      JS_MarkFunc *mark_func)
+{
+    JSExtFunctionDataRecord *s = js_ext_function_data(val);
+    int i;
+
+    if (s)
+    {
+        for (i = 0; i < s->data_len; i++)
+            JS_MarkValue(rt, s->data[i], mark_func);
+    }
+}
+
+static JSValue js_ext_function_data_call(JSContext *ctx, JSValueConst func_obj,
+                                         JSValueConst this_val, int argc,
+                                         JSValueConst *argv, int flags)
+{
+    JSExtFunctionDataRecord *s = js_ext_function_data(func_obj);
+    JSValueConst *arg_buf;
+    int i;
+
+    // Missing arguments are undefined, like for JS_NewCFunctionData.
+    if (unlikely(argc < s->length))
+    {
+        arg_buf = alloca(sizeof(arg_buf[0]) * s->length);
+        for (i = 0; i < argc; i++)
+            arg_buf[i] = argv[i];
+        for (i = argc; i < s->length; i++)
+            arg_buf[i] = JS_UNDEFINED;
+    }
+    else
+    {
+        arg_buf = argv;
+    }
+
+    return s->func(ctx, this_val, argc, arg_buf,
+                   (flags & JS_CALL_FLAG_CONSTRUCTOR) != 0, s->data);
+}
+
+// The class of the functions is registered in the runtime on first use, with
+// its id stored in `*pclass_id`.
+JSValue JS_Ext_NewCFunctionDataNewTarget(JSContext *ctx, JSClassID *pclass_id,
+                                         JSCFunctionData *func, int length,
+                                         int data_len, JSValue *data)
+{
+    JSRuntime *rt = JS_GetRuntime(ctx);
+    JSExtFunctionDataRecord *s;
+    JSValue func_obj;
+    int i;
+
+    if (*pclass_id == 0)
+    {
+        JSClassID class_id = 0;
+        JSClassDef def = {
+            .class_name = "Function",
+            .finalizer = js_ext_function_data_finalizer,
+            .gc_mark = js_ext_function_data_mark,
+            .call = js_ext_function_data_call,
+        };
+
+        JS_NewClassID(rt, &class_id);
+        if (JS_NewClass(rt, class_id, &def) < 0)
+            return JS_ThrowInternalError(ctx, "could not register the function class");
+        *pclass_id = class_id;
+    }
+
+    func_obj = JS_NewObjectProtoClass(ctx, ctx->function_proto, *pclass_id);
+    if (JS_IsException(func_obj))
+        return func_obj;
+    s = js_malloc(ctx, sizeof(*s) + data_len * sizeof(JSValue));
+    if (!s)
+    {
+        JS_FreeValue(ctx, func_obj);
+        return JS_EXCEPTION;
+    }
+    s->func = func;
+    s->length = length;
+    s->data_len = data_len;
+    for (i = 0; i < data_len; i++)
+        s->data[i] = js_dup(data[i]);
+    JS_VALUE_GET_OBJ(func_obj)->u.opaque = s;
+    js_function_set_properties(ctx, func_obj, JS_ATOM_empty_string, length);
+    return func_obj;
+}
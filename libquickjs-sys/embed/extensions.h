@@ -86,6 +86,24 @@ extern "C"
   JSCFunctionListEntry JS_Ext_Alias_Def(const char *name, const char *from);
   JSCFunctionListEntry JS_Ext_Alias_Base_Def(const char *name, const char *from, int base);
 
+  // Access to the modules loaded in a context, which quickjs does not expose.
+  JSModuleDef *JS_Ext_GetNextModule(JSContext *ctx, JSModuleDef *m);
+  int JS_Ext_GetModuleRequestCount(JSModuleDef *m);
+  JSModuleDef *JS_Ext_GetModuleRequest(JSModuleDef *m, int index);
+  void JS_Ext_UnlinkModule(JSModuleDef *m);
+  void JS_Ext_RelinkModule(JSContext *ctx, JSModuleDef *m);
+  char *JS_Ext_NormalizeModuleName(JSContext *ctx, const char *base_name, const char *name);
+  void *JS_Ext_GetModuleLoaderOpaque(JSContext *ctx);
+
+  JSValue JS_Ext_NewInterruptedError(JSContext *ctx);
+  int JS_Ext_GetInterruptCounter(JSContext *ctx);
+  void JS_Ext_SetInterruptCounter(JSContext *ctx, int counter);
+  int JS_Ext_GetInterruptCounterInit(void);
+
+  JSValue JS_Ext_NewCFunctionDataNewTarget(JSContext *ctx, JSClassID *pclass_id,
+                                           JSCFunctionData *func, int length,
+                                           int data_len, JSValue *data);
+
 #ifdef __cplusplus
 }
 #endif
@@ -0,0 +1,68 @@
+use proc_macro2::TokenStream;
+use syn::parse::Parser;
+use syn::{Attribute, LitStr, Result};
+
+/// Options given with `#[js(...)]` helper attributes.
+#[derive(Default)]
+pub struct JsAttrs {
+    pub rename: Option<String>,
+    pub skip: bool,
+    pub constructor: bool,
+    pub getter: bool,
+    pub setter: bool,
+}
+
+impl JsAttrs {
+    /// Parse the `#[js(...)]` attributes, only accepting the `allowed` options.
+    pub fn parse(attrs: &[Attribute], allowed: &[&str]) -> Result<Self> {
+        let mut options = Self::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                let Some(ident) = meta.path.get_ident().map(|i| i.to_string()) else {
+                    return Err(meta.error("expected an identifier"));
+                };
+                if !allowed.contains(&ident.as_str()) {
+                    return Err(meta.error(format!("unsupported js attribute `{}`", ident)));
+                }
+
+                match ident.as_str() {
+                    "rename" => {
+                        options.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                    }
+                    "skip" => options.skip = true,
+                    "constructor" => options.constructor = true,
+                    "getter" => options.getter = true,
+                    "setter" => options.setter = true,
+                    _ => unreachable!(),
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(options)
+    }
+
+    /// Parse and remove the `#[js(...)]` attributes.
+    pub fn take(attrs: &mut Vec<Attribute>, allowed: &[&str]) -> Result<Self> {
+        let options = Self::parse(attrs, allowed)?;
+        attrs.retain(|attr| !attr.path().is_ident("js"));
+        Ok(options)
+    }
+}
+
+/// Parse the `name = "..."` argument of the `#[js_function]` and `#[js_class]`
+/// attributes.
+pub fn parse_name_arg(args: TokenStream) -> Result<Option<String>> {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        } else {
+            Err(meta.error("unsupported argument, expected `name = \"...\"`"))
+        }
+    });
+    parser.parse2(args)?;
+    Ok(name)
+}
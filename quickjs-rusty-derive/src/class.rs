@@ -0,0 +1,170 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, FnArg, ImplItem, ItemImpl, ItemStruct, Pat, Result, Visibility};
+
+use crate::attr::{parse_name_arg, JsAttrs};
+
+pub fn expand_class(args: TokenStream, item: ItemStruct) -> Result<TokenStream> {
+    let name = parse_name_arg(args)?.unwrap_or_else(|| item.ident.to_string());
+
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &item.generics,
+            "#[js_class] does not support generic types",
+        ));
+    }
+
+    let ident = &item.ident;
+    Ok(quote! {
+        #item
+
+        impl ::quickjs_rusty::JsClass for #ident {
+            const NAME: &'static str = #name;
+
+            fn define(class: &mut ::quickjs_rusty::ClassBuilder<Self>) {
+                <Self as ::quickjs_rusty::JsClassMembers>::define_members(class)
+            }
+        }
+    })
+}
+
+enum Receiver {
+    None,
+    Ref,
+    Mut,
+}
+
+pub fn expand_methods(mut item: ItemImpl) -> Result<TokenStream> {
+    if !item.generics.params.is_empty() || item.trait_.is_some() {
+        return Err(Error::new_spanned(
+            &item.self_ty,
+            "#[js_methods] only supports inherent impls of non-generic types",
+        ));
+    }
+
+    let mut members = Vec::new();
+
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        let options = JsAttrs::take(
+            &mut method.attrs,
+            &["rename", "skip", "constructor", "getter", "setter"],
+        )?;
+        if options.skip {
+            continue;
+        }
+
+        let sig = &method.sig;
+        if !sig.generics.params.is_empty() {
+            return Err(Error::new_spanned(
+                &sig.generics,
+                "#[js_methods] does not support generic methods, mark them with #[js(skip)]",
+            ));
+        }
+
+        let receiver = match sig.inputs.first() {
+            Some(FnArg::Receiver(r)) if r.reference.is_none() => {
+                return Err(Error::new_spanned(
+                    r,
+                    "methods taking `self` by value can not be exposed, mark them with #[js(skip)]",
+                ));
+            }
+            Some(FnArg::Receiver(r)) if r.mutability.is_some() => Receiver::Mut,
+            Some(FnArg::Receiver(_)) => Receiver::Ref,
+            _ => Receiver::None,
+        };
+
+        // Only public methods are exposed unless explicitly marked.
+        let marked = options.constructor || options.getter || options.setter;
+        if !marked
+            && (!matches!(method.vis, Visibility::Public(_)) || matches!(receiver, Receiver::None))
+        {
+            continue;
+        }
+
+        let fn_ident = &sig.ident;
+        let args = sig
+            .inputs
+            .iter()
+            .filter_map(|arg| match arg {
+                FnArg::Typed(arg) => Some(arg),
+                FnArg::Receiver(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let arg_idents = (0..args.len())
+            .map(|i| format_ident!("__arg{}", i))
+            .collect::<Vec<_>>();
+        let arg_types = args.iter().map(|arg| &arg.ty).collect::<Vec<_>>();
+        for arg in &args {
+            if !matches!(*arg.pat, Pat::Ident(_) | Pat::Wild(_)) {
+                return Err(Error::new_spanned(&arg.pat, "unsupported argument pattern"));
+            }
+        }
+
+        let rust_name = fn_ident.to_string();
+        if options.constructor {
+            if !matches!(receiver, Receiver::None) {
+                return Err(Error::new_spanned(sig, "a constructor can not take `self`"));
+            }
+            members.push(quote! {
+                class.constructor(|#( #arg_idents: #arg_types ),*| Self::#fn_ident(#( #arg_idents ),*));
+            });
+        } else if options.getter {
+            if !matches!(receiver, Receiver::Ref) || !args.is_empty() {
+                return Err(Error::new_spanned(sig, "a getter must only take `&self`"));
+            }
+            let name = options.rename.unwrap_or_else(|| {
+                rust_name
+                    .strip_prefix("get_")
+                    .unwrap_or(&rust_name)
+                    .to_string()
+            });
+            members.push(quote! {
+                class.getter(#name, |this: &Self| Self::#fn_ident(this));
+            });
+        } else if options.setter {
+            if !matches!(receiver, Receiver::Mut) || args.len() != 1 {
+                return Err(Error::new_spanned(
+                    sig,
+                    "a setter must take `&mut self` and a single value",
+                ));
+            }
+            let name = options.rename.unwrap_or_else(|| {
+                rust_name
+                    .strip_prefix("set_")
+                    .unwrap_or(&rust_name)
+                    .to_string()
+            });
+            members.push(quote! {
+                class.setter(#name, |this: &mut Self, #( #arg_idents: #arg_types ),*| {
+                    Self::#fn_ident(this, #( #arg_idents ),*);
+                });
+            });
+        } else {
+            let name = options.rename.unwrap_or(rust_name);
+            let this_type = match receiver {
+                Receiver::Mut => quote!(&mut Self),
+                _ => quote!(&Self),
+            };
+            members.push(quote! {
+                class.method(#name, |this: #this_type, #( #arg_idents: #arg_types ),*| {
+                    Self::#fn_ident(this, #( #arg_idents ),*)
+                });
+            });
+        }
+    }
+
+    let self_ty = &item.self_ty;
+    Ok(quote! {
+        #item
+
+        impl ::quickjs_rusty::JsClassMembers for #self_ty {
+            #[allow(unused_variables)]
+            fn define_members(class: &mut ::quickjs_rusty::ClassBuilder<Self>) {
+                #( #members )*
+            }
+        }
+    })
+}
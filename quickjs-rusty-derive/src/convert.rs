@@ -0,0 +1,174 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Error, Fields, Generics, Result};
+
+use crate::attr::JsAttrs;
+
+/// Add `bound` to every type parameter.
+fn add_bounds(generics: &Generics, bound: impl Fn(&syn::Ident) -> syn::WherePredicate) -> Generics {
+    let mut generics = generics.clone();
+    let predicates = generics
+        .type_params()
+        .map(|param| bound(&param.ident))
+        .collect::<Vec<_>>();
+    generics.make_where_clause().predicates.extend(predicates);
+    generics
+}
+
+/// The names of the unit variants of an enum, which are converted from and to strings.
+fn unit_variants(input: &DeriveInput) -> Result<Vec<(syn::Ident, String)>> {
+    let Data::Enum(data) = &input.data else {
+        unreachable!()
+    };
+
+    data.variants
+        .iter()
+        .map(|variant| {
+            if !matches!(variant.fields, Fields::Unit) {
+                return Err(Error::new_spanned(
+                    variant,
+                    "only enums with unit variants are supported",
+                ));
+            }
+            let options = JsAttrs::parse(&variant.attrs, &["rename"])?;
+            let name = options.rename.unwrap_or_else(|| variant.ident.to_string());
+            Ok((variant.ident.clone(), name))
+        })
+        .collect()
+}
+
+pub fn expand_into_js(input: DeriveInput) -> Result<TokenStream> {
+    let ident = &input.ident;
+    let generics = add_bounds(
+        &input.generics,
+        |param| parse_quote!(#param: ::quickjs_rusty::ToOwnedJsValue),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let mut setters = Vec::new();
+                for field in &fields.named {
+                    let options = JsAttrs::parse(&field.attrs, &["rename", "skip"])?;
+                    if options.skip {
+                        continue;
+                    }
+                    let field_ident = field.ident.as_ref().unwrap();
+                    let name = options.rename.unwrap_or_else(|| field_ident.to_string());
+                    setters.push(quote! {
+                        ::quickjs_rusty::derive::set_field(&object, #name, self.#field_ident);
+                    });
+                }
+                quote! {
+                    let object = ::quickjs_rusty::derive::new_object(context);
+                    #( #setters )*
+                    object.into_value()
+                }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                quote!((context, self.0).into())
+            }
+            Fields::Unnamed(_) => {
+                return Err(Error::new_spanned(
+                    ident,
+                    "tuple structs are only supported with a single field",
+                ))
+            }
+            Fields::Unit => quote!((context, ()).into()),
+        },
+        Data::Enum(_) => {
+            let (variants, names): (Vec<_>, Vec<_>) = unit_variants(&input)?.into_iter().unzip();
+            quote! {
+                let name = match self {
+                    #( Self::#variants => #names, )*
+                };
+                (context, name).into()
+            }
+        }
+        Data::Union(_) => return Err(Error::new_spanned(ident, "unions are not supported")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::quickjs_rusty::ToOwnedJsValue for #ident #ty_generics #where_clause {
+            fn to_owned(
+                self,
+                context: *mut ::quickjs_rusty::JSContext,
+            ) -> ::quickjs_rusty::OwnedJsValue {
+                #body
+            }
+        }
+    })
+}
+
+pub fn expand_from_js(input: DeriveInput) -> Result<TokenStream> {
+    let ident = &input.ident;
+    let generics = add_bounds(
+        &input.generics,
+        |param| parse_quote!(#param: ::quickjs_rusty::derive::FromJsValue),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let mut getters = Vec::new();
+                for field in &fields.named {
+                    let options = JsAttrs::parse(&field.attrs, &["rename", "skip"])?;
+                    let field_ident = field.ident.as_ref().unwrap();
+                    if options.skip {
+                        getters.push(quote!(#field_ident: ::std::default::Default::default()));
+                        continue;
+                    }
+                    let name = options.rename.unwrap_or_else(|| field_ident.to_string());
+                    getters.push(quote! {
+                        #field_ident: ::quickjs_rusty::derive::get_field(&object, #name)?
+                    });
+                }
+                quote! {
+                    let object = value.try_into_object()?;
+                    Ok(Self { #( #getters, )* })
+                }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                quote!(Ok(Self(
+                    ::quickjs_rusty::derive::FromJsValue::from_js_value(value)?
+                )))
+            }
+            Fields::Unnamed(_) => {
+                return Err(Error::new_spanned(
+                    ident,
+                    "tuple structs are only supported with a single field",
+                ))
+            }
+            Fields::Unit => quote!(Ok(Self)),
+        },
+        Data::Enum(_) => {
+            let (variants, names): (Vec<_>, Vec<_>) = unit_variants(&input)?.into_iter().unzip();
+            quote! {
+                let name = value.to_string()?;
+                match name.as_str() {
+                    #( #names => Ok(Self::#variants), )*
+                    _ => Err(::quickjs_rusty::ValueError::Internal(
+                        ::std::format!("Unknown variant '{}'", name),
+                    )),
+                }
+            }
+        }
+        Data::Union(_) => return Err(Error::new_spanned(ident, "unions are not supported")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::std::convert::TryFrom<::quickjs_rusty::OwnedJsValue>
+            for #ident #ty_generics #where_clause
+        {
+            type Error = ::quickjs_rusty::ValueError;
+
+            fn try_from(
+                value: ::quickjs_rusty::OwnedJsValue,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,51 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, FnArg, ItemFn, Result};
+
+use crate::attr::parse_name_arg;
+
+pub fn expand(args: TokenStream, item: ItemFn) -> Result<TokenStream> {
+    let name = parse_name_arg(args)?.unwrap_or_else(|| item.sig.ident.to_string());
+
+    let sig = &item.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "#[js_function] does not support generic functions",
+        ));
+    }
+    if let Some(FnArg::Receiver(receiver)) = sig.inputs.first() {
+        return Err(Error::new_spanned(
+            receiver,
+            "#[js_function] does not support methods, use #[js_methods] instead",
+        ));
+    }
+
+    let vis = &item.vis;
+    let ident = &sig.ident;
+    let def = format_ident!("js_{}", ident);
+    let create = if sig.asyncness.is_some() {
+        quote!(create_async_callback)
+    } else {
+        quote!(create_callback)
+    };
+    let doc = format!("Javascript binding of [`{}`], named `{}`.", ident, name);
+
+    Ok(quote! {
+        #item
+
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        #vis struct #def;
+
+        impl ::quickjs_rusty::JsFunctionDef for #def {
+            const NAME: &'static str = #name;
+
+            fn create(
+                context: &::quickjs_rusty::Context,
+            ) -> ::std::result::Result<::quickjs_rusty::JsFunction, ::quickjs_rusty::ExecutionError> {
+                context.#create(#ident)
+            }
+        }
+    })
+}
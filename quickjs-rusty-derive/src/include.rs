@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::LitStr;
+
+pub fn expand(dir: LitStr) -> syn::Result<TokenStream> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| syn::Error::new(dir.span(), "CARGO_MANIFEST_DIR is not set"))?;
+    let root = Path::new(&manifest_dir).join(dir.value());
+
+    let mut files = Vec::new();
+    collect_files(&root, "", &mut files)
+        .map_err(|e| syn::Error::new(dir.span(), format!("{}: {}", root.display(), e)))?;
+    files.sort();
+
+    let inserts = files.iter().map(|(path, file)| {
+        let file = file.to_str().ok_or_else(|| {
+            syn::Error::new(
+                Span::call_site(),
+                format!("{} is not valid UTF-8", file.display()),
+            )
+        })?;
+        // Files that are not UTF-8 are added as raw bytes, like
+        // `VirtualModuleFs::from_dir` does.
+        let is_source = fs::read(file)
+            .map(|content| std::str::from_utf8(&content).is_ok())
+            .map_err(|e| syn::Error::new(dir.span(), format!("{}: {}", file, e)))?;
+        Ok(if is_source {
+            quote! { fs.insert(#path, include_str!(#file)); }
+        } else {
+            quote! {
+                fs.insert(
+                    #path,
+                    ::quickjs_rusty::module_loader::VirtualFile::Bytes(
+                        ::std::borrow::Cow::Borrowed(include_bytes!(#file)),
+                    ),
+                );
+            }
+        })
+    });
+    let inserts = inserts.collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        {
+            let mut fs = ::quickjs_rusty::module_loader::VirtualModuleFs::new();
+            #(#inserts)*
+            fs
+        }
+    })
+}
+
+/// Collect the files of `dir` and its subdirectories, with their path from
+/// the root.
+fn collect_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<(String, PathBuf)>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let path = format!("{}/{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &path, files)?;
+        } else {
+            files.push((path, entry.path()));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Procedural macros generating [quickjs-rusty](https://docs.rs/quickjs-rusty)
+//! bindings for Rust functions and types.
+//!
+//! The macros are re-exported by `quickjs-rusty` when its `derive` feature
+//! is enabled, and should be used through it.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemFn, ItemImpl, ItemStruct, LitStr};
+
+mod attr;
+mod class;
+mod convert;
+mod function;
+mod include;
+
+/// Expose a function to Javascript.
+///
+/// Generates a `js_<name>` type implementing `JsFunctionDef`, to be passed to
+/// `Context::register_function`. The arguments and return value follow the
+/// same rules as for `Context::add_callback`, and `async` functions return
+/// a promise like `Context::add_async_callback`.
+///
+/// The Javascript name defaults to the function name, and can be set with
+/// `#[js_function(name = "...")]`.
+///
+/// ```ignore
+/// use quickjs_rusty::{js_function, Context};
+///
+/// #[js_function(name = "add")]
+/// fn add_numbers(a: i32, b: i32) -> i32 {
+///     a + b
+/// }
+///
+/// let context = Context::builder().build().unwrap();
+/// context.register_function::<js_add_numbers>().unwrap();
+/// assert_eq!(context.eval_as::<i32>("add(1, 2)").unwrap(), 3);
+/// ```
+#[proc_macro_attribute]
+pub fn js_function(args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
+    function::expand(args.into(), item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implement `JsClass` for a struct, whose members are defined by a
+/// `#[js_methods]` impl block.
+///
+/// The class name defaults to the struct name, and can be set with
+/// `#[js_class(name = "...")]`.
+#[proc_macro_attribute]
+pub fn js_class(args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemStruct);
+    class::expand_class(args.into(), item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Define the members of a `#[js_class]` from an impl block.
+///
+/// Every `pub` method taking `&self` or `&mut self` becomes a method of the
+/// class prototype. The other functions are exposed when marked with:
+///
+/// * `#[js(constructor)]`: the function invoked by `new`, returning `Self`
+///   or `Result<Self, E>`
+/// * `#[js(getter)]`: a getter taking `&self`, named after the function
+///   without its `get_` prefix
+/// * `#[js(setter)]`: a setter taking `&mut self` and the new value, named
+///   after the function without its `set_` prefix
+///
+/// `#[js(rename = "...")]` sets the Javascript name of a member, and
+/// `#[js(skip)]` keeps a public method from being exposed.
+///
+/// ```ignore
+/// use quickjs_rusty::{js_class, js_methods, Context};
+///
+/// #[js_class]
+/// struct Counter {
+///     count: i32,
+/// }
+///
+/// #[js_methods]
+/// impl Counter {
+///     #[js(constructor)]
+///     fn new(count: i32) -> Self {
+///         Counter { count }
+///     }
+///
+///     pub fn increment(&mut self) -> i32 {
+///         self.count += 1;
+///         self.count
+///     }
+///
+///     #[js(getter)]
+///     fn get_count(&self) -> i32 {
+///         self.count
+///     }
+/// }
+///
+/// let context = Context::builder().build().unwrap();
+/// context.register_class::<Counter>().unwrap();
+/// let count = context
+///     .eval_as::<i32>("const c = new Counter(1); c.increment(); c.count")
+///     .unwrap();
+/// assert_eq!(count, 2);
+/// ```
+#[proc_macro_attribute]
+pub fn js_methods(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "#[js_methods] does not take arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let item = parse_macro_input!(item as ItemImpl);
+    class::expand_methods(item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `ToOwnedJsValue`.
+///
+/// Structs with named fields become plain objects, newtype structs convert
+/// as their inner value, and enums with unit variants become the variant
+/// name as a string. Fields and variants can be renamed with
+/// `#[js(rename = "...")]`, and fields left out with `#[js(skip)]`.
+#[proc_macro_derive(IntoJs, attributes(js))]
+pub fn derive_into_js(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::expand_into_js(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `TryFrom<OwnedJsValue>`, the reverse of [IntoJs](derive.IntoJs.html).
+///
+/// Skipped fields are set to their `Default` value, and missing properties
+/// are read as `undefined`, so that `Option` fields become `None`.
+#[proc_macro_derive(FromJs, attributes(js))]
+pub fn derive_from_js(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::expand_from_js(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Embed the files of a directory into a `VirtualModuleFs`.
+///
+/// The path is relative to the directory of the crate's `Cargo.toml`, and
+/// the directory becomes the root `/` of the filesystem. Files that are not
+/// valid UTF-8 are added as raw bytes, to be imported with a `type`
+/// attribute.
+///
+/// The files are embedded with `include_str!` and `include_bytes!`, changes
+/// to them trigger a rebuild, but added or removed files are only picked up
+/// once the calling crate is rebuilt.
+///
+/// ```ignore
+/// use quickjs_rusty::{include_modules, Context};
+///
+/// let context = Context::builder().build().unwrap();
+/// context.set_virtual_module_fs(include_modules!("scripts"));
+/// context.run_module("/main.js").unwrap();
+/// ```
+#[proc_macro]
+pub fn include_modules(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr);
+    include::expand(dir)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
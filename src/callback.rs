@@ -1,36 +1,44 @@
 use std::ffi::{c_int, c_void};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::task::{Context as TaskContext, Poll};
 use std::{convert::TryFrom, marker::PhantomData, panic::RefUnwindSafe};
 
 use anyhow::Result;
 use libquickjs_ng_sys as q;
 
-use crate::utils::create_string;
+use crate::context::{eval_script, interrupted_error_value, RuntimeState};
 use crate::utils::create_undefined;
 use crate::ExecutionError;
-use crate::OwnedJsValue;
+use crate::JsFunction;
+use crate::JsThrow;
 use crate::ValueError;
+use crate::{OwnedJsObject, OwnedJsPromise, OwnedJsValue, ToOwnedJsValue};
 
 pub trait IntoCallbackResult {
-    fn into_callback_res(self, context: *mut q::JSContext) -> Result<OwnedJsValue, String>;
+    fn into_callback_res(self, context: *mut q::JSContext) -> Result<OwnedJsValue, JsThrow>;
 }
 
 impl<T> IntoCallbackResult for T
 where
     OwnedJsValue: From<(*mut q::JSContext, T)>,
 {
-    fn into_callback_res(self, context: *mut q::JSContext) -> Result<OwnedJsValue, String> {
+    fn into_callback_res(self, context: *mut q::JSContext) -> Result<OwnedJsValue, JsThrow> {
         Ok((context, self).into())
     }
 }
 
-impl<T, E: std::fmt::Display> IntoCallbackResult for Result<T, E>
+impl<T, E: std::fmt::Display + 'static> IntoCallbackResult for Result<T, E>
 where
     OwnedJsValue: From<(*mut q::JSContext, T)>,
 {
-    fn into_callback_res(self, context: *mut q::JSContext) -> Result<OwnedJsValue, String> {
+    fn into_callback_res(self, context: *mut q::JSContext) -> Result<OwnedJsValue, JsThrow> {
         match self {
             Ok(v) => Ok((context, v).into()),
-            Err(e) => Err(e.to_string()),
+            Err(e) => Err(JsThrow::from_error(e)),
         }
     }
 }
@@ -46,66 +54,385 @@ pub trait Callback<F>: RefUnwindSafe {
     /// Should return:
     ///   - Err(_) if the JS values could not be converted
     ///   - Ok(Err(_)) if an error ocurred while processing.
-    ///       The given error will be raised as a JS exception.
+    ///     The given error will be raised as a JS exception.
     ///   - Ok(Ok(result)) when execution succeeded.
-    fn call(
-        &self,
+    fn call(&self, call: CallArgs) -> Result<Result<OwnedJsValue, JsThrow>, ValueError>;
+}
+
+/// A Rust function exposed to Javascript under a fixed name.
+///
+/// Implemented by the types generated by the `#[js_function]` macro of the
+/// `derive` feature, and registered with
+/// [Context::register_function](crate::Context::register_function).
+pub trait JsFunctionDef {
+    /// The name of the function, as seen from Javascript.
+    const NAME: &'static str;
+
+    /// Create the Javascript function.
+    fn create(context: &crate::Context) -> Result<JsFunction, ExecutionError>;
+}
+
+/// The receiver and arguments of a callback invocation, from which the
+/// callback parameters are extracted.
+pub struct CallArgs {
+    context: *mut q::JSContext,
+    this: OwnedJsValue,
+    new_target: Option<OwnedJsValue>,
+    args: std::vec::IntoIter<OwnedJsValue>,
+    index: usize,
+}
+
+impl CallArgs {
+    pub(crate) fn new(
         context: *mut q::JSContext,
+        this: OwnedJsValue,
         args: Vec<OwnedJsValue>,
-    ) -> Result<Result<OwnedJsValue, String>, ValueError>;
+    ) -> Self {
+        Self {
+            context,
+            this,
+            new_target: None,
+            args: args.into_iter(),
+            index: 0,
+        }
+    }
+
+    /// Collect the arguments passed by the C trampoline.
+    pub(crate) fn from_raw(
+        context: *mut q::JSContext,
+        this: q::JSValue,
+        argc: c_int,
+        argv: *mut q::JSValue,
+    ) -> Self {
+        let arg_slice = unsafe { std::slice::from_raw_parts(argv, argc as usize) };
+        let args = arg_slice
+            .iter()
+            .map(|raw| OwnedJsValue::own(context, raw))
+            .collect::<Vec<_>>();
+
+        Self::new(context, OwnedJsValue::own(context, &this), args)
+    }
+
+    /// Set the `new.target` passed by the C trampoline, undefined if the
+    /// function was not called with `new`.
+    pub(crate) fn with_new_target(mut self, new_target: q::JSValue) -> Self {
+        let new_target = OwnedJsValue::own(self.context, &new_target);
+        self.new_target = (!new_target.is_undefined()).then_some(new_target);
+        self
+    }
+
+    /// Get raw pointer to the context the callback is invoked in.
+    pub fn context(&self) -> *mut q::JSContext {
+        self.context
+    }
+
+    /// The `this` value of the call.
+    pub fn this(&self) -> &OwnedJsValue {
+        &self.this
+    }
+
+    /// The number of arguments that were not extracted yet.
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.len() == 0
+    }
+
+    /// The index of the next argument.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Take the next argument.
+    ///
+    /// The index advances even if the argument was not passed, so that it
+    /// keeps matching the parameter position.
+    pub fn next_arg(&mut self) -> Option<OwnedJsValue> {
+        self.index += 1;
+        self.args.next()
+    }
 }
 
-macro_rules! impl_callback {
-    (@call $len:literal $self:ident $args:ident ) => {
-        $self()
-    };
+/// Extracts a callback parameter from the [CallArgs] of an invocation.
+///
+/// `M` is a marker type that keeps apart the implementations for plain values
+/// and for the extractors like [This] or [Ctx].
+pub trait FromCallArg<M>: Sized {
+    /// The number of Javascript arguments consumed by the parameter.
+    const ARGS: usize;
+
+    fn from_call_arg(call: &mut CallArgs) -> Result<Self, ValueError>;
+}
 
-    (@call $len:literal $self:ident $args:ident $( $arg:ident ),* ) => {
-        {
-            let mut iter = $args.into_iter();
-            $self(
-                $(
-                    $arg::try_from(iter.next().unwrap())?,
-                )*
-            )
+#[doc(hidden)]
+pub struct ViaValue;
+#[doc(hidden)]
+pub struct ViaThis;
+#[doc(hidden)]
+pub struct ViaContext;
+#[doc(hidden)]
+pub struct ViaNewTarget;
+#[doc(hidden)]
+pub struct ViaRest;
+
+/// Convert the argument at `index`, naming it in the error.
+fn convert_arg<T>(index: usize, arg: OwnedJsValue) -> Result<T, ValueError>
+where
+    T: TryFrom<OwnedJsValue>,
+    ValueError: From<T::Error>,
+{
+    T::try_from(arg).map_err(|e| ValueError::InvalidArgument {
+        index,
+        error: Box::new(e.into()),
+    })
+}
+
+impl<T> FromCallArg<ViaValue> for T
+where
+    T: TryFrom<OwnedJsValue>,
+    ValueError: From<T::Error>,
+{
+    const ARGS: usize = 1;
+
+    fn from_call_arg(call: &mut CallArgs) -> Result<Self, ValueError> {
+        let index = call.index();
+        match call.next_arg() {
+            Some(arg) => convert_arg(index, arg),
+            // Missing arguments are undefined, which `Option` maps to `None`.
+            None => T::try_from(OwnedJsValue::new(call.context, create_undefined()))
+                .map_err(|_| ValueError::MissingArgument(index)),
         }
-    };
+    }
+}
+
+/// Collects the remaining arguments of a call, converted to `T`.
+///
+/// Must be the last parameter of a callback.
+///
+/// ```rust
+/// use quickjs_rusty::{Context, Rest};
+/// let context = Context::builder().build().unwrap();
+///
+/// context
+///     .add_callback("sum", |first: i32, rest: Rest<i32>| {
+///         first + rest.iter().sum::<i32>()
+///     })
+///     .unwrap();
+///
+/// assert_eq!(context.eval_as::<i32>("sum(1, 2, 3)").unwrap(), 6);
+/// assert_eq!(context.eval_as::<i32>("sum.length").unwrap(), 1);
+/// ```
+pub struct Rest<T>(pub Vec<T>);
 
-    [ $(  $len:literal : ( $( $arg:ident, )* ), )* ] => {
+impl<T> Rest<T> {
+    /// Unpack the arguments into a Vec.
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for Rest<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Rest<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> FromCallArg<ViaRest> for Rest<T>
+where
+    T: TryFrom<OwnedJsValue>,
+    ValueError: From<T::Error>,
+{
+    const ARGS: usize = 0;
+
+    fn from_call_arg(call: &mut CallArgs) -> Result<Self, ValueError> {
+        let mut values = Vec::with_capacity(call.len());
+        while !call.is_empty() {
+            let index = call.index();
+            let arg = call.next_arg().unwrap();
+            values.push(convert_arg(index, arg)?);
+        }
+        Ok(Rest(values))
+    }
+}
+
+/// Extracts the `this` value of a call, converted to `T`.
+///
+/// ```rust
+/// use quickjs_rusty::{Context, OwnedJsObject, This};
+/// let context = Context::builder().build().unwrap();
+///
+/// context
+///     .add_callback("getName", |this: This<OwnedJsObject>| {
+///         this.property_require("name").and_then(|n| Ok(n.to_string()?))
+///     })
+///     .unwrap();
+///
+/// let name = context
+///     .eval_as::<String>("({ name: 'quickjs', getName }).getName()")
+///     .unwrap();
+/// assert_eq!(name, "quickjs");
+/// ```
+pub struct This<T>(pub T);
+
+impl<T> std::ops::Deref for This<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for This<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> FromCallArg<ViaThis> for This<T>
+where
+    T: TryFrom<OwnedJsValue>,
+    ValueError: From<T::Error>,
+{
+    const ARGS: usize = 0;
+
+    fn from_call_arg(call: &mut CallArgs) -> Result<Self, ValueError> {
+        Ok(This(T::try_from(call.this.clone())?))
+    }
+}
+
+/// A handle to the context a callback is invoked in, used to create values
+/// and to call back into Javascript.
+#[derive(Clone, Copy)]
+pub struct Ctx {
+    context: *mut q::JSContext,
+}
+
+impl Ctx {
+    // Get raw pointer to the underlying QuickJS context.
+    pub fn context_raw(&self) -> *mut q::JSContext {
+        self.context
+    }
+
+    /// Get the global object.
+    pub fn global(&self) -> Result<OwnedJsObject, ExecutionError> {
+        let global = unsafe { q::JS_GetGlobalObject(self.context) };
+        Ok(OwnedJsValue::new(self.context, global).try_into_object()?)
+    }
+
+    /// Create a new empty object.
+    pub fn new_object(&self) -> Result<OwnedJsObject, ExecutionError> {
+        let obj = unsafe { q::JS_NewObject(self.context) };
+        Ok(OwnedJsValue::new(self.context, obj).try_into_object()?)
+    }
+
+    /// Convert a Rust value to a Javascript value.
+    pub fn value<T: ToOwnedJsValue>(&self, value: T) -> OwnedJsValue {
+        (self.context, value).into()
+    }
+
+    /// Evaluate Javascript code, without resolving promises.
+    ///
+    /// Like [Context::eval](crate::Context::eval), it fails on a terminated
+    /// runtime and counts against the budget of the running metered
+    /// execution.
+    pub fn eval(&self, code: &str) -> Result<OwnedJsValue, ExecutionError> {
+        let state = unsafe { RuntimeState::from_context(self.context) }
+            .ok_or_else(|| ExecutionError::Internal("Context has no runtime".to_string()))?;
+        state.ensure_not_terminated()?;
+        state
+            .with_meter(self.context, None, || eval_script(self.context, code))
+            .0
+    }
+}
+
+impl FromCallArg<ViaContext> for Ctx {
+    const ARGS: usize = 0;
+
+    fn from_call_arg(call: &mut CallArgs) -> Result<Self, ValueError> {
+        Ok(Ctx {
+            context: call.context,
+        })
+    }
+}
+
+/// The `new.target` of a call, set when a callback registered with
+/// [Context::create_constructor](crate::Context::create_constructor) is
+/// called with `new`.
+pub struct NewTarget(Option<OwnedJsValue>);
+
+impl NewTarget {
+    /// The `new.target` value, `None` if the function was not called with `new`.
+    pub fn value(&self) -> Option<&OwnedJsValue> {
+        self.0.as_ref()
+    }
+
+    /// Returns true if the function was called with `new`.
+    pub fn is_construct_call(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Create the object to be returned by a constructor, with the
+    /// `new.target.prototype` as prototype so that `instanceof` works.
+    ///
+    /// Creates a plain object if the function was not called with `new`.
+    pub fn new_object(&self, ctx: Ctx) -> Result<OwnedJsObject, ExecutionError> {
+        let Some(new_target) = &self.0 else {
+            return ctx.new_object();
+        };
+
+        let proto = new_target
+            .clone()
+            .try_into_object()?
+            .property_require("prototype")?;
+        let obj = unsafe { q::JS_NewObjectProto(ctx.context, proto.value) };
+        Ok(OwnedJsValue::new(ctx.context, obj).try_into_object()?)
+    }
+}
+
+impl FromCallArg<ViaNewTarget> for NewTarget {
+    const ARGS: usize = 0;
+
+    fn from_call_arg(call: &mut CallArgs) -> Result<Self, ValueError> {
+        Ok(NewTarget(call.new_target.clone()))
+    }
+}
+
+macro_rules! impl_callback {
+    [ $(  $len:literal : ( $( $arg:ident : $marker:ident, )* ), )* ] => {
         $(
 
             impl<
-                $( $arg, )*
-                E,
+                $( $arg, $marker, )*
                 R,
                 F,
             > Callback<PhantomData<(
-                $( &$arg, )*
-                &E,
+                $( &$arg, &$marker, )*
                 &R,
                 &F,
             )>> for F
             where
-                $( $arg: TryFrom<OwnedJsValue, Error = E>, )*
-                ValueError: From<E>,
+                $( $arg: FromCallArg<$marker>, )*
                 R: IntoCallbackResult,
                 F: Fn( $( $arg, )*  ) -> R + Sized + RefUnwindSafe,
             {
                 fn argument_count(&self) -> usize {
-                    $len
+                    0 $( + $arg::ARGS )*
                 }
 
-                fn call(&self, context: *mut q::JSContext, args: Vec<OwnedJsValue>)
-                    -> Result<Result<OwnedJsValue, String>, ValueError> {
-                    if args.len() != $len {
-                        return Ok(Err(format!(
-                            "Invalid argument count: Expected {}, got {}",
-                            self.argument_count(),
-                            args.len()
-                        )));
-                    }
-
-                    let res = impl_callback!(@call $len self args $($arg),* );
+                #[allow(unused_mut)]
+                fn call(&self, mut call: CallArgs) -> Result<Result<OwnedJsValue, JsThrow>, ValueError> {
+                    let context = call.context();
+                    let res = self( $( $arg::from_call_arg(&mut call)?, )* );
                     Ok(res.into_callback_res(context))
                 }
             }
@@ -113,38 +440,24 @@ macro_rules! impl_callback {
     };
 }
 
-impl<R, F> Callback<PhantomData<(&R, &F)>> for F
-where
-    R: IntoCallbackResult,
-    F: Fn() -> R + Sized + RefUnwindSafe,
-{
-    fn argument_count(&self) -> usize {
-        0
-    }
-
-    fn call(
-        &self,
-        context: *mut q::JSContext,
-        args: Vec<OwnedJsValue>,
-    ) -> Result<Result<OwnedJsValue, String>, ValueError> {
-        if !args.is_empty() {
-            return Ok(Err(format!(
-                "Invalid argument count: Expected 0, got {}",
-                args.len(),
-            )));
-        }
-
-        let res = self();
-        Ok(res.into_callback_res(context))
-    }
-}
-
 impl_callback![
-    1: (A1,),
-    2: (A1, A2,),
-    3: (A1, A2, A3,),
-    4: (A1, A2, A3, A4,),
-    5: (A1, A2, A3, A4, A5,),
+    0: (),
+    1: (A1: M1,),
+    2: (A1: M1, A2: M2,),
+    3: (A1: M1, A2: M2, A3: M3,),
+    4: (A1: M1, A2: M2, A3: M3, A4: M4,),
+    5: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5,),
+    6: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6,),
+    7: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7,),
+    8: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8,),
+    9: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9,),
+    10: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10,),
+    11: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11,),
+    12: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12,),
+    13: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13,),
+    14: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14,),
+    15: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14, A15: M15,),
+    16: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14, A15: M15, A16: M16,),
 ];
 
 /// A wrapper around Vec<JsValue>, used for vararg callbacks.
@@ -160,24 +473,6 @@ impl Arguments {
     }
 }
 
-impl<F> Callback<PhantomData<(&Arguments, &F)>> for F
-where
-    F: Fn(Arguments) + Sized + RefUnwindSafe,
-{
-    fn argument_count(&self) -> usize {
-        0
-    }
-
-    fn call(
-        &self,
-        context: *mut q::JSContext,
-        args: Vec<OwnedJsValue>,
-    ) -> Result<Result<OwnedJsValue, String>, ValueError> {
-        (self)(Arguments(args));
-        Ok(Ok(OwnedJsValue::new(context, create_undefined())))
-    }
-}
-
 impl<F, R> Callback<PhantomData<(&Arguments, &F, &R)>> for F
 where
     R: IntoCallbackResult,
@@ -187,53 +482,245 @@ where
         0
     }
 
-    fn call(
-        &self,
-        context: *mut q::JSContext,
-        args: Vec<OwnedJsValue>,
-    ) -> Result<Result<OwnedJsValue, String>, ValueError> {
-        let res = (self)(Arguments(args));
+    fn call(&self, call: CallArgs) -> Result<Result<OwnedJsValue, JsThrow>, ValueError> {
+        let context = call.context();
+        let res = (self)(Arguments(call.args.collect()));
         Ok(res.into_callback_res(context))
     }
 }
 
 /// Helper for executing a callback closure.
+///
+/// `new_target` is undefined unless the callback is called with `new`.
 pub fn exec_callback<F>(
     context: *mut q::JSContext,
+    this: q::JSValue,
     argc: c_int,
     argv: *mut q::JSValue,
+    new_target: q::JSValue,
     callback: &impl Callback<F>,
 ) -> Result<q::JSValue, ExecutionError> {
     let result = std::panic::catch_unwind(|| {
-        let arg_slice = unsafe { std::slice::from_raw_parts(argv, argc as usize) };
+        let call = CallArgs::from_raw(context, this, argc, argv).with_new_target(new_target);
 
-        let args = arg_slice
-            .iter()
-            .map(|raw| OwnedJsValue::own(context, raw))
-            .collect::<Vec<_>>();
-
-        match callback.call(context, args) {
+        match callback.call(call) {
             Ok(Ok(result)) => {
                 let serialized = unsafe { result.extract() };
                 Ok(serialized)
             }
-            // TODO: better error reporting.
-            Ok(Err(e)) => Err(ExecutionError::Exception(OwnedJsValue::new(
-                context,
-                create_string(context, &e).unwrap(),
-            ))),
+            Ok(Err(e)) => Err(e.into_exception(context)),
             Err(e) => Err(e.into()),
         }
     });
 
     match result {
         Ok(r) => r,
-        Err(_e) => Err(ExecutionError::Internal("Callback panicked!".to_string())),
+        Err(e) => Err(JsThrow::from_panic(e).into_exception(context)),
+    }
+}
+
+/// Convert a callback error into the JS value that should be thrown.
+///
+/// Errors other than exceptions are turned into JS `Error` objects.
+pub(crate) fn callback_error_value(context: *mut q::JSContext, e: ExecutionError) -> OwnedJsValue {
+    let throw = match e {
+        ExecutionError::Exception(e) => return e.into_value(),
+        ExecutionError::Conversion(e) => JsThrow::type_error(e.to_string()),
+        ExecutionError::Internal(e) => JsThrow::internal_error(e),
+        ExecutionError::OutOfMemory => JsThrow::internal_error("out of memory"),
+        // Keep interrupting the calling code.
+        e @ (ExecutionError::Interrupted
+        | ExecutionError::Timeout
+        | ExecutionError::Terminated
+        | ExecutionError::BudgetExceeded) => return interrupted_error_value(context, &e),
+        other => JsThrow::error(other.to_string()),
+    };
+    throw.into_value(context)
+}
+
+/// Raise the given error as a JS exception and return the exception marker
+/// value, which must be handed back to QuickJS by the calling C function.
+pub(crate) fn throw_callback_error(context: *mut q::JSContext, e: ExecutionError) -> q::JSValue {
+    let js_exception_value = callback_error_value(context, e);
+    unsafe {
+        q::JS_Throw(context, js_exception_value.extract());
+    }
+
+    unsafe { q::JS_Ext_NewSpecialValue(q::JS_TAG_EXCEPTION, 0) }
+}
+
+/// The future returned by an [AsyncCallback], already converted to a JS value.
+pub type AsyncCallbackFuture = Pin<Box<dyn Future<Output = Result<OwnedJsValue, JsThrow>>>>;
+
+/// The AsyncCallback trait is implemented for functions/closures that return
+/// a [Future], and can be used as callbacks in the JS runtime.
+///
+/// Calling such a callback from Javascript returns a Promise, which settles
+/// once the future completes. The futures are driven by
+/// [Context::poll_async](crate::Context::poll_async).
+pub trait AsyncCallback<F>: RefUnwindSafe {
+    /// Returns the number of required Javascript arguments.
+    fn argument_count(&self) -> usize;
+
+    /// Start the callback.
+    ///
+    /// Should return:
+    ///   - Err(_) if the JS values could not be converted
+    ///   - Ok(Err(_)) if the callback could not be started.
+    ///     The returned promise will be rejected with the given error.
+    ///   - Ok(Ok(future)) when the callback was started.
+    fn call(&self, call: CallArgs) -> Result<Result<AsyncCallbackFuture, JsThrow>, ValueError>;
+}
+
+macro_rules! impl_async_callback {
+    [ $(  $len:literal : ( $( $arg:ident : $marker:ident, )* ), )* ] => {
+        $(
+
+            impl<
+                $( $arg, $marker, )*
+                R,
+                Fut,
+                F,
+            > AsyncCallback<PhantomData<(
+                $( &$arg, &$marker, )*
+                &R,
+                &Fut,
+                &F,
+            )>> for F
+            where
+                $( $arg: FromCallArg<$marker>, )*
+                R: IntoCallbackResult,
+                Fut: Future<Output = R> + 'static,
+                F: Fn( $( $arg, )*  ) -> Fut + Sized + RefUnwindSafe,
+            {
+                fn argument_count(&self) -> usize {
+                    0 $( + $arg::ARGS )*
+                }
+
+                #[allow(unused_mut)]
+                fn call(&self, mut call: CallArgs) -> Result<Result<AsyncCallbackFuture, JsThrow>, ValueError> {
+                    let context = call.context();
+                    let future = self( $( $arg::from_call_arg(&mut call)?, )* );
+                    Ok(Ok(Box::pin(async move {
+                        future.await.into_callback_res(context)
+                    })))
+                }
+            }
+        )*
+    };
+}
+
+impl_async_callback![
+    0: (),
+    1: (A1: M1,),
+    2: (A1: M1, A2: M2,),
+    3: (A1: M1, A2: M2, A3: M3,),
+    4: (A1: M1, A2: M2, A3: M3, A4: M4,),
+    5: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5,),
+    6: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6,),
+    7: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7,),
+    8: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8,),
+    9: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9,),
+    10: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10,),
+    11: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11,),
+    12: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12,),
+    13: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13,),
+    14: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14,),
+    15: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14, A15: M15,),
+    16: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14, A15: M15, A16: M16,),
+];
+
+impl<R, Fut, F> AsyncCallback<PhantomData<(&Arguments, &R, &Fut, &F)>> for F
+where
+    R: IntoCallbackResult,
+    Fut: Future<Output = R> + 'static,
+    F: Fn(Arguments) -> Fut + Sized + RefUnwindSafe,
+{
+    fn argument_count(&self) -> usize {
+        0
+    }
+
+    fn call(&self, call: CallArgs) -> Result<Result<AsyncCallbackFuture, JsThrow>, ValueError> {
+        let context = call.context();
+        let future = (self)(Arguments(call.args.collect()));
+        Ok(Ok(Box::pin(async move {
+            future.await.into_callback_res(context)
+        })))
+    }
+}
+
+/// A running async callback, and the functions settling its promise.
+pub(crate) struct AsyncTask {
+    future: AsyncCallbackFuture,
+    resolve: JsFunction,
+    reject: JsFunction,
+}
+
+/// Async callbacks that have been started but have not completed yet.
+///
+/// Shared between the context and the callback closures, which add to it.
+pub(crate) type AsyncTaskQueue = Rc<Mutex<Vec<AsyncTask>>>;
+
+impl AsyncTask {
+    /// Poll the future, and settle the promise once it has completed.
+    pub(crate) fn poll(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), ExecutionError>> {
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| self.future.as_mut().poll(cx)));
+
+        let (settle, value) = match result {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(Ok(value))) => (&self.resolve, value),
+            Ok(Poll::Ready(Err(e))) => (&self.reject, e.into_value(self.reject.context())),
+            Err(e) => {
+                let value = JsThrow::from_panic(e).into_value(self.reject.context());
+                (&self.reject, value)
+            }
+        };
+
+        Poll::Ready(settle.call(vec![value]).map(|_| ()))
     }
 }
 
+/// Helper for starting an async callback closure.
+///
+/// Returns the promise handed to Javascript, and queues the callback's
+/// future in `tasks`.
+pub(crate) fn exec_async_callback<F>(
+    context: *mut q::JSContext,
+    this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+    callback: &impl AsyncCallback<F>,
+    tasks: &AsyncTaskQueue,
+) -> Result<q::JSValue, ExecutionError> {
+    let (promise, resolve, reject) = OwnedJsPromise::with_resolvers_raw(context)?;
+
+    let result = std::panic::catch_unwind(|| {
+        let call = CallArgs::from_raw(context, this, argc, argv);
+        callback.call(call)
+    });
+
+    let error = match result {
+        Ok(Ok(Ok(future))) => {
+            tasks.lock().unwrap().push(AsyncTask {
+                future,
+                resolve,
+                reject,
+            });
+            return Ok(unsafe { promise.into_value().extract() });
+        }
+        Ok(Ok(Err(e))) => e.into_value(context),
+        Ok(Err(e)) => callback_error_value(context, e.into()),
+        Err(e) => JsThrow::from_panic(e).into_value(context),
+    };
+
+    reject.call(vec![error])?;
+    Ok(unsafe { promise.into_value().extract() })
+}
+
 pub type CustomCallback = fn(*mut q::JSContext, &[q::JSValue]) -> Result<Option<q::JSValue>>;
-pub type WrappedCallback = dyn Fn(c_int, *mut q::JSValue) -> q::JSValue;
+/// A type-erased callback closure, called with `(this, argc, argv, new_target)`,
+/// `new_target` being undefined unless the function is called with `new`.
+pub type WrappedCallback = dyn Fn(q::JSValue, c_int, *mut q::JSValue, q::JSValue) -> q::JSValue;
 
 /// Taken from: https://s3.amazonaws.com/temp.michaelfbryan.com/callbacks/index.html
 ///
@@ -242,26 +729,37 @@ pub type WrappedCallback = dyn Fn(c_int, *mut q::JSValue) -> q::JSValue;
 ///
 /// Both the boxed closure and the boxed data are returned and must be stored
 /// by the caller to guarantee they stay alive.
+///
+/// The trampoline tells constructor calls apart when the function is created
+/// with `JS_Ext_NewCFunctionDataNewTarget`, which passes the constructor flag
+/// as `magic`. Functions created with `JS_NewCFunctionData` and a zero magic
+/// are never called as constructors.
 pub unsafe fn build_closure_trampoline<F>(
     closure: F,
 ) -> ((Box<WrappedCallback>, Box<q::JSValue>), q::JSCFunctionData)
 where
-    F: Fn(c_int, *mut q::JSValue) -> q::JSValue + 'static,
+    F: Fn(q::JSValue, c_int, *mut q::JSValue, q::JSValue) -> q::JSValue + 'static,
 {
     unsafe extern "C" fn trampoline<F>(
         _ctx: *mut q::JSContext,
-        _this: q::JSValue,
+        this: q::JSValue,
         argc: c_int,
         argv: *mut q::JSValue,
-        _magic: c_int,
+        is_constructor_call: c_int,
         data: *mut q::JSValue,
     ) -> q::JSValue
     where
-        F: Fn(c_int, *mut q::JSValue) -> q::JSValue,
+        F: Fn(q::JSValue, c_int, *mut q::JSValue, q::JSValue) -> q::JSValue,
     {
         let closure_ptr = q::JS_Ext_GetPtr(*data);
         let closure: &mut F = &mut *(closure_ptr as *mut F);
-        (*closure)(argc, argv)
+        // Constructors get new.target as `this`.
+        let new_target = if is_constructor_call != 0 {
+            this
+        } else {
+            create_undefined()
+        };
+        (*closure)(this, argc, argv, new_target)
     }
 
     let boxed_f = Box::new(closure);
@@ -0,0 +1,634 @@
+//! Expose Rust types to Javascript as classes.
+
+use std::any::TypeId;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void, CString};
+use std::marker::PhantomData;
+use std::panic::{AssertUnwindSafe, RefUnwindSafe};
+use std::sync::Mutex;
+
+use libquickjs_ng_sys as q;
+
+use crate::callback::{CallArgs, FromCallArg, IntoCallbackResult};
+use crate::context::RuntimeState;
+use crate::value::{OwnedJsObject, OwnedJsValue, ToOwnedJsValue};
+use crate::{ExecutionError, JsThrow, ValueError};
+
+/// A Rust type that can be exposed to Javascript as a class.
+///
+/// Register it with [Context::register_class](crate::Context::register_class).
+/// Every object created from Javascript with `new` owns a `Self` value, which
+/// is dropped once the object is garbage collected.
+///
+/// ```rust
+/// use quickjs_rusty::{ClassBuilder, Context, JsClass};
+///
+/// struct Counter {
+///     count: i32,
+/// }
+///
+/// impl JsClass for Counter {
+///     const NAME: &'static str = "Counter";
+///
+///     fn define(class: &mut ClassBuilder<Self>) {
+///         class
+///             .constructor(|start: i32| Counter { count: start })
+///             .method("increment", |this: &mut Counter| {
+///                 this.count += 1;
+///                 this.count
+///             })
+///             .getter("count", |this: &Counter| this.count);
+///     }
+/// }
+///
+/// let context = Context::builder().build().unwrap();
+/// context.register_class::<Counter>().unwrap();
+///
+/// let value = context
+///     .eval_as::<i32>("const c = new Counter(10); c.increment(); c.count")
+///     .unwrap();
+/// assert_eq!(value, 11);
+/// ```
+pub trait JsClass: Sized + 'static {
+    /// The name of the class, as seen from Javascript.
+    const NAME: &'static str;
+
+    /// Describe the constructor, methods and accessors of the class.
+    ///
+    /// A class without a constructor can not be instantiated from
+    /// Javascript, only from Rust via
+    /// [Context::create_class_instance](crate::Context::create_class_instance).
+    fn define(class: &mut ClassBuilder<Self>);
+}
+
+/// The members of a [JsClass], implemented by the `#[js_methods]` macro of
+/// the `derive` feature.
+#[doc(hidden)]
+pub trait JsClassMembers: JsClass {
+    fn define_members(class: &mut ClassBuilder<Self>);
+}
+
+pub(crate) type ClassFunction<T> = Box<dyn Fn(&RefCell<T>, CallArgs) -> CallResult>;
+pub(crate) type ClassConstructor<T> =
+    Box<dyn Fn(CallArgs) -> Result<Result<T, JsThrow>, ValueError>>;
+
+type CallResult = Result<Result<OwnedJsValue, JsThrow>, ValueError>;
+type ClassAccessor<T> = (String, Option<ClassFunction<T>>, Option<ClassFunction<T>>);
+
+/// Collects the members of a [JsClass].
+pub struct ClassBuilder<T: JsClass> {
+    pub(crate) constructor: Option<(usize, ClassConstructor<T>)>,
+    pub(crate) methods: Vec<(String, usize, ClassFunction<T>)>,
+    pub(crate) accessors: Vec<ClassAccessor<T>>,
+}
+
+impl<T: JsClass> ClassBuilder<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            constructor: None,
+            methods: Vec::new(),
+            accessors: Vec::new(),
+        }
+    }
+
+    /// Set the function invoked by `new`.
+    ///
+    /// It takes 0 - 16 arguments following the same rules as
+    /// [Context::add_callback](crate::Context::add_callback) and returns either
+    /// `T` or a `Result<T, E>`, where an error is raised as a Javascript exception.
+    pub fn constructor<F>(&mut self, constructor: impl Constructor<T, F> + 'static) -> &mut Self {
+        let argument_count = constructor.argument_count();
+        self.constructor = Some((
+            argument_count,
+            Box::new(move |call| constructor.construct(call)),
+        ));
+        self
+    }
+
+    /// Add a method to the class prototype.
+    ///
+    /// The first argument of the method is either `&T` or `&mut T`, the
+    /// remaining 0 - 16 arguments follow the same rules as
+    /// [Context::add_callback](crate::Context::add_callback).
+    pub fn method<F>(&mut self, name: &str, method: impl Method<T, F> + 'static) -> &mut Self {
+        let argument_count = method.argument_count();
+        self.methods.push((
+            name.to_string(),
+            argument_count,
+            Box::new(move |this, call| method.call(this, call)),
+        ));
+        self
+    }
+
+    /// Add a getter for the property `name`.
+    pub fn getter<F, R>(&mut self, name: &str, getter: F) -> &mut Self
+    where
+        F: Fn(&T) -> R + RefUnwindSafe + 'static,
+        R: IntoCallbackResult,
+    {
+        let getter: ClassFunction<T> =
+            Box::new(move |this, call| Method::<T, _>::call(&getter, this, call));
+        match self.accessors.iter_mut().find(|(n, _, _)| n == name) {
+            Some(accessor) => accessor.1 = Some(getter),
+            None => self.accessors.push((name.to_string(), Some(getter), None)),
+        }
+        self
+    }
+
+    /// Add a setter for the property `name`.
+    pub fn setter<F, V, M>(&mut self, name: &str, setter: F) -> &mut Self
+    where
+        F: Fn(&mut T, V) + RefUnwindSafe + 'static,
+        V: FromCallArg<M>,
+    {
+        let setter: ClassFunction<T> =
+            Box::new(move |this, call| Method::<T, _>::call(&setter, this, call));
+        match self.accessors.iter_mut().find(|(n, _, _)| n == name) {
+            Some(accessor) => accessor.2 = Some(setter),
+            None => self.accessors.push((name.to_string(), None, Some(setter))),
+        }
+        self
+    }
+}
+
+/// Implemented for closures that can be used as a class constructor.
+pub trait Constructor<T, F>: RefUnwindSafe {
+    /// Returns the number of required Javascript arguments.
+    fn argument_count(&self) -> usize;
+
+    /// Build the Rust value backing a new instance.
+    fn construct(&self, call: CallArgs) -> Result<Result<T, JsThrow>, ValueError>;
+}
+
+/// Implemented for closures that can be used as a class method.
+pub trait Method<T, F>: RefUnwindSafe {
+    /// Returns the number of required Javascript arguments.
+    fn argument_count(&self) -> usize;
+
+    /// Execute the method on the given instance.
+    fn call(&self, this: &RefCell<T>, call: CallArgs) -> CallResult;
+}
+
+/// Conversion of a constructor return value into the class instance.
+pub trait IntoClassInstance<T> {
+    fn into_instance(self) -> Result<T, JsThrow>;
+}
+
+impl<T: JsClass> IntoClassInstance<T> for T {
+    fn into_instance(self) -> Result<T, JsThrow> {
+        Ok(self)
+    }
+}
+
+impl<T: JsClass, E: std::fmt::Display + 'static> IntoClassInstance<T> for Result<T, E> {
+    fn into_instance(self) -> Result<T, JsThrow> {
+        self.map_err(JsThrow::from_error)
+    }
+}
+
+fn borrow_error(name: &str) -> JsThrow {
+    JsThrow::error(format!("{} instance is already borrowed", name))
+}
+
+macro_rules! impl_class_members {
+    [ $( $len:literal : ( $( $arg:ident : $marker:ident, )* ), )* ] => {
+        $(
+            impl<
+                T,
+                $( $arg, $marker, )*
+                R,
+                F,
+            > Constructor<T, PhantomData<(
+                $( &$arg, &$marker, )*
+                &R,
+                &F,
+            )>> for F
+            where
+                $( $arg: FromCallArg<$marker>, )*
+                R: IntoClassInstance<T>,
+                F: Fn( $( $arg, )* ) -> R + Sized + RefUnwindSafe,
+            {
+                fn argument_count(&self) -> usize {
+                    0 $( + $arg::ARGS )*
+                }
+
+                #[allow(unused_mut, unused_variables)]
+                fn construct(&self, mut call: CallArgs) -> Result<Result<T, JsThrow>, ValueError> {
+                    let res = self( $( $arg::from_call_arg(&mut call)?, )* );
+                    Ok(res.into_instance())
+                }
+            }
+
+            impl<
+                T: JsClass,
+                $( $arg, $marker, )*
+                R,
+                F,
+            > Method<T, PhantomData<(
+                fn(&T),
+                $( &$arg, &$marker, )*
+                &R,
+                &F,
+            )>> for F
+            where
+                $( $arg: FromCallArg<$marker>, )*
+                R: IntoCallbackResult,
+                F: Fn( &T, $( $arg, )* ) -> R + Sized + RefUnwindSafe,
+            {
+                fn argument_count(&self) -> usize {
+                    0 $( + $arg::ARGS )*
+                }
+
+                #[allow(unused_mut)]
+                fn call(&self, this: &RefCell<T>, mut call: CallArgs) -> CallResult {
+                    let Ok(this) = this.try_borrow() else {
+                        return Ok(Err(borrow_error(T::NAME)));
+                    };
+                    let context = call.context();
+                    let res = self(&this, $( $arg::from_call_arg(&mut call)?, )* );
+                    Ok(res.into_callback_res(context))
+                }
+            }
+
+            impl<
+                T: JsClass,
+                $( $arg, $marker, )*
+                R,
+                F,
+            > Method<T, PhantomData<(
+                fn(&mut T),
+                $( &$arg, &$marker, )*
+                &R,
+                &F,
+            )>> for F
+            where
+                $( $arg: FromCallArg<$marker>, )*
+                R: IntoCallbackResult,
+                F: Fn( &mut T, $( $arg, )* ) -> R + Sized + RefUnwindSafe,
+            {
+                fn argument_count(&self) -> usize {
+                    0 $( + $arg::ARGS )*
+                }
+
+                #[allow(unused_mut)]
+                fn call(&self, this: &RefCell<T>, mut call: CallArgs) -> CallResult {
+                    let Ok(mut this) = this.try_borrow_mut() else {
+                        return Ok(Err(borrow_error(T::NAME)));
+                    };
+                    let context = call.context();
+                    let res = self(&mut this, $( $arg::from_call_arg(&mut call)?, )* );
+                    Ok(res.into_callback_res(context))
+                }
+            }
+        )*
+    };
+}
+
+impl_class_members![
+    0: (),
+    1: (A1: M1,),
+    2: (A1: M1, A2: M2,),
+    3: (A1: M1, A2: M2, A3: M3,),
+    4: (A1: M1, A2: M2, A3: M3, A4: M4,),
+    5: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5,),
+    6: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6,),
+    7: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7,),
+    8: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8,),
+    9: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9,),
+    10: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10,),
+    11: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11,),
+    12: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12,),
+    13: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13,),
+    14: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14,),
+    15: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14, A15: M15,),
+    16: (A1: M1, A2: M2, A3: M3, A4: M4, A5: M5, A6: M6, A7: M7, A8: M8, A9: M9, A10: M10, A11: M11, A12: M12, A13: M13, A14: M14, A15: M15, A16: M16,),
+];
+
+/// Class ids registered in a runtime, keyed by the Rust type they wrap.
+///
+/// The registry is part of the runtime opaque, so that class instances can be
+/// recognized from a raw `JSContext`.
+#[derive(Default)]
+pub(crate) struct ClassRegistry {
+    ids: Mutex<HashMap<TypeId, q::JSClassID>>,
+    /// The class of the functions created by [ClassRegistry::new_function],
+    /// registered on first use.
+    function_class: Mutex<q::JSClassID>,
+}
+
+impl ClassRegistry {
+    fn from_context<'a>(context: *mut q::JSContext) -> Option<&'a ClassRegistry> {
+        let state = unsafe { RuntimeState::from_context(context) };
+        state.map(|state| &state.classes)
+    }
+
+    /// Create a function calling `func` with `data`, like `JS_NewCFunctionData`
+    /// does, but telling constructor calls apart, see
+    /// [build_closure_trampoline](crate::build_closure_trampoline).
+    pub(crate) fn new_function(
+        &self,
+        context: *mut q::JSContext,
+        func: q::JSCFunctionData,
+        length: c_int,
+        data: *mut q::JSValue,
+    ) -> q::JSValue {
+        let mut class_id = self.function_class.lock().unwrap();
+        unsafe {
+            q::JS_Ext_NewCFunctionDataNewTarget(context, &mut *class_id, func, length, 1, data)
+        }
+    }
+
+    pub(crate) fn class_id<T: JsClass>(&self) -> Option<q::JSClassID> {
+        self.ids.lock().unwrap().get(&TypeId::of::<T>()).copied()
+    }
+
+    /// Return the class id of `T`, registering the class in the runtime if needed.
+    pub(crate) fn register<T: JsClass>(
+        &self,
+        runtime: *mut q::JSRuntime,
+    ) -> Result<q::JSClassID, ValueError> {
+        let mut ids = self.ids.lock().unwrap();
+        if let Some(id) = ids.get(&TypeId::of::<T>()) {
+            return Ok(*id);
+        }
+
+        // The runtime copies the class name into an atom.
+        let class_name = CString::new(T::NAME).map_err(ValueError::StringWithZeroBytes)?;
+        let mut class_id: q::JSClassID = 0;
+        let ret = unsafe {
+            q::JS_NewClassID(runtime, &mut class_id);
+            let def = q::JSClassDef {
+                class_name: class_name.as_ptr(),
+                finalizer: Some(finalize_instance::<T>),
+                gc_mark: None,
+                call: None,
+                exotic: std::ptr::null_mut(),
+            };
+            q::JS_NewClass(runtime, class_id, &def)
+        };
+        if ret < 0 {
+            return Err(ValueError::Internal(format!(
+                "Could not register class {}",
+                T::NAME
+            )));
+        }
+
+        ids.insert(TypeId::of::<T>(), class_id);
+        Ok(class_id)
+    }
+}
+
+unsafe extern "C" fn finalize_instance<T: JsClass>(_rt: *mut q::JSRuntime, value: q::JSValue) {
+    let ptr = q::JS_GetOpaque(value, q::JS_GetClassID(value));
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr as *mut RefCell<T>));
+    }
+}
+
+/// Wrap `value` into a new object of class `class_id`, using `proto` as prototype.
+pub(crate) fn new_instance<T: JsClass>(
+    context: *mut q::JSContext,
+    proto: q::JSValue,
+    class_id: q::JSClassID,
+    value: T,
+) -> Result<OwnedJsValue, ValueError> {
+    let obj = unsafe { q::JS_NewObjectProtoClass(context, proto, class_id) };
+    let obj = OwnedJsValue::new(context, obj);
+    if obj.is_exception() {
+        return Err(ValueError::Internal(format!(
+            "Could not create {} instance",
+            T::NAME
+        )));
+    }
+
+    let ptr = Box::into_raw(Box::new(RefCell::new(value)));
+    unsafe { q::JS_SetOpaque(obj.value, ptr as *mut c_void) };
+    Ok(obj)
+}
+
+/// Get the `RefCell<T>` backing a class instance, if `value` is one.
+pub(crate) fn instance_cell<'a, T: JsClass>(
+    context: *mut q::JSContext,
+    value: q::JSValue,
+) -> Option<&'a RefCell<T>> {
+    let class_id = ClassRegistry::from_context(context)?.class_id::<T>()?;
+    let ptr = unsafe { q::JS_GetOpaque(value, class_id) };
+    if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe { &*(ptr as *const RefCell<T>) })
+    }
+}
+
+/// Run a class member on `this` and convert its outcome to a JS result.
+pub(crate) fn call_member<T: JsClass>(
+    context: *mut q::JSContext,
+    this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+    member: &ClassFunction<T>,
+) -> Result<q::JSValue, ExecutionError> {
+    let cell = instance_cell::<T>(context, this).ok_or_else(|| {
+        JsThrow::type_error(format!("Receiver is not an instance of {}", T::NAME))
+            .into_exception(context)
+    })?;
+
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        member(cell, CallArgs::from_raw(context, this, argc, argv))
+    }));
+
+    match result {
+        Ok(Ok(Ok(value))) => Ok(unsafe { value.extract() }),
+        Ok(Ok(Err(e))) => Err(e.into_exception(context)),
+        Ok(Err(e)) => Err(e.into()),
+        Err(e) => Err(JsThrow::from_panic(e).into_exception(context)),
+    }
+}
+
+/// Run the class constructor for a `new` call, where `new_target` is the one
+/// received by the constructor function, undefined without `new`.
+pub(crate) fn call_constructor<T: JsClass>(
+    context: *mut q::JSContext,
+    class_id: q::JSClassID,
+    new_target: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+    constructor: Option<&ClassConstructor<T>>,
+) -> Result<q::JSValue, ExecutionError> {
+    use crate::utils::{get_exception, make_cstring};
+    let Some(constructor) = constructor else {
+        return Err(
+            JsThrow::type_error(format!("{} is not a constructor", T::NAME))
+                .into_exception(context),
+        );
+    };
+    if unsafe { q::JS_Ext_IsUndefined(new_target) } {
+        return Err(JsThrow::type_error(format!(
+            "Class constructor {} cannot be invoked without 'new'",
+            T::NAME
+        ))
+        .into_exception(context));
+    }
+
+    // Read the prototype from new.target so that subclasses work.
+    let prototype = make_cstring("prototype")?;
+    let proto = OwnedJsValue::new(context, unsafe {
+        q::JS_GetPropertyStr(context, new_target, prototype.as_ptr())
+    });
+    if proto.is_exception() {
+        return Err(get_exception(context).unwrap_or_else(|| {
+            ExecutionError::Internal(format!("Could not get the prototype of {}", T::NAME))
+        }));
+    }
+
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        constructor(CallArgs::from_raw(context, new_target, argc, argv).with_new_target(new_target))
+    }));
+
+    match result {
+        Ok(Ok(Ok(value))) => {
+            let instance = new_instance(context, proto.value, class_id, value)?;
+            Ok(unsafe { instance.extract() })
+        }
+        Ok(Ok(Err(e))) => Err(e.into_exception(context)),
+        Ok(Err(e)) => Err(e.into()),
+        Err(e) => Err(JsThrow::from_panic(e).into_exception(context)),
+    }
+}
+
+/// Define a non-enumerable property, as done for class members.
+pub(crate) fn define_member(
+    context: *mut q::JSContext,
+    object: &OwnedJsObject,
+    name: &str,
+    getter: Option<OwnedJsValue>,
+    setter: Option<OwnedJsValue>,
+    value: Option<OwnedJsValue>,
+) -> Result<(), ExecutionError> {
+    use crate::utils::{create_undefined, make_cstring};
+
+    let name_c = make_cstring(name)?;
+    let ret = unsafe {
+        let atom = q::JS_NewAtom(context, name_c.as_ptr());
+        let extract = |v: Option<OwnedJsValue>| match v {
+            Some(v) => v.extract(),
+            None => create_undefined(),
+        };
+        // Both functions take ownership of the given values.
+        let ret = if let Some(value) = value {
+            q::JS_DefinePropertyValue(
+                context,
+                object.value,
+                atom,
+                value.extract(),
+                (q::JS_PROP_CONFIGURABLE | q::JS_PROP_WRITABLE) as i32,
+            )
+        } else {
+            q::JS_DefinePropertyGetSet(
+                context,
+                object.value,
+                atom,
+                extract(getter),
+                extract(setter),
+                q::JS_PROP_CONFIGURABLE as i32,
+            )
+        };
+        q::JS_FreeAtom(context, atom);
+        ret
+    };
+
+    if ret < 0 {
+        Err(ExecutionError::Internal(format!(
+            "Could not define property '{}'",
+            name
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// A Javascript object that wraps a Rust value of a registered [JsClass].
+///
+/// Holding a `JsClassInstance` keeps the object, and therefore the Rust value,
+/// alive.
+pub struct JsClassInstance<T: JsClass> {
+    value: OwnedJsObject,
+    _marker: PhantomData<T>,
+}
+
+impl<T: JsClass> JsClassInstance<T> {
+    pub fn try_from_value(value: OwnedJsValue) -> Result<Self, ValueError> {
+        if instance_cell::<T>(value.context(), value.value).is_none() {
+            return Err(ValueError::Internal(format!(
+                "Expected an instance of {}",
+                T::NAME
+            )));
+        }
+
+        Ok(Self {
+            value: value.try_into_object()?,
+            _marker: PhantomData,
+        })
+    }
+
+    fn cell(&self) -> &RefCell<T> {
+        // checked on construction, and kept alive by `self.value`
+        instance_cell::<T>(self.value.context(), self.value.value).unwrap()
+    }
+
+    /// Immutably borrow the wrapped Rust value.
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.cell().borrow()
+    }
+
+    /// Mutably borrow the wrapped Rust value.
+    ///
+    /// Panics if the value is currently borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.cell().borrow_mut()
+    }
+
+    pub fn into_value(self) -> OwnedJsValue {
+        self.value.into_value()
+    }
+}
+
+impl<T: JsClass> Clone for JsClassInstance<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: JsClass> std::fmt::Debug for JsClassInstance<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "JsClassInstance<{}>", T::NAME)
+    }
+}
+
+impl<T: JsClass> std::ops::Deref for JsClassInstance<T> {
+    type Target = OwnedJsValue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T: JsClass> TryFrom<OwnedJsValue> for JsClassInstance<T> {
+    type Error = ValueError;
+
+    fn try_from(value: OwnedJsValue) -> Result<Self, Self::Error> {
+        JsClassInstance::try_from_value(value)
+    }
+}
+
+impl<T: JsClass> ToOwnedJsValue for JsClassInstance<T> {
+    fn to_owned(self, _: *mut q::JSContext) -> OwnedJsValue {
+        self.into_value()
+    }
+}
@@ -5,7 +5,7 @@ use std::os::raw::c_void;
 use libquickjs_ng_sys as q;
 
 use crate::utils::{ensure_no_excpetion, get_exception, make_cstring};
-use crate::value::{JsCompiledFunction, OwnedJsValue};
+use crate::value::{JsCompiledFunction, JsModule, OwnedJsValue};
 use crate::ExecutionError;
 
 /// compile a script, will result in a JSValueRef with tag JS_TAG_FUNCTION_BYTECODE or JS_TAG_MODULE.
@@ -84,12 +84,24 @@ pub fn run_compiled_function(func: &JsCompiledFunction) -> Result<OwnedJsValue,
 
 /// write a function to bytecode
 pub fn to_bytecode(context: *mut q::JSContext, compiled_func: &JsCompiledFunction) -> Vec<u8> {
+    write_bytecode(context, compiled_func.as_value())
+}
+
+/// write a module compiled with compile_module to bytecode
+///
+/// The bytecode can be returned by a module loader as
+/// [ModuleSource::Bytecode](crate::module_loader::ModuleSource::Bytecode).
+pub fn module_to_bytecode(context: *mut q::JSContext, module: &JsModule) -> Vec<u8> {
+    write_bytecode(context, module)
+}
+
+fn write_bytecode(context: *mut q::JSContext, value: &OwnedJsValue) -> Vec<u8> {
     unsafe {
         let mut len = 0;
         let raw = q::JS_WriteObject(
             context,
             &mut len,
-            *compiled_func.as_value().as_inner(),
+            *value.as_inner(),
             q::JS_WRITE_OBJ_BYTECODE as i32,
         );
         let slice = std::slice::from_raw_parts(raw, len);
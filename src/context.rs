@@ -1,5 +1,15 @@
 mod builder;
 mod context;
+mod interrupt;
+mod intrinsics;
+mod runtime;
 
 pub use builder::ContextBuilder;
-pub use context::Context;
+pub use context::{Context, ResolveLimits};
+pub use interrupt::TerminationHandle;
+pub use intrinsics::Intrinsics;
+pub use runtime::Runtime;
+
+pub(crate) use context::eval_script;
+pub(crate) use interrupt::{interrupt_error, interrupted_error_value};
+pub(crate) use runtime::RuntimeState;
@@ -1,4 +1,4 @@
-use super::Context;
+use super::{Context, Intrinsics, ResolveLimits, Runtime};
 use crate::{console, ContextError};
 
 /// A builder for [Context](Context).
@@ -6,20 +6,53 @@ use crate::{console, ContextError};
 /// Create with [Context::builder](Context::builder).
 #[derive(Default)]
 pub struct ContextBuilder {
+    runtime: Option<Runtime>,
+    intrinsics: Intrinsics,
     memory_limit: Option<usize>,
     console_backend: Option<Box<dyn console::ConsoleBackend>>,
+    resolve_limits: ResolveLimits,
 }
 
 impl ContextBuilder {
     pub fn new() -> Self {
         Self {
+            runtime: None,
+            intrinsics: Intrinsics::default(),
             memory_limit: None,
             console_backend: None,
+            resolve_limits: ResolveLimits::default(),
         }
     }
 
+    /// Create the context in an existing runtime, shared with other contexts,
+    /// instead of a new one.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::{Context, Runtime};
+    /// let runtime = Runtime::new().unwrap();
+    ///
+    /// let context = Context::builder().runtime(runtime.clone()).build().unwrap();
+    /// assert!(context.runtime().ptr_eq(&runtime));
+    /// ```
+    pub fn runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Choose the built-in objects of the context, see [Intrinsics].
+    ///
+    /// Leaving out intrinsics reduces the attack surface of sandboxed scripts,
+    /// and the cost of creating the context.
+    pub fn intrinsics(mut self, intrinsics: Intrinsics) -> Self {
+        self.intrinsics = intrinsics;
+        self
+    }
+
     /// Sets the memory limit of the Javascript runtime (in bytes).
     ///
+    /// With a shared [runtime](Self::runtime), the limit applies to all of
+    /// its contexts.
+    ///
     /// If the limit is exceeded, methods like `eval` will return
     /// a `Err(ExecutionError::Exception(JsValue::Null))`
     // TODO: investigate why we don't get a proper exception message here.
@@ -43,9 +76,25 @@ impl ContextBuilder {
         self
     }
 
+    /// Sets the limits on the event loop run when resolving promises.
+    ///
+    /// See [Context::set_resolve_limits].
+    pub fn resolve_limits(mut self, limits: ResolveLimits) -> Self {
+        self.resolve_limits = limits;
+        self
+    }
+
     /// Finalize the builder and build a JS Context.
     pub fn build(self) -> Result<Context, ContextError> {
-        let context = Context::new(self.memory_limit)?;
+        let runtime = match self.runtime {
+            Some(runtime) => runtime,
+            None => Runtime::new()?,
+        };
+        if let Some(limit) = self.memory_limit {
+            runtime.set_memory_limit(limit);
+        }
+        let context = Context::from_runtime(runtime, self.intrinsics)?;
+        context.set_resolve_limits(self.resolve_limits);
         if let Some(be) = self.console_backend {
             context.set_console(be).map_err(ContextError::Execution)?;
         }
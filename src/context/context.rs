@@ -3,46 +3,86 @@
 use std::{
     convert::TryFrom,
     ffi::{c_char, c_int, c_void},
-    sync::Mutex,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Poll, Wake, Waker},
+    time::{Duration, Instant},
 };
 
-use libquickjs_ng_sys::{self as q, JSContext};
+use libquickjs_ng_sys as q;
 
 use crate::callback::*;
+use crate::class::*;
 use crate::console::ConsoleBackend;
 use crate::errors::*;
 use crate::module_loader::*;
-use crate::utils::{create_string, ensure_no_excpetion, get_exception, make_cstring};
+use crate::native_module::*;
+use crate::utils::{ensure_no_excpetion, get_exception, make_cstring};
 use crate::value::*;
 
-use super::ContextBuilder;
+use super::{ContextBuilder, Intrinsics, Runtime, TerminationHandle};
 
 /// Context is a wrapper around a QuickJS Javascript context.
 /// It is the primary way to interact with the runtime.
 ///
-/// For each `Context` instance a new instance of QuickJS
-/// runtime is created. It means that it is safe to use
-/// different contexts in different threads, but each
-/// `Context` instance must be used only from a single thread.
+/// A `Context` created with [Context::new] or [Context::builder] gets its own
+/// QuickJS runtime. It means that it is safe to use different contexts in
+/// different threads, but each `Context` instance must be used only from a
+/// single thread.
+///
+/// Several contexts can share a [Runtime], see [Runtime::context]. The
+/// context keeps the runtime alive.
 pub struct Context {
-    runtime: *mut q::JSRuntime,
+    runtime: Runtime,
     pub(crate) context: *mut q::JSContext,
+    /// The intrinsics the context was created with, kept for [Context::reset].
+    intrinsics: Intrinsics,
     /// Stores callback closures and quickjs data pointers.
     /// This array is write-only and only exists to ensure the lifetime of
     /// the closure.
     // A Mutex is used over a RefCell because it needs to be unwind-safe.
     callbacks: Mutex<Vec<(Box<WrappedCallback>, Box<q::JSValue>)>>,
-    module_loader: Mutex<Option<Box<ModuleLoader>>>,
+    /// Futures of async callbacks that have not completed yet.
+    tasks: AsyncTaskQueue,
+    /// Native modules, also stored as the context opaque.
+    native_modules: Box<NativeModuleRegistry>,
+    /// Invalidated modules, freed with the context.
+    module_cache: ModuleCache,
+    resolve_limits: Mutex<ResolveLimits>,
+    /// The budget of each metered execution, see [Context::set_budget].
+    budget: Mutex<Option<u64>>,
+    /// The units consumed by the last metered execution.
+    consumed_units: Mutex<u64>,
+}
+
+/// Limits on the event loop run while resolving a promise.
+///
+/// Without limits, resolving a promise that never settles while jobs keep
+/// being queued would never return.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResolveLimits {
+    /// The maximum number of pending jobs to execute, failing with
+    /// [ExecutionError::JobLimitExceeded] once reached.
+    pub max_jobs: Option<usize>,
+    /// The maximum time to spend running the event loop, failing with
+    /// [ExecutionError::Timeout] once elapsed.
+    pub timeout: Option<Duration>,
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
+        // Pending tasks, module exports and the CommonJS state hold values
+        // that must be freed before the context.
+        self.tasks.lock().unwrap().clear();
+        self.native_modules.clear();
+        self.module_cache.relink(self.context);
+
+        // The runtime is freed with its last handle.
         unsafe {
             q::JS_FreeContext(self.context);
-            q::JS_FreeRuntime(self.runtime);
-
-            // Drop the module loader.
-            let _ = self.module_loader.lock().unwrap().take();
         }
     }
 }
@@ -64,38 +104,50 @@ impl Context {
 
     /// Initialize a wrapper by creating a JSRuntime and JSContext.
     pub fn new(memory_limit: Option<usize>) -> Result<Self, ContextError> {
-        let runtime = unsafe { q::JS_NewRuntime() };
-        if runtime.is_null() {
-            return Err(ContextError::RuntimeCreationFailed);
-        }
+        let runtime = Runtime::new()?;
 
         // Configure memory limit if specified.
         if let Some(limit) = memory_limit {
-            unsafe {
-                q::JS_SetMemoryLimit(runtime, limit as _);
-            }
+            runtime.set_memory_limit(limit);
         }
 
-        let context = unsafe { q::JS_NewContext(runtime) };
+        Self::from_runtime(runtime, Intrinsics::default())
+    }
+
+    /// Create a JSContext with the given intrinsics in the given runtime.
+    pub(crate) fn from_runtime(
+        runtime: Runtime,
+        intrinsics: Intrinsics,
+    ) -> Result<Self, ContextError> {
+        let context = unsafe { intrinsics.new_context(runtime.runtime_raw()) };
         if context.is_null() {
-            unsafe {
-                q::JS_FreeRuntime(runtime);
-            }
             return Err(ContextError::ContextCreationFailed);
         }
 
-        // Initialize the promise resolver helper code.
-        // This code is needed by Self::resolve_value
         let wrapper = Self {
             runtime,
             context,
+            intrinsics,
             callbacks: Mutex::new(Vec::new()),
-            module_loader: Mutex::new(None),
+            tasks: AsyncTaskQueue::default(),
+            native_modules: Box::default(),
+            module_cache: ModuleCache::default(),
+            resolve_limits: Mutex::new(ResolveLimits::default()),
+            budget: Mutex::new(None),
+            consumed_units: Mutex::new(0),
         };
+        wrapper.set_context_opaque();
+        intrinsics.init(&wrapper).map_err(ContextError::Execution)?;
 
         Ok(wrapper)
     }
 
+    /// The runtime of the context, shared with the other contexts created
+    /// from it.
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
     // See console standard: https://console.spec.whatwg.org
     pub fn set_console(&self, backend: Box<dyn ConsoleBackend>) -> Result<(), ExecutionError> {
         use crate::console::Level;
@@ -155,20 +207,30 @@ impl Context {
     ///
     /// All state and callbacks will be removed.
     pub fn reset(self) -> Result<Self, ContextError> {
+        self.tasks.lock().unwrap().clear();
+        self.native_modules.clear();
+        self.module_cache.relink(self.context);
         unsafe {
             q::JS_FreeContext(self.context);
         };
         self.callbacks.lock().unwrap().clear();
-        let context = unsafe { q::JS_NewContext(self.runtime) };
+        let context = unsafe { self.intrinsics.new_context(self.runtime.runtime_raw()) };
         if context.is_null() {
             return Err(ContextError::ContextCreationFailed);
         }
 
         let mut s = self;
         s.context = context;
+        s.set_context_opaque();
+        s.intrinsics.init(&s).map_err(ContextError::Execution)?;
         Ok(s)
     }
 
+    fn set_context_opaque(&self) {
+        let native_modules = &*self.native_modules as *const NativeModuleRegistry;
+        unsafe { q::JS_SetContextOpaque(self.context, native_modules as *mut c_void) };
+    }
+
     // Get raw pointer to the underlying QuickJS context.
     pub unsafe fn context_raw(&self) -> *mut q::JSContext {
         self.context
@@ -215,9 +277,7 @@ impl Context {
     /// This is especially important in debug builds where Rust/C frames are
     /// significantly larger than in release builds.
     pub fn update_stack_top(&self) {
-        unsafe {
-            q::JS_UpdateStackTop(self.runtime);
-        }
+        self.runtime.update_stack_top();
     }
 
     /// Set the maximum JS stack size (in bytes).
@@ -229,28 +289,13 @@ impl Context {
     ///
     /// Use `0` to disable the stack size limit entirely.
     pub fn set_max_stack_size(&self, size: usize) {
-        unsafe {
-            q::JS_SetMaxStackSize(self.runtime, size);
-        }
+        self.runtime.set_max_stack_size(size);
     }
 
+    /// The jobs of all the contexts of the runtime are executed, see
+    /// [Runtime::execute_pending_job].
     pub fn execute_pending_job(&self) -> Result<(), ExecutionError> {
-        let mut pctx = Box::new(std::ptr::null_mut::<JSContext>());
-        unsafe {
-            loop {
-                // TODO: is it actually essential to lock the context here?
-                // let _handle = self.context_lock.lock();
-                let err = q::JS_ExecutePendingJob(self.runtime, pctx.as_mut());
-
-                if err <= 0 {
-                    if err < 0 {
-                        ensure_no_excpetion(*pctx)?
-                    }
-                    break;
-                }
-            }
-        }
-        Ok(())
+        self.runtime.execute_pending_job()
     }
 
     /// Check if the given value is an exception, and return the exception if it is.
@@ -266,79 +311,74 @@ impl Context {
 
     /// If the given value is a promise, run the event loop until it is
     /// resolved, and return the final value.
+    ///
+    /// If the promise is rejected, the rejection value is returned as
+    /// `ExecutionError::Exception`.
+    ///
+    /// The event loop is bounded by the limits set with
+    /// [Context::set_resolve_limits].
+    ///
+    /// The futures of [async callbacks](Context::add_async_callback) are
+    /// polled while they are ready, but there is no executor waiting for them:
+    /// a promise waiting on a future that depends on an external event fails
+    /// to resolve. Drive such futures with [Context::run_async] instead.
     pub fn resolve_value(&self, value: OwnedJsValue) -> Result<OwnedJsValue, ExecutionError> {
-        if value.is_object() {
-            let obj = value.try_into_object()?;
-            if obj.is_promise()? {
-                self.eval(
-                    r#"
-                    // Values:
-                    //   - undefined: promise not finished
-                    //   - false: error ocurred, __promiseError is set.
-                    //   - true: finished, __promiseSuccess is set.
-                    var __promiseResult = 0;
-                    var __promiseValue = 0;
-
-                    var __resolvePromise = function(p) {
-                        p
-                            .then(value => {
-                                __promiseResult = true;
-                                __promiseValue = value;
-                            })
-                            .catch(e => {
-                                __promiseResult = false;
-                                __promiseValue = e;
-                            });
-                    }
-                "#,
-                    false,
-                )?;
-
-                let global = self.global()?;
-                let resolver = global
-                    .property_require("__resolvePromise")?
-                    .try_into_function()?;
-
-                // Call the resolver code that sets the result values once
-                // the promise resolves.
-                resolver.call(vec![obj.into_value()])?;
-
-                loop {
-                    let flag = unsafe {
-                        let wrapper_mut = self as *const Self as *mut Self;
-                        let ctx_mut = &mut (*wrapper_mut).context;
-                        q::JS_ExecutePendingJob(self.runtime, ctx_mut)
-                    };
-                    if flag < 0 {
-                        let e = get_exception(self.context).unwrap_or_else(|| {
-                            ExecutionError::Internal("Unknown exception".to_string())
-                        });
-                        return Err(e);
-                    }
+        let limits = *self.resolve_limits.lock().unwrap();
+        self.resolve_value_with_limits(value, limits)
+    }
 
-                    // Check if promise is finished.
-                    let res_val = global.property_require("__promiseResult")?;
-                    if res_val.is_bool() {
-                        let ok = res_val.to_bool()?;
-                        let value = global.property_require("__promiseValue")?;
-
-                        if ok {
-                            return self.resolve_value(value);
-                        } else {
-                            let err_msg = value.js_to_string()?;
-                            return Err(ExecutionError::Exception(OwnedJsValue::new(
-                                self.context,
-                                create_string(self.context, &err_msg).unwrap(),
-                            )));
-                        }
+    /// Like [Context::resolve_value], with the given limits instead of the
+    /// ones of the context.
+    pub fn resolve_value_with_limits(
+        &self,
+        value: OwnedJsValue,
+        limits: ResolveLimits,
+    ) -> Result<OwnedJsValue, ExecutionError> {
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        let mut jobs = 0;
+
+        let mut value = value;
+        while value.is_promise() {
+            let promise = value.try_into_promise()?;
+
+            value = loop {
+                match promise.state() {
+                    PromiseState::Fulfilled => break promise.result(),
+                    PromiseState::Rejected => {
+                        return Err(ExecutionError::Exception(promise.result().into()))
                     }
+                    PromiseState::Pending => {}
                 }
-            } else {
-                Ok(obj.into_value())
-            }
-        } else {
-            Ok(value)
+
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return Err(ExecutionError::Timeout);
+                }
+                if limits.max_jobs.is_some_and(|max| jobs >= max) {
+                    return Err(ExecutionError::JobLimitExceeded);
+                }
+
+                if !self.runtime.execute_one_pending_job()? && !self.poll_ready_tasks()? {
+                    let reason = if self.has_pending_tasks() {
+                        "async callbacks are not ready, drive them with Context::run_async"
+                    } else {
+                        "no pending jobs are left"
+                    };
+                    return Err(ExecutionError::Internal(format!(
+                        "Promise can not settle: {}",
+                        reason
+                    )));
+                }
+                jobs += 1;
+            };
         }
+
+        Ok(value)
+    }
+
+    /// Set the limits used by [Context::resolve_value], and therefore by
+    /// `eval` and `call_function` when resolving promises.
+    pub fn set_resolve_limits(&self, limits: ResolveLimits) {
+        *self.resolve_limits.lock().unwrap() = limits;
     }
 
     /// Evaluates Javascript code and returns the value of the final expression.
@@ -373,28 +413,15 @@ impl Context {
     /// );
     /// ```
     pub fn eval(&self, code: &str, resolve: bool) -> Result<OwnedJsValue, ExecutionError> {
-        let filename = "script.js";
-        let filename_c = make_cstring(filename)?;
-        let code_c = make_cstring(code)?;
-
-        let value_raw = unsafe {
-            q::JS_Eval(
-                self.context,
-                code_c.as_ptr(),
-                code.len(),
-                filename_c.as_ptr(),
-                q::JS_EVAL_TYPE_GLOBAL as i32,
-            )
-        };
-        let value = OwnedJsValue::new(self.context, value_raw);
-
-        self.check_exception(&value)?;
-
-        if resolve {
-            self.resolve_value(value)
-        } else {
-            Ok(value)
-        }
+        self.runtime.ensure_not_terminated()?;
+        self.metered(|| {
+            let value = eval_script(self.context, code)?;
+            if resolve {
+                self.resolve_value(value)
+            } else {
+                Ok(value)
+            }
+        })
     }
 
     /// Evaluates Javascript code and returns the value of the final expression
@@ -409,7 +436,8 @@ impl Context {
     /// promise failed.
     ///
     /// **Returns**:
-    /// Return value will always be undefined on module mode.
+    /// Return value will always be undefined on module mode, use
+    /// [Context::load_module] to access the exports of the module.
     ///
     /// ```ignore
     /// use quickjs_rusty::Context;
@@ -418,28 +446,19 @@ impl Context {
     /// let value = context.eval_module("import {foo} from 'bar'; foo();", false).unwrap();
     /// ```
     pub fn eval_module(&self, code: &str, resolve: bool) -> Result<OwnedJsValue, ExecutionError> {
-        let filename = "module.js";
-        let filename_c = make_cstring(filename)?;
-        let code_c = make_cstring(code)?;
-
-        let value_raw = unsafe {
-            q::JS_Eval(
-                self.context,
-                code_c.as_ptr(),
-                code.len(),
-                filename_c.as_ptr(),
-                q::JS_EVAL_TYPE_MODULE as i32,
-            )
-        };
-        let value = OwnedJsValue::new(self.context, value_raw);
-
-        self.check_exception(&value)?;
-
-        if resolve {
-            self.resolve_value(value)
-        } else {
-            Ok(value)
-        }
+        self.runtime.ensure_not_terminated()?;
+        self.metered(|| {
+            let filename = "module.js";
+            let module =
+                crate::compile::compile_module(self.context, code, filename)?.try_into_module()?;
+            let (_, value) = self.evaluate_module(module, filename)?;
+
+            if resolve {
+                self.resolve_value(value)
+            } else {
+                Ok(value)
+            }
+        })
     }
 
     /// Evaluates Javascript code and returns the value of the final expression
@@ -493,15 +512,16 @@ impl Context {
     /// let value = context.run_module("./module");
     /// ```
     pub fn run_module(&self, filename: &str) -> Result<OwnedJsPromise, ExecutionError> {
+        self.runtime.ensure_not_terminated()?;
         let filename_c = make_cstring(filename)?;
 
-        let ret = unsafe {
+        let ret = self.metered(|| unsafe {
             q::JS_LoadModule(
                 self.context,
                 ".\0".as_ptr() as *const c_char,
                 filename_c.as_ptr(),
             )
-        };
+        });
 
         let ret = OwnedJsValue::new(self.context, ret);
 
@@ -516,43 +536,319 @@ impl Context {
         }
     }
 
-    /// register module loader function, giving module name as input and return module code as output.
-    pub fn set_module_loader(
+    /// The names of the modules loaded in the context.
+    ///
+    /// Modules are cached by name, importing a loaded module again does not
+    /// call the module loader.
+    pub fn loaded_modules(&self) -> Result<Vec<String>, ExecutionError> {
+        let modules = self.module_cache.loaded(self.context)?;
+        Ok(modules.into_iter().map(|(_, name)| name).collect())
+    }
+
+    /// Remove a module and the modules importing it, directly or not, from
+    /// the module cache, so that the next import loads them again.
+    ///
+    /// Returns the names of the invalidated modules, which is empty if the
+    /// module is not loaded. Values created by the invalidated modules keep
+    /// working with the old code. Native modules can not be invalidated.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use quickjs_rusty::module_loader::{loader_fn, ModuleSource};
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// let version = Rc::new(Cell::new(1));
+    /// let current = version.clone();
+    /// context.set_module_loader(loader_fn(move |_, _| {
+    ///     Ok(ModuleSource::from(format!("globalThis.version = {};", current.get())))
+    /// }));
+    ///
+    /// context.eval_module("import 'plugin';", false).unwrap();
+    /// version.set(2);
+    /// context.invalidate_module("plugin").unwrap();
+    /// context.eval_module("import 'plugin';", false).unwrap();
+    /// assert_eq!(context.eval_as::<i32>("version").unwrap(), 2);
+    /// ```
+    pub fn invalidate_module(&self, name: &str) -> Result<Vec<String>, ExecutionError> {
+        let mut names = self
+            .module_cache
+            .invalidate(self.context, &self.native_modules, name)?;
+        if let Some(state) = self.native_modules.commonjs() {
+            let mut uncached = names.clone();
+            uncached.push(name.to_string());
+            let uncached = commonjs::uncache(self.context, &state, &uncached)?;
+            if uncached.iter().any(|n| n == name) && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Invalidate a module and its dependents, then import it again,
+    /// see [Context::invalidate_module] and [Context::run_module].
+    ///
+    /// To pick up the changes of an imported module, invalidate it and
+    /// reload the module importing it.
+    pub fn reload_module(&self, name: &str) -> Result<OwnedJsPromise, ExecutionError> {
+        self.invalidate_module(name)?;
+        self.run_module(name)
+    }
+
+    /// Compile and evaluate module code, returning the module to access its
+    /// exports from Rust.
+    ///
+    /// `name` is used to resolve the imports of the module. If the evaluation
+    /// returns a promise, because of a top level await, it is resolved.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// let module = context
+    ///     .load_module("export const greet = (name) => `Hello ${name}`;", "plugin.js")
+    ///     .unwrap();
+    /// let greeting = module.call_export("greet", vec!["world"]).unwrap();
+    /// assert_eq!(greeting.to_string(), Ok("Hello world".to_string()));
+    /// assert!(module.namespace().is_ok());
+    /// ```
+    pub fn load_module(&self, code: &str, name: &str) -> Result<JsModule, ExecutionError> {
+        self.runtime.ensure_not_terminated()?;
+        self.metered(|| {
+            let module =
+                crate::compile::compile_module(self.context, code, name)?.try_into_module()?;
+            let (module, ret) = self.evaluate_module(module, name)?;
+            self.resolve_value(ret)?;
+
+            Ok(module)
+        })
+    }
+
+    /// Resolve the imports of a compiled module, populate its `import.meta`
+    /// and evaluate it, returning the module and the evaluation result.
+    fn evaluate_module(
         &self,
-        module_loader_func: JSModuleLoaderFunc,
-        module_normalize: Option<JSModuleNormalizeFunc>,
-        opaque: *mut c_void,
-    ) {
-        let has_module_normalize = module_normalize.is_some();
+        module: JsModule,
+        name: &str,
+    ) -> Result<(JsModule, OwnedJsValue), ExecutionError> {
+        if unsafe { q::JS_ResolveModule(self.context, *module.as_inner()) } < 0 {
+            // QuickJS frees the modules that could not be resolved.
+            std::mem::forget(module);
+            ensure_no_excpetion(self.context)?;
+            return Err(ExecutionError::Internal(format!(
+                "Could not resolve the imports of module {}",
+                name
+            )));
+        }
 
-        let module_loader = ModuleLoader {
-            loader: module_loader_func,
-            normalize: module_normalize,
-            opaque,
+        let hook = self.runtime.import_meta_hook();
+        init_import_meta(self.context, module.def(), name, hook.as_deref())?;
+
+        let ret = unsafe {
+            // NOTE: JS_EvalFunction takes ownership.
+            q::JS_EvalFunction(self.context, OwnedJsValue::clone(&module).extract())
         };
+        let ret = OwnedJsValue::new(self.context, ret);
+        self.check_exception(&ret)?;
 
-        let module_loader = Box::new(module_loader);
-        let module_loader_ptr = module_loader.as_ref() as *const _ as *mut c_void;
+        Ok((module, ret))
+    }
 
-        unsafe {
-            if has_module_normalize {
-                q::JS_SetModuleLoaderFunc(
-                    self.runtime,
-                    Some(js_module_normalize),
-                    Some(js_module_loader),
-                    module_loader_ptr,
-                );
-            } else {
-                q::JS_SetModuleLoaderFunc(
-                    self.runtime,
-                    None,
-                    Some(js_module_loader),
-                    module_loader_ptr,
-                );
-            }
+    /// Set the loader of the modules imported by Javascript code.
+    ///
+    /// The loader is given the module name returned by the
+    /// [module resolver](Self::set_module_resolver), and returns the module
+    /// source, bytecode or compiled module, see [ModuleSource].
+    /// Errors are thrown into Javascript, see [ModuleError].
+    ///
+    /// The module loader is shared by the contexts of the runtime.
+    /// Replaces any module loader set before.
+    pub fn set_module_loader(&self, loader: impl ModuleLoader + 'static) {
+        self.runtime.set_module_loader(loader);
+    }
+
+    /// Set the resolver of the specifiers of imports to module names.
+    ///
+    /// Without a resolver, relative specifiers are resolved against the
+    /// name of the importing module, and other specifiers are used as is.
+    ///
+    /// The module resolver is shared by the contexts of the runtime.
+    /// Replaces any module resolver set before.
+    pub fn set_module_resolver(&self, resolver: impl ModuleResolver + 'static) {
+        self.runtime.set_module_resolver(resolver);
+    }
+
+    /// Enable CommonJS modules, installing a global `require` function.
+    ///
+    /// `require` resolves specifiers with
+    /// [ModuleResolver::resolve_require](crate::module_loader::ModuleResolver::resolve_require)
+    /// of the [module resolver](Self::set_module_resolver), which matches the
+    /// `"require"` condition of package exports for the [FsModuleLoader], and
+    /// loads modules with the [module loader](Self::set_module_loader), like
+    /// imports do. The code of
+    /// the module is wrapped in a function getting `exports`, `require`,
+    /// `module`, `__filename` and `__dirname`, and `module.exports` is
+    /// returned. Modules are cached by name in `require.cache`, a module
+    /// required while it is evaluated, by a circular dependency, returns its
+    /// exports as they are at that point. Modules named `*.json` are parsed as
+    /// JSON.
+    ///
+    /// ES modules can import modules loaded as [ModuleSource::CommonJs], they
+    /// get `module.exports` as default export and its properties as named
+    /// exports. The [FsModuleLoader] loads `.cjs` files, and `.js` files of
+    /// packages with `"type": "commonjs"`, as CommonJS modules.
+    ///
+    /// The global `require` resolves specifiers like an import of the module
+    /// named `.`. `require` is removed by [Context::reset].
+    ///
+    /// ```rust
+    /// use quickjs_rusty::module_loader::{loader_fn, ModuleSource};
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context.set_module_loader(loader_fn(|name, _| match name {
+    ///     "greet" => Ok(ModuleSource::CommonJs(
+    ///         "module.exports = (name) => `Hello ${name}`;".to_string(),
+    ///     )),
+    ///     _ => Ok(ModuleSource::from("exports.version = 2;")),
+    /// }));
+    /// context.enable_commonjs().unwrap();
+    ///
+    /// assert_eq!(context.eval_as::<i32>("require('lib').version").unwrap(), 2);
+    /// let module = context
+    ///     .load_module("import greet from 'greet'; export default greet('world');", "main.js")
+    ///     .unwrap();
+    /// assert_eq!(module.get_export::<String>("default").unwrap(), "Hello world");
+    /// ```
+    pub fn enable_commonjs(&self) -> Result<(), ExecutionError> {
+        if self.native_modules.commonjs().is_some() {
+            return Ok(());
         }
+        // `require` finds the module loader through the module loader opaque.
+        self.runtime.install_module_loader();
+        let state = commonjs::install(self.context)?;
+        self.native_modules.set_commonjs(state);
+        Ok(())
+    }
+
+    /// Set a hook populating the `import.meta` of modules, called with the
+    /// module name before the module is evaluated.
+    ///
+    /// `import.meta.url` and `import.meta.resolve()` are always set, as well
+    /// as `import.meta.filename` and `import.meta.dirname` for modules named
+    /// by an absolute path, like the ones of the [FsModuleLoader]. The hook
+    /// can override them. `import.meta.resolve()` resolves a specifier like an
+    /// import of the module, with the [module resolver](Self::set_module_resolver).
+    ///
+    /// Errors are thrown into Javascript, see [ModuleError].
+    ///
+    /// The hook is shared by the contexts of the runtime.
+    /// Replaces any hook set before.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context.set_import_meta_hook(|name, meta| {
+    ///     meta.set("main", name == "main.js")?;
+    ///     Ok(())
+    /// });
+    /// let module = context
+    ///     .load_module("export const main = import.meta.main;", "main.js")
+    ///     .unwrap();
+    /// assert_eq!(module.get_export::<bool>("main").unwrap(), true);
+    /// ```
+    pub fn set_import_meta_hook<F>(&self, hook: F)
+    where
+        F: Fn(&str, &ImportMeta) -> Result<(), ModuleError> + 'static,
+    {
+        self.runtime.set_import_meta_hook(hook);
+    }
 
-        *self.module_loader.lock().unwrap() = Some(module_loader);
+    /// Load modules from the filesystem, see [FsModuleLoader].
+    ///
+    /// Replaces any module loader and resolver set before.
+    pub fn set_fs_module_loader(&self, loader: FsModuleLoader) {
+        self.runtime.set_fs_module_loader(loader);
+    }
+
+    /// Load modules from memory, see [VirtualModuleFs].
+    ///
+    /// Replaces any module loader and resolver set before.
+    pub fn set_virtual_module_fs(&self, fs: VirtualModuleFs) {
+        self.runtime.set_virtual_module_fs(fs);
+    }
+
+    /// Register an ES module whose exports are defined in Rust.
+    ///
+    /// The module can then be imported by its name from module code, without
+    /// going through the module loader.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context
+    ///     .register_native_module("math", |m| {
+    ///         m.export("version", "1.0")
+    ///             .export_callback("add", |a: i32, b: i32| a + b);
+    ///     })
+    ///     .unwrap();
+    ///
+    /// context
+    ///     .eval_module("import { add, version } from 'math'; globalThis.x = `${add(1, 2)} ${version}`;", false)
+    ///     .unwrap();
+    /// assert_eq!(context.eval_as::<String>("x").unwrap(), "3 1.0");
+    /// ```
+    pub fn register_native_module(
+        &self,
+        name: &str,
+        define: impl FnOnce(&mut NativeModuleBuilder),
+    ) -> Result<(), ExecutionError> {
+        let mut module = NativeModuleBuilder::new(self);
+        define(&mut module);
+        let exports = module.finish()?;
+        self.native_modules.register(self.context, name, exports)?;
+        Ok(())
+    }
+
+    /// Register a module type, imported with a `type` attribute.
+    ///
+    /// The content returned by the module loader is converted by `create`,
+    /// given the module name, into the default export of the module.
+    /// The `json`, `text` and `bytes` types are supported out of the box,
+    /// and can be replaced.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::module_loader::{loader_fn, ModuleSource};
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context.set_module_loader(loader_fn(|_, _| Ok(ModuleSource::from("a,b,c"))));
+    /// context.register_module_type("csv", |_, content| {
+    ///     let content = String::from_utf8(content)?;
+    ///     Ok(content.split(',').map(str::to_string).collect::<Vec<_>>())
+    /// });
+    ///
+    /// context
+    ///     .eval_module("import row from 'row.csv' with { type: 'csv' }; globalThis.x = row[1];", false)
+    ///     .unwrap();
+    /// assert_eq!(context.eval_as::<String>("x").unwrap(), "b");
+    /// ```
+    pub fn register_module_type<T, F>(&self, module_type: &str, create: F)
+    where
+        T: ToOwnedJsValue,
+        F: Fn(&str, Vec<u8>) -> anyhow::Result<T> + 'static,
+    {
+        self.native_modules.add_module_type(
+            module_type,
+            Box::new(move |context, name, content| {
+                let value =
+                    create(name, content).map_err(|e| ExecutionError::Internal(e.to_string()))?;
+                Ok((context, value).into())
+            }),
+        );
     }
 
     /// Set the host promise rejection tracker.\
@@ -562,17 +858,153 @@ impl Context {
         func: q::JSHostPromiseRejectionTracker,
         opaque: *mut c_void,
     ) {
-        unsafe {
-            q::JS_SetHostPromiseRejectionTracker(self.runtime, func, opaque);
-        }
+        self.runtime
+            .set_host_promise_rejection_tracker(func, opaque);
     }
 
-    /// Set the interrupt handler.\
+    /// Set the interrupt handler of the runtime.\
     /// Return != 0 if the JS code needs to be interrupted.
+    ///
+    /// Prefer [Context::set_interrupt], this replaces its handler and
+    /// disables timeouts, termination handles and budgets, until one of them
+    /// is used again and silently replaces the raw handler, see
+    /// [Runtime::set_interrupt_handler].
     pub fn set_interrupt_handler(&self, func: q::JSInterruptHandler, opaque: *mut c_void) {
-        unsafe {
-            q::JS_SetInterruptHandler(self.runtime, func, opaque);
+        self.runtime.set_interrupt_handler(func, opaque);
+    }
+
+    /// Set a handler called regularly while Javascript code runs, returning
+    /// true to interrupt it.
+    ///
+    /// The interrupted execution fails with [ExecutionError::Interrupted],
+    /// which Javascript code can not catch. The handler is shared by the
+    /// contexts of the runtime, and replaces any handler set before.
+    ///
+    /// The handler may replace or remove itself, but must not run Javascript
+    /// code, as it is called while the code of the runtime is suspended.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use quickjs_rusty::{Context, ExecutionError};
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// let stop = Rc::new(Cell::new(false));
+    /// let flag = stop.clone();
+    /// context.set_interrupt(move || flag.get());
+    /// context.add_callback("stop", move || stop.set(true)).unwrap();
+    ///
+    /// let result = context.eval("stop(); try { for (;;) {} } catch (e) {}", false);
+    /// assert_eq!(result, Err(ExecutionError::Interrupted));
+    /// ```
+    pub fn set_interrupt<F>(&self, handler: F)
+    where
+        F: FnMut() -> bool + 'static,
+    {
+        self.runtime.set_interrupt(handler);
+    }
+
+    /// A handle terminating the Javascript code running in the context, or
+    /// another context of the runtime, from any thread.
+    ///
+    /// See [TerminationHandle].
+    pub fn termination_handle(&self) -> TerminationHandle {
+        self.runtime.termination_handle()
+    }
+
+    /// Run `f`, interrupting the Javascript code it runs once `timeout` has
+    /// elapsed, which then fails with [ExecutionError::Timeout].
+    ///
+    /// The timeout covers the Javascript code run by `f` with this context or
+    /// other contexts of the runtime. A timeout nested in another one can only
+    /// shorten it.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use quickjs_rusty::{Context, ExecutionError};
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context.eval("function spin() { for (;;) {} }", false).unwrap();
+    /// let result = context.with_timeout(Duration::from_millis(50), || {
+    ///     context.call_function("spin", Vec::<i32>::new())
+    /// });
+    /// assert_eq!(result, Err(ExecutionError::Timeout));
+    /// ```
+    pub fn with_timeout<R>(&self, timeout: Duration, f: impl FnOnce() -> R) -> R {
+        self.runtime.with_deadline(Instant::now() + timeout, f)
+    }
+
+    /// Evaluates Javascript code like [Context::eval], without resolving
+    /// promises, failing with [ExecutionError::Timeout] if it does not
+    /// complete within `timeout`.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use quickjs_rusty::{Context, ExecutionError};
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// let value = context.eval_with_timeout("1 + 2", Duration::from_secs(1)).unwrap();
+    /// assert_eq!(value.to_int(), Ok(3));
+    ///
+    /// let result = context.eval_with_timeout("for (;;) {}", Duration::from_millis(50));
+    /// assert_eq!(result, Err(ExecutionError::Timeout));
+    /// ```
+    pub fn eval_with_timeout(
+        &self,
+        code: &str,
+        timeout: Duration,
+    ) -> Result<OwnedJsValue, ExecutionError> {
+        self.with_timeout(timeout, || self.eval(code, false))
+    }
+
+    /// Set the budget of units of work of each execution, or None to disable
+    /// metering.
+    ///
+    /// A unit is counted at each function call and loop iteration of the
+    /// Javascript code, so that running the same code gives the same count,
+    /// unlike a [timeout](Context::with_timeout). [Context::eval],
+    /// [Context::eval_module], [Context::load_module], [Context::run_module]
+    /// and [Context::call_function] are metered, including the jobs run to
+    /// resolve their promises, and fail with [ExecutionError::BudgetExceeded]
+    /// once the budget is exceeded, which Javascript code can not catch.
+    /// An execution started while another one is metered, from a callback,
+    /// is counted by the outer one, also in another context of the runtime.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::{Context, ExecutionError};
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context.set_budget(Some(1_000));
+    /// context.eval("for (let i = 0; i < 100; i++) {}", false).unwrap();
+    /// let units = context.consumed_units();
+    /// assert!(units > 0);
+    ///
+    /// context.eval("for (let i = 0; i < 100; i++) {}", false).unwrap();
+    /// assert_eq!(context.consumed_units(), units);
+    ///
+    /// let result = context.eval("for (;;) {}", false);
+    /// assert_eq!(result, Err(ExecutionError::BudgetExceeded));
+    /// assert_eq!(context.consumed_units(), 1_000);
+    /// ```
+    pub fn set_budget(&self, budget: Option<u64>) {
+        *self.budget.lock().unwrap() = budget;
+    }
+
+    /// The units of work consumed by the last metered execution, see
+    /// [Context::set_budget].
+    pub fn consumed_units(&self) -> u64 {
+        *self.consumed_units.lock().unwrap()
+    }
+
+    /// Run `f` against the budget of the context, if any, or the one of the
+    /// running metered execution.
+    fn metered<R>(&self, f: impl FnOnce() -> R) -> R {
+        let budget = *self.budget.lock().unwrap();
+        let (ret, consumed) = self.runtime.with_meter(self.context, budget, f);
+        if let Some(consumed) = consumed {
+            *self.consumed_units.lock().unwrap() = consumed;
         }
+        ret
     }
 
     /// Call a global function in the Javascript namespace.
@@ -598,6 +1030,7 @@ impl Context {
         function_name: &str,
         args: impl IntoIterator<Item = impl ToOwnedJsValue>,
     ) -> Result<OwnedJsValue, ExecutionError> {
+        self.runtime.ensure_not_terminated()?;
         let qargs = args
             .into_iter()
             .map(|v| (self.context, v).into())
@@ -608,18 +1041,21 @@ impl Context {
             .property_require(function_name)?
             .try_into_function()?;
 
-        let ret = func.call(qargs)?;
-        let v = self.resolve_value(ret)?;
-
-        Ok(v)
+        self.metered(|| {
+            let ret = func.call(qargs)?;
+            self.resolve_value(ret)
+        })
     }
 
     /// Create a JS function that is backed by a Rust function or closure.
     /// Can be used to create a function and add it to an object.
     ///
     /// The callback must satisfy several requirements:
-    /// * accepts 0 - 5 arguments
-    /// * each argument must be convertible from a JsValue
+    /// * accepts 0 - 16 arguments
+    /// * each argument must be convertible from a JsValue, or be one of the
+    ///   [This], [Ctx], [NewTarget] and [Rest] extractors
+    /// * missing arguments are passed as undefined, so that `Option<T>`
+    ///   arguments become `None`; extra arguments are ignored
     /// * must return a value
     /// * the return value must either:
     ///   - be convertible to JsValue
@@ -650,45 +1086,82 @@ impl Context {
         &self,
         callback: impl Callback<F> + 'static,
     ) -> Result<JsFunction, ExecutionError> {
-        let argcount = callback.argument_count() as i32;
+        let argcount = callback.argument_count();
 
         let context = self.context;
-        let wrapper = move |argc: c_int, argv: *mut q::JSValue| -> q::JSValue {
-            match exec_callback(context, argc, argv, &callback) {
+        let wrapper = move |this: q::JSValue,
+                            argc: c_int,
+                            argv: *mut q::JSValue,
+                            new_target: q::JSValue|
+              -> q::JSValue {
+            match exec_callback(context, this, argc, argv, new_target, &callback) {
                 Ok(value) => value,
-                // TODO: better error reporting.
-                Err(e) => {
-                    let js_exception_value = match e {
-                        ExecutionError::Exception(e) => unsafe { e.extract() },
-                        other => create_string(context, other.to_string().as_str()).unwrap(),
-                    };
-                    unsafe {
-                        q::JS_Throw(context, js_exception_value);
-                    }
-
-                    unsafe { q::JS_Ext_NewSpecialValue(q::JS_TAG_EXCEPTION, 0) }
-                }
+                Err(e) => throw_callback_error(context, e),
             }
         };
 
-        let (pair, trampoline) = unsafe { build_closure_trampoline(wrapper) };
-        let data = (&*pair.1) as *const q::JSValue as *mut q::JSValue;
-        self.callbacks.lock().unwrap().push(pair);
-
-        let obj = unsafe {
-            let f = q::JS_NewCFunctionData(self.context, trampoline, argcount, 0, 1, data);
-            OwnedJsValue::new(self.context, f)
-        };
+        let f = self.create_raw_function(wrapper, argcount)?;
+        Ok(f.try_into_function()?)
+    }
 
-        let f = obj.try_into_function()?;
+    /// Create a JS constructor function that is backed by a Rust function or
+    /// closure.
+    ///
+    /// The function can be called with `new`, and must then return the
+    /// constructed object. The [NewTarget] extractor tells whether the
+    /// function was called with `new`, and creates an object with the right
+    /// prototype.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::{Context, Ctx, ExecutionError, NewTarget, OwnedJsObject};
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context
+    ///     .add_constructor("Point", |ctx: Ctx, target: NewTarget, x: i32, y: i32| {
+    ///         let point = target.new_object(ctx)?;
+    ///         point.set_property("x", ctx.value(x))?;
+    ///         point.set_property("y", ctx.value(y))?;
+    ///         Ok::<OwnedJsObject, ExecutionError>(point)
+    ///     })
+    ///     .unwrap();
+    ///
+    /// let ok = context
+    ///     .eval_as::<bool>("const p = new Point(1, 2); p instanceof Point && p.y === 2")
+    ///     .unwrap();
+    /// assert!(ok);
+    /// ```
+    pub fn create_constructor<F>(
+        &self,
+        callback: impl Callback<F> + 'static,
+    ) -> Result<JsFunction, ExecutionError> {
+        let f = self.create_callback(callback)?;
+        unsafe {
+            q::JS_SetConstructorBit(self.context, f.value, true);
+        }
         Ok(f)
     }
 
+    /// Add a global JS constructor function that is backed by a Rust function
+    /// or closure.
+    ///
+    /// See [Context::create_constructor] for details.
+    pub fn add_constructor<F>(
+        &self,
+        name: &str,
+        callback: impl Callback<F> + 'static,
+    ) -> Result<(), ExecutionError> {
+        let cfunc = self.create_constructor(callback)?;
+        let global = self.global()?;
+        global.set_property(name, cfunc.into_value())?;
+        Ok(())
+    }
+
     /// Add a global JS function that is backed by a Rust function or closure.
     ///
     /// The callback must satisfy several requirements:
-    /// * accepts 0 - 5 arguments
-    /// * each argument must be convertible from a JsValue
+    /// * accepts 0 - 16 arguments
+    /// * each argument must be convertible from a JsValue, or be an extractor
+    ///   (see [Context::create_callback])
     /// * must return a value
     /// * the return value must either:
     ///   - be convertible to JsValue
@@ -721,44 +1194,224 @@ impl Context {
         Ok(())
     }
 
+    /// Create a JS function that is backed by an async Rust function or closure.
+    ///
+    /// Calling the function from Javascript returns a Promise, which is
+    /// resolved with the output of the returned future, or rejected if the
+    /// output is an `Err(e)`.
+    ///
+    /// The same argument and return value requirements as for
+    /// [Context::create_callback] apply, with the return value being the
+    /// output of the future.
+    ///
+    /// The futures are not spawned on any executor: they are polled by
+    /// [Context::poll_async], usually through [Context::run_async].
+    pub fn create_async_callback<F>(
+        &self,
+        callback: impl AsyncCallback<F> + 'static,
+    ) -> Result<JsFunction, ExecutionError> {
+        let argcount = callback.argument_count() as i32;
+
+        let context = self.context;
+        let tasks = self.tasks.clone();
+        // Not a constructor, so never called with new.target.
+        let wrapper = move |this: q::JSValue,
+                            argc: c_int,
+                            argv: *mut q::JSValue,
+                            _new_target: q::JSValue|
+              -> q::JSValue {
+            match exec_async_callback(context, this, argc, argv, &callback, &tasks) {
+                Ok(value) => value,
+                Err(e) => throw_callback_error(context, e),
+            }
+        };
+
+        let (pair, trampoline) = unsafe { build_closure_trampoline(wrapper) };
+        let data = (&*pair.1) as *const q::JSValue as *mut q::JSValue;
+        self.callbacks.lock().unwrap().push(pair);
+
+        let obj = unsafe {
+            let f = q::JS_NewCFunctionData(self.context, trampoline, argcount, 0, 1, data);
+            OwnedJsValue::new(self.context, f)
+        };
+
+        let f = obj.try_into_function()?;
+        Ok(f)
+    }
+
+    /// Add a global JS function that is backed by an async Rust function or
+    /// closure.
+    ///
+    /// See [Context::create_async_callback] for details.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// context
+    ///     .add_async_callback("add", |a: i32, b: i32| async move { a + b })
+    ///     .unwrap();
+    ///
+    /// let promise = context.eval("add(3, 4)", false).unwrap();
+    /// assert!(promise.is_promise());
+    /// ```
+    pub fn add_async_callback<F>(
+        &self,
+        name: &str,
+        callback: impl AsyncCallback<F> + 'static,
+    ) -> Result<(), ExecutionError> {
+        let cfunc = self.create_async_callback(callback)?;
+        let global = self.global()?;
+        global.set_property(name, cfunc.into_value())?;
+        Ok(())
+    }
+
+    /// Add a global JS function generated by the `#[js_function]` macro of the
+    /// `derive` feature, under its Javascript name.
+    pub fn register_function<F: JsFunctionDef>(&self) -> Result<(), ExecutionError> {
+        let func = F::create(self)?;
+        let global = self.global()?;
+        global.set_property(F::NAME, func.into_value())?;
+        Ok(())
+    }
+
+    /// Returns true if there are async callbacks that have not completed yet.
+    pub fn has_pending_tasks(&self) -> bool {
+        !self.tasks.lock().unwrap().is_empty()
+    }
+
+    /// Poll the futures of pending async callbacks, settling the promises of
+    /// the completed ones, and execute the pending jobs of the event loop.
+    ///
+    /// Returns `Poll::Ready` once no async callback is pending anymore.
+    /// Otherwise the waker of `cx` is registered with the pending futures, so
+    /// this can be driven by any executor.
+    pub fn poll_async(&self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), ExecutionError>> {
+        loop {
+            let (_, spawned) = self.poll_tasks(cx)?;
+            let count = if spawned {
+                None
+            } else {
+                Some(self.tasks.lock().unwrap().len())
+            };
+
+            self.execute_pending_job()?;
+
+            // Poll again if new tasks were started, so they can register the waker.
+            let queue = self.tasks.lock().unwrap();
+            if count != Some(queue.len()) {
+                continue;
+            }
+
+            return if queue.is_empty() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            };
+        }
+    }
+
+    /// Poll the futures of the pending async callbacks once, settling the
+    /// promises of the completed ones.
+    ///
+    /// Returns whether any callback completed, and whether callbacks were
+    /// started meanwhile, which have not been polled yet. All the callbacks
+    /// are polled and the unfinished ones stay queued, even if settling a
+    /// promise fails, the first error being returned.
+    fn poll_tasks(&self, cx: &mut std::task::Context<'_>) -> Result<(bool, bool), ExecutionError> {
+        // Take the tasks out of the queue, as callbacks may add to it
+        // while they are polled.
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+
+        let mut pending = Vec::with_capacity(tasks.len());
+        let mut completed = false;
+        let mut error = None;
+        for mut task in tasks {
+            match task.poll(cx) {
+                Poll::Ready(result) => {
+                    completed = true;
+                    if let Err(e) = result {
+                        error.get_or_insert(e);
+                    }
+                }
+                Poll::Pending => pending.push(task),
+            }
+        }
+
+        let mut queue = self.tasks.lock().unwrap();
+        let spawned = !queue.is_empty();
+        pending.append(&mut queue);
+        *queue = pending;
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok((completed, spawned)),
+        }
+    }
+
+    /// Poll the pending async callbacks without an executor, as long as they
+    /// wake themselves up, returning whether any of them completed.
+    fn poll_ready_tasks(&self) -> Result<bool, ExecutionError> {
+        #[derive(Default)]
+        struct WakeFlag(AtomicBool);
+
+        impl Wake for WakeFlag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let woken = Arc::new(WakeFlag::default());
+        let waker = Waker::from(woken.clone());
+        let mut cx = std::task::Context::from_waker(&waker);
+        while self.has_pending_tasks() {
+            woken.0.store(false, Ordering::SeqCst);
+            let (completed, spawned) = self.poll_tasks(&mut cx)?;
+            if completed {
+                return Ok(true);
+            }
+            if !spawned && !woken.0.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns a future that drives async callbacks and the event loop until
+    /// no async callback is pending anymore.
+    ///
+    /// See [Context::poll_async].
+    pub fn run_async(&self) -> impl Future<Output = Result<(), ExecutionError>> + '_ {
+        std::future::poll_fn(move |cx| self.poll_async(cx))
+    }
+
     /// create a custom callback function
     pub fn create_custom_callback(
         &self,
         callback: CustomCallback,
     ) -> Result<JsFunction, ExecutionError> {
         let context = self.context;
-        let wrapper = move |argc: c_int, argv: *mut q::JSValue| -> q::JSValue {
+        let wrapper = move |_this: q::JSValue,
+                            argc: c_int,
+                            argv: *mut q::JSValue,
+                            _new_target: q::JSValue|
+              -> q::JSValue {
             let result = std::panic::catch_unwind(|| {
                 let arg_slice = unsafe { std::slice::from_raw_parts(argv, argc as usize) };
                 match callback(context, arg_slice) {
                     Ok(Some(value)) => value,
                     Ok(None) => unsafe { q::JS_Ext_NewSpecialValue(q::JS_TAG_UNDEFINED, 0) },
-                    // TODO: better error reporting.
-                    Err(e) => {
-                        // TODO: should create an Error type.
-                        let js_exception_value =
-                            create_string(context, e.to_string().as_str()).unwrap();
-
-                        unsafe {
-                            q::JS_Throw(context, js_exception_value);
-                        }
-
-                        unsafe { q::JS_Ext_NewSpecialValue(q::JS_TAG_EXCEPTION, 0) }
-                    }
+                    Err(e) => throw_callback_error(
+                        context,
+                        JsThrow::from_error(e).into_exception(context),
+                    ),
                 }
             });
 
             match result {
                 Ok(v) => v,
-                Err(_) => {
-                    // TODO: should create an Error type.
-                    let js_exception_value = create_string(context, "Callback panicked!").unwrap();
-
-                    unsafe {
-                        q::JS_Throw(context, js_exception_value);
-                    }
-
-                    unsafe { q::JS_Ext_NewSpecialValue(q::JS_TAG_EXCEPTION, 0) }
+                Err(e) => {
+                    throw_callback_error(context, JsThrow::from_panic(e).into_exception(context))
                 }
             }
         };
@@ -775,4 +1428,199 @@ impl Context {
         let f = obj.try_into_function()?;
         Ok(f)
     }
+
+    /// Register the Rust type `T` as a Javascript class.
+    ///
+    /// The class constructor is exposed as a global named [JsClass::NAME].
+    /// Methods and accessors declared in [JsClass::define] are placed on the
+    /// class prototype, and operate on the Rust value wrapped by each instance.
+    ///
+    /// The wrapped value is dropped when the instance is garbage collected.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::{ClassBuilder, Context, JsClass};
+    ///
+    /// struct Point {
+    ///     x: i32,
+    ///     y: i32,
+    /// }
+    ///
+    /// impl JsClass for Point {
+    ///     const NAME: &'static str = "Point";
+    ///
+    ///     fn define(class: &mut ClassBuilder<Self>) {
+    ///         class
+    ///             .constructor(|x: i32, y: i32| Point { x, y })
+    ///             .getter("x", |p: &Point| p.x)
+    ///             .method("sum", |p: &Point| p.x + p.y);
+    ///     }
+    /// }
+    ///
+    /// let context = Context::builder().build().unwrap();
+    /// context.register_class::<Point>().unwrap();
+    ///
+    /// let sum = context.eval_as::<i32>("new Point(1, 2).sum()").unwrap();
+    /// assert_eq!(sum, 3);
+    /// ```
+    pub fn register_class<T: JsClass>(&self) -> Result<(), ExecutionError> {
+        let class_id = self
+            .runtime
+            .classes()
+            .register::<T>(unsafe { self.runtime.runtime_raw() })?;
+
+        let mut class = ClassBuilder::<T>::new();
+        T::define(&mut class);
+
+        let proto = OwnedJsValue::new(self.context, unsafe { q::JS_NewObject(self.context) })
+            .try_into_object()?;
+
+        for (name, argument_count, method) in class.methods {
+            let func = self.create_class_function::<T>(method, argument_count)?;
+            define_member(self.context, &proto, &name, None, None, Some(func))?;
+        }
+        for (name, getter, setter) in class.accessors {
+            let getter = getter
+                .map(|f| self.create_class_function::<T>(f, 0))
+                .transpose()?;
+            let setter = setter
+                .map(|f| self.create_class_function::<T>(f, 1))
+                .transpose()?;
+            define_member(self.context, &proto, &name, getter, setter, None)?;
+        }
+
+        let (argument_count, constructor) = match class.constructor {
+            Some((count, constructor)) => (count, Some(constructor)),
+            None => (0, None),
+        };
+
+        let context = self.context;
+        let wrapper = move |_this: q::JSValue,
+                            argc: c_int,
+                            argv: *mut q::JSValue,
+                            new_target: q::JSValue|
+              -> q::JSValue {
+            match call_constructor::<T>(
+                context,
+                class_id,
+                new_target,
+                argc,
+                argv,
+                constructor.as_ref(),
+            ) {
+                Ok(value) => value,
+                Err(e) => throw_callback_error(context, e),
+            }
+        };
+        let ctor = self.create_raw_function(wrapper, argument_count)?;
+
+        unsafe {
+            q::JS_SetConstructorBit(self.context, ctor.value, true);
+            q::JS_SetConstructor(self.context, ctor.value, proto.value);
+            // Takes ownership of the prototype.
+            q::JS_SetClassProto(self.context, class_id, proto.into_value().extract());
+        }
+
+        let global = self.global()?;
+        global.set_property(T::NAME, ctor)?;
+        Ok(())
+    }
+
+    /// Wrap `value` into a new instance of the registered class `T`.
+    ///
+    /// [Context::register_class] must have been called for `T` beforehand, in
+    /// this context.
+    pub fn create_class_instance<T: JsClass>(
+        &self,
+        value: T,
+    ) -> Result<JsClassInstance<T>, ExecutionError> {
+        let not_registered =
+            || ExecutionError::Internal(format!("Class {} is not registered", T::NAME));
+        let class_id = self
+            .runtime
+            .classes()
+            .class_id::<T>()
+            .ok_or_else(not_registered)?;
+
+        // Class ids are shared by the runtime, prototypes are set by context.
+        let proto = OwnedJsValue::new(self.context, unsafe {
+            q::JS_GetClassProto(self.context, class_id)
+        });
+        if !proto.is_object() {
+            return Err(not_registered());
+        }
+        let instance = new_instance(self.context, proto.value, class_id, value)?;
+        Ok(JsClassInstance::try_from_value(instance)?)
+    }
+
+    fn create_class_function<T: JsClass>(
+        &self,
+        member: ClassFunction<T>,
+        argument_count: usize,
+    ) -> Result<OwnedJsValue, ExecutionError> {
+        let context = self.context;
+        let wrapper = move |this: q::JSValue,
+                            argc: c_int,
+                            argv: *mut q::JSValue,
+                            _new_target: q::JSValue|
+              -> q::JSValue {
+            match call_member::<T>(context, this, argc, argv, &member) {
+                Ok(value) => value,
+                Err(e) => throw_callback_error(context, e),
+            }
+        };
+        self.create_raw_function(wrapper, argument_count)
+    }
+
+    fn create_raw_function<F>(
+        &self,
+        wrapper: F,
+        argument_count: usize,
+    ) -> Result<OwnedJsValue, ExecutionError>
+    where
+        F: Fn(q::JSValue, c_int, *mut q::JSValue, q::JSValue) -> q::JSValue + 'static,
+    {
+        let (pair, trampoline) = unsafe { build_closure_trampoline(wrapper) };
+        let data = (&*pair.1) as *const q::JSValue as *mut q::JSValue;
+        self.callbacks.lock().unwrap().push(pair);
+
+        let f = self.runtime.classes().new_function(
+            self.context,
+            trampoline,
+            argument_count as c_int,
+            data,
+        );
+        let func = OwnedJsValue::new(self.context, f);
+        if func.is_exception() {
+            return Err(ExecutionError::Internal(
+                "Could not create function".to_string(),
+            ));
+        }
+        Ok(func)
+    }
+}
+
+/// Evaluate `code` as a global script of `context`, without resolving
+/// promises, the code path of [Context::eval] and [Ctx::eval].
+pub(crate) fn eval_script(
+    context: *mut q::JSContext,
+    code: &str,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let filename_c = make_cstring("script.js")?;
+    let code_c = make_cstring(code)?;
+
+    let value_raw = unsafe {
+        q::JS_Eval(
+            context,
+            code_c.as_ptr(),
+            code.len(),
+            filename_c.as_ptr(),
+            q::JS_EVAL_TYPE_GLOBAL as i32,
+        )
+    };
+    let value = OwnedJsValue::new(context, value_raw);
+    if value.is_exception() {
+        return Err(get_exception(context)
+            .unwrap_or_else(|| ExecutionError::Internal("Unknown exception".to_string())));
+    }
+    Ok(value)
 }
@@ -0,0 +1,378 @@
+use std::{
+    ffi::{c_int, c_void},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use libquickjs_ng_sys as q;
+
+use crate::errors::ExecutionError;
+use crate::value::OwnedJsValue;
+
+use super::runtime::RuntimeState;
+
+/// Why the interrupt handler stopped the execution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum InterruptReason {
+    /// The handler set with [Runtime::set_interrupt](super::Runtime::set_interrupt).
+    Handler,
+    /// The deadline set with [Runtime::with_deadline](super::Runtime::with_deadline).
+    Deadline,
+    /// A [TerminationHandle].
+    Terminated,
+    /// The budget set with [Context::set_budget](super::Context::set_budget).
+    Budget,
+}
+
+/// A handle terminating the Javascript code running in a runtime, from any
+/// thread.
+///
+/// Get one with [Context::termination_handle](super::Context::termination_handle).
+/// Once [terminated](Self::terminate), the Javascript code running in the
+/// runtime fails with [ExecutionError::Terminated] at its next interrupt
+/// check. Until the handle is [reset](Self::reset), the code run afterwards
+/// with [Context::eval](super::Context::eval),
+/// [Context::eval_module](super::Context::eval_module),
+/// [Context::load_module](super::Context::load_module),
+/// [Context::run_module](super::Context::run_module) or
+/// [Context::call_function](super::Context::call_function) fails the same way
+/// without being run. Handles of the same runtime share their state.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use quickjs_rusty::{Context, ExecutionError};
+/// let context = Context::builder().build().unwrap();
+///
+/// let handle = context.termination_handle();
+/// let watchdog = handle.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_millis(50));
+///     watchdog.terminate();
+/// });
+///
+/// let result = context.eval("for (;;) {}", false);
+/// assert_eq!(result, Err(ExecutionError::Terminated));
+/// assert!(handle.is_terminated());
+///
+/// handle.reset();
+/// assert_eq!(context.eval_as::<i32>("1 + 1").unwrap(), 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TerminationHandle {
+    terminated: Arc<AtomicBool>,
+}
+
+impl TerminationHandle {
+    /// Terminate the running Javascript code, at its next interrupt check.
+    pub fn terminate(&self) {
+        self.terminated.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the runtime is terminated.
+    pub fn is_terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    /// Clear the termination, so that Javascript code can run again.
+    pub fn reset(&self) {
+        self.terminated.store(false, Ordering::SeqCst);
+    }
+}
+
+type InterruptHandler = dyn FnMut() -> bool;
+
+/// The handler set with [Runtime::set_interrupt](super::Runtime::set_interrupt).
+#[derive(Default)]
+struct HandlerSlot {
+    handler: Option<Box<InterruptHandler>>,
+    /// Incremented each time the handler is set, so that a handler replaced
+    /// while it runs is not put back.
+    generation: u64,
+}
+
+/// Counts the units of work done by the code of contexts of a runtime against
+/// a budget.
+///
+/// A unit is a poll of the interrupt counter of a context, at each backward
+/// jump and function call. The counters are set to the units left, so that
+/// the interrupt handler is called when they are used. The units used by the
+/// contexts are summed, a context run by a callback of the metered one is
+/// attached while it runs.
+pub(crate) struct Meter {
+    budget: u64,
+    consumed: u64,
+    contexts: Vec<MeteredContext>,
+    exhausted: bool,
+}
+
+struct MeteredContext {
+    context: *mut q::JSContext,
+    /// The value the counter was last set to.
+    chunk: c_int,
+    /// The counter before metering started, restored afterwards.
+    saved: c_int,
+}
+
+impl Meter {
+    fn start(context: *mut q::JSContext, budget: u64) -> Self {
+        let mut meter = Self {
+            budget,
+            consumed: 0,
+            contexts: Vec::new(),
+            exhausted: false,
+        };
+        meter.attach(context);
+        meter
+    }
+
+    /// Count the units of `context` too, returning false if they already are.
+    fn attach(&mut self, context: *mut q::JSContext) -> bool {
+        if self
+            .contexts
+            .iter()
+            .any(|metered| metered.context == context)
+        {
+            return false;
+        }
+        let saved = unsafe { q::JS_Ext_GetInterruptCounter(context) };
+        self.contexts.push(MeteredContext {
+            context,
+            chunk: 0,
+            saved,
+        });
+        self.settle();
+        true
+    }
+
+    /// Stop counting the units of `context`.
+    fn detach(&mut self, context: *mut q::JSContext) {
+        self.settle();
+        if let Some(index) = self
+            .contexts
+            .iter()
+            .position(|metered| metered.context == context)
+        {
+            let metered = self.contexts.remove(index);
+            unsafe { q::JS_Ext_SetInterruptCounter(metered.context, metered.saved) };
+        }
+    }
+
+    /// Add the units used by the contexts since their counters were set, and
+    /// set them to the units left.
+    fn settle(&mut self) {
+        let init = unsafe { q::JS_Ext_GetInterruptCounterInit() };
+        for metered in &self.contexts {
+            let counter = unsafe { q::JS_Ext_GetInterruptCounter(metered.context) };
+            // QuickJS resets the counter once it reaches zero.
+            let used = if counter == init {
+                metered.chunk
+            } else {
+                (metered.chunk - counter).max(0)
+            };
+            self.consumed += used as u64;
+        }
+        if self.consumed > self.budget {
+            self.exhausted = true;
+        }
+
+        // Stay below the value QuickJS resets the counters to, so that a reset
+        // tells that a counter reached zero. The unit after the budget is the
+        // one exceeding it.
+        let left = self
+            .budget
+            .saturating_sub(self.consumed)
+            .saturating_add(1)
+            .min((init - 1) as u64) as c_int;
+        for metered in &mut self.contexts {
+            metered.chunk = left;
+            unsafe { q::JS_Ext_SetInterruptCounter(metered.context, left) };
+        }
+    }
+
+    /// Called by the interrupt handler, returns true once the budget is
+    /// exceeded.
+    fn tick(&mut self) -> bool {
+        self.settle();
+        self.exhausted
+    }
+
+    /// Stop metering, returning the consumed units, at most the budget.
+    fn finish(mut self) -> u64 {
+        self.settle();
+        for metered in &self.contexts {
+            unsafe { q::JS_Ext_SetInterruptCounter(metered.context, metered.saved) };
+        }
+        self.consumed.min(self.budget)
+    }
+}
+
+/// The interrupt handler, deadline and termination of a runtime, given as
+/// opaque to the interrupt handler function.
+#[derive(Default)]
+pub(crate) struct InterruptState {
+    handler: Mutex<HandlerSlot>,
+    termination: TerminationHandle,
+    deadline: Mutex<Option<Instant>>,
+    meter: Mutex<Option<Meter>>,
+    /// The reason of the last interruption, to report it when the error is
+    /// caught in Rust.
+    reason: Mutex<Option<InterruptReason>>,
+}
+
+impl InterruptState {
+    pub(crate) fn set_handler(&self, handler: Option<Box<InterruptHandler>>) {
+        let mut slot = self.handler.lock().unwrap();
+        slot.handler = handler;
+        slot.generation += 1;
+    }
+
+    pub(crate) fn termination(&self) -> &TerminationHandle {
+        &self.termination
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        *self.deadline.lock().unwrap()
+    }
+
+    pub(crate) fn set_deadline(&self, deadline: Option<Instant>) {
+        *self.deadline.lock().unwrap() = deadline;
+    }
+
+    /// Start metering the code of `context`, returning false if metering is
+    /// already running.
+    pub(crate) fn start_meter(&self, context: *mut q::JSContext, budget: u64) -> bool {
+        let mut meter = self.meter.lock().unwrap();
+        if meter.is_some() {
+            return false;
+        }
+        *meter = Some(Meter::start(context, budget));
+        true
+    }
+
+    /// Count the units of `context` with the running meter, returning None if
+    /// metering is not running, or whether the context was attached, false if
+    /// its units are already counted.
+    pub(crate) fn attach_meter(&self, context: *mut q::JSContext) -> Option<bool> {
+        let mut meter = self.meter.lock().unwrap();
+        meter.as_mut().map(|meter| meter.attach(context))
+    }
+
+    /// Stop counting the units of an attached `context`.
+    pub(crate) fn detach_meter(&self, context: *mut q::JSContext) {
+        if let Some(meter) = self.meter.lock().unwrap().as_mut() {
+            meter.detach(context);
+        }
+    }
+
+    /// Stop metering, returning the consumed units.
+    pub(crate) fn finish_meter(&self) -> u64 {
+        let meter = self.meter.lock().unwrap().take();
+        meter.map_or(0, Meter::finish)
+    }
+
+    pub(crate) fn reason(&self) -> Option<InterruptReason> {
+        *self.reason.lock().unwrap()
+    }
+
+    pub(crate) fn reset_reason(&self) {
+        self.reason.lock().unwrap().take();
+    }
+
+    fn check(&self) -> Option<InterruptReason> {
+        // The meter counts the units even when interrupting for another reason.
+        let exceeded = self
+            .meter
+            .lock()
+            .unwrap()
+            .as_mut()
+            .is_some_and(|meter| meter.tick());
+
+        if self.termination.is_terminated() {
+            return Some(InterruptReason::Terminated);
+        }
+        if self
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Some(InterruptReason::Deadline);
+        }
+        if exceeded {
+            return Some(InterruptReason::Budget);
+        }
+        self.call_handler().then_some(InterruptReason::Handler)
+    }
+
+    /// Call the handler without holding the lock, so that it can replace or
+    /// remove itself.
+    fn call_handler(&self) -> bool {
+        let (handler, generation) = {
+            let mut slot = self.handler.lock().unwrap();
+            (slot.handler.take(), slot.generation)
+        };
+        let Some(mut handler) = handler else {
+            return false;
+        };
+        // A panicking handler interrupts the execution.
+        let interrupt = catch_unwind(AssertUnwindSafe(&mut handler)).unwrap_or(true);
+
+        let mut slot = self.handler.lock().unwrap();
+        if slot.generation == generation {
+            slot.handler = Some(handler);
+        }
+        interrupt
+    }
+}
+
+pub(crate) unsafe extern "C" fn js_interrupt_handler(
+    _rt: *mut q::JSRuntime,
+    opaque: *mut c_void,
+) -> c_int {
+    let state = &*(opaque as *const InterruptState);
+    // A panicking handler interrupts the execution.
+    let reason =
+        catch_unwind(AssertUnwindSafe(|| state.check())).unwrap_or(Some(InterruptReason::Handler));
+    match reason {
+        Some(reason) => {
+            *state.reason.lock().unwrap() = Some(reason);
+            1
+        }
+        None => 0,
+    }
+}
+
+/// The error of an execution stopped by the interrupt handler.
+pub(crate) fn interrupt_error(context: *mut q::JSContext) -> ExecutionError {
+    let reason =
+        unsafe { RuntimeState::from_context(context) }.and_then(|state| state.interrupt.reason());
+    match reason {
+        Some(InterruptReason::Deadline) => ExecutionError::Timeout,
+        Some(InterruptReason::Terminated) => ExecutionError::Terminated,
+        Some(InterruptReason::Budget) => ExecutionError::BudgetExceeded,
+        _ => ExecutionError::Interrupted,
+    }
+}
+
+/// Create the uncatchable error rethrowing an interruption into Javascript.
+///
+/// The reason of `error` is recorded, so that the error is reported again
+/// once the interruption reaches Rust, also if it was not raised by the
+/// interrupt handler.
+pub(crate) fn interrupted_error_value(
+    context: *mut q::JSContext,
+    error: &ExecutionError,
+) -> OwnedJsValue {
+    let reason = match error {
+        ExecutionError::Timeout => InterruptReason::Deadline,
+        ExecutionError::Terminated => InterruptReason::Terminated,
+        ExecutionError::BudgetExceeded => InterruptReason::Budget,
+        _ => InterruptReason::Handler,
+    };
+    if let Some(state) = unsafe { RuntimeState::from_context(context) } {
+        *state.interrupt.reason.lock().unwrap() = Some(reason);
+    }
+    OwnedJsValue::new(context, unsafe { q::JS_Ext_NewInterruptedError(context) })
+}
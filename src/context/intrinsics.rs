@@ -0,0 +1,176 @@
+use libquickjs_ng_sys as q;
+
+use crate::errors::*;
+
+use super::Context;
+
+/// Replaces `eval` and the constructors of the function kinds by functions
+/// throwing an `EvalError`, keeping `instanceof Function` working. The
+/// replacements can not be overwritten nor deleted, to restore the originals.
+const DISABLE_EVAL: &str = r#"
+(function () {
+    function disabled(proto) {
+        const thrower = function () {
+            throw new EvalError('Code generation from strings is disabled');
+        };
+        thrower.prototype = proto;
+        return thrower;
+    }
+
+    function lock(object, key, value) {
+        Object.defineProperty(object, key, { value, writable: false, configurable: false });
+    }
+
+    lock(globalThis, 'eval', disabled(undefined));
+    const functions = [function () {}, function* () {}, async function () {}, async function* () {}];
+    for (const f of functions) {
+        const proto = Object.getPrototypeOf(f);
+        // Async functions have no prototype without the Promise intrinsic.
+        if (proto !== null) {
+            lock(proto, 'constructor', disabled(proto));
+        }
+    }
+    lock(globalThis, 'Function', Function.prototype.constructor);
+})();
+"#;
+
+/// The built-in objects of a context, see
+/// [ContextBuilder::intrinsics](super::ContextBuilder::intrinsics).
+///
+/// The base objects, like `Object`, `Function`, `Array`, `Error`, `Math` or
+/// `Reflect`, are always available. The default enables all intrinsics, like
+/// [Context::new] does.
+///
+/// ```rust
+/// use quickjs_rusty::{Context, Intrinsics};
+///
+/// let context = Context::builder()
+///     .intrinsics(Intrinsics {
+///         eval: false,
+///         proxy: false,
+///         ..Intrinsics::default()
+///     })
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(context.eval_as::<String>("typeof Proxy").unwrap(), "undefined");
+/// assert!(context.eval("eval('1 + 1')", false).is_err());
+/// assert!(context.eval("new Function('return 1')", false).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Intrinsics {
+    /// `Date`.
+    pub date: bool,
+    /// `eval` and the `Function` constructors, generating code from strings.
+    ///
+    /// Without it, they throw an `EvalError`. Code evaluated from Rust is not
+    /// affected.
+    ///
+    /// This is a best-effort block at the Javascript level: the intrinsic is
+    /// still there, since evaluating code from Rust needs it, and `eval` and
+    /// the constructors are replaced by throwing functions, which can not be
+    /// overwritten nor deleted.
+    pub eval: bool,
+    /// `RegExp` and regular expression literals.
+    pub regexp: bool,
+    /// `JSON`.
+    ///
+    /// Without it, [serde](crate::serde) conversions, the console,
+    /// [OwnedJsValue::to_json_string](crate::OwnedJsValue::to_json_string),
+    /// JSON imports and `require` of `.json` files still work.
+    pub json: bool,
+    /// `Proxy`.
+    pub proxy: bool,
+    /// `Map`, `Set`, `WeakMap` and `WeakSet`.
+    pub map_set: bool,
+    /// `ArrayBuffer`, `SharedArrayBuffer`, `DataView` and the typed arrays.
+    pub typed_arrays: bool,
+    /// `Promise`, which async functions and modules need.
+    pub promise: bool,
+    /// `BigInt`.
+    pub bigint: bool,
+    /// `WeakRef` and `FinalizationRegistry`.
+    pub weak_ref: bool,
+    /// `performance`.
+    pub performance: bool,
+}
+
+impl Default for Intrinsics {
+    fn default() -> Self {
+        Self {
+            date: true,
+            eval: true,
+            regexp: true,
+            json: true,
+            proxy: true,
+            map_set: true,
+            typed_arrays: true,
+            promise: true,
+            bigint: true,
+            weak_ref: true,
+            performance: true,
+        }
+    }
+}
+
+impl Intrinsics {
+    /// Only the base objects.
+    pub fn none() -> Self {
+        Self {
+            date: false,
+            eval: false,
+            regexp: false,
+            json: false,
+            proxy: false,
+            map_set: false,
+            typed_arrays: false,
+            promise: false,
+            bigint: false,
+            weak_ref: false,
+            performance: false,
+        }
+    }
+
+    /// Create a JSContext with the intrinsics, returning null on failure.
+    pub(crate) unsafe fn new_context(&self, runtime: *mut q::JSRuntime) -> *mut q::JSContext {
+        if *self == Self::default() {
+            return q::JS_NewContext(runtime);
+        }
+
+        let context = q::JS_NewContextRaw(runtime);
+        if context.is_null() {
+            return context;
+        }
+        q::JS_AddIntrinsicBaseObjects(context);
+        // Evaluating any code needs the eval intrinsic, `eval` itself is
+        // disabled once the context is created.
+        q::JS_AddIntrinsicEval(context);
+
+        let intrinsics = [
+            (self.date, q::JS_AddIntrinsicDate as unsafe extern "C" fn(_)),
+            (self.regexp, q::JS_AddIntrinsicRegExp),
+            (self.json, q::JS_AddIntrinsicJSON),
+            (self.proxy, q::JS_AddIntrinsicProxy),
+            (self.map_set, q::JS_AddIntrinsicMapSet),
+            (self.typed_arrays, q::JS_AddIntrinsicTypedArrays),
+            (self.promise, q::JS_AddIntrinsicPromise),
+            (self.bigint, q::JS_AddIntrinsicBigInt),
+            (self.weak_ref, q::JS_AddIntrinsicWeakRef),
+            (self.performance, q::JS_AddPerformance),
+        ];
+        for (enabled, add) in intrinsics {
+            if enabled {
+                add(context);
+            }
+        }
+        context
+    }
+
+    /// Finish the setup of a context created by [Intrinsics::new_context].
+    pub(crate) fn init(&self, context: &Context) -> Result<(), ExecutionError> {
+        if !self.eval {
+            context.eval(DISABLE_EVAL, false)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,460 @@
+use std::{ffi::c_void, rc::Rc, time::Instant};
+
+use libquickjs_ng_sys::{self as q, JSContext};
+
+use crate::class::ClassRegistry;
+use crate::errors::*;
+use crate::module_loader::*;
+use crate::utils::{ensure_no_excpetion, get_exception};
+use crate::value::JobWakers;
+
+use super::interrupt::{js_interrupt_handler, InterruptState, TerminationHandle};
+use super::{Context, Intrinsics};
+
+/// Runtime is a wrapper around a QuickJS Javascript runtime.
+///
+/// A runtime owns the heap, the memory limit, the garbage collector, the
+/// interrupt handler, the module loader and the job queue, which are shared
+/// by the [contexts](Runtime::context) created from it. Each context is an
+/// isolated realm, with its own global object, but values can be passed
+/// between the contexts of a runtime. A value must not outlive the context
+/// that created it.
+///
+/// `Runtime` is a cheap handle that can be cloned. Contexts hold a handle to
+/// their runtime, so the runtime is freed once all of its handles and
+/// contexts are dropped. A runtime and its contexts must be used from a
+/// single thread.
+///
+/// ```rust
+/// use quickjs_rusty::Runtime;
+/// let runtime = Runtime::new().unwrap();
+///
+/// let a = runtime.context().unwrap();
+/// let b = runtime.context().unwrap();
+///
+/// a.set_global("x", 1).unwrap();
+/// assert_eq!(b.eval_as::<bool>("typeof x === 'undefined'").unwrap(), true);
+///
+/// let point = a.eval("({ x: 1, y: 2 })", false).unwrap();
+/// b.set_global("point", point).unwrap();
+/// assert_eq!(b.eval_as::<i32>("point.x + point.y").unwrap(), 3);
+/// ```
+#[derive(Clone)]
+pub struct Runtime {
+    inner: Rc<RuntimeInner>,
+}
+
+struct RuntimeInner {
+    runtime: *mut q::JSRuntime,
+    /// The module loader, resolver and `import.meta` hook, also stored as the
+    /// module loader opaque.
+    module_loaders: Box<ModuleLoaders>,
+    /// Also stored as the runtime opaque.
+    state: Box<RuntimeState>,
+}
+
+/// The state of a runtime reachable from a raw `JSContext`, stored as the
+/// runtime opaque.
+#[derive(Default)]
+pub(crate) struct RuntimeState {
+    /// Class ids registered in the runtime.
+    pub(crate) classes: ClassRegistry,
+    pub(crate) interrupt: InterruptState,
+    pub(crate) job_wakers: JobWakers,
+}
+
+impl RuntimeState {
+    pub(crate) unsafe fn from_context<'a>(context: *mut q::JSContext) -> Option<&'a Self> {
+        (q::JS_GetRuntimeOpaque(q::JS_GetRuntime(context)) as *const Self).as_ref()
+    }
+
+    /// Fail with [ExecutionError::Terminated] if the runtime is terminated,
+    /// as code may not reach an interrupt check before it completes.
+    pub(crate) fn ensure_not_terminated(&self) -> Result<(), ExecutionError> {
+        if self.interrupt.termination().is_terminated() {
+            Err(ExecutionError::Terminated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run `f`, counting the units of work of the Javascript code it runs in
+    /// `context` against `budget`, see [Context::set_budget].
+    ///
+    /// The state is reached from the context, so that code only holding a
+    /// raw context, like [Ctx](crate::Ctx), is metered too.
+    ///
+    /// Returns the consumed units, or None if metering is already running,
+    /// in which case the outer meter counts the units of `context` too, or if
+    /// there is no budget.
+    pub(crate) fn with_meter<R>(
+        &self,
+        context: *mut q::JSContext,
+        budget: Option<u64>,
+        f: impl FnOnce() -> R,
+    ) -> (R, Option<u64>) {
+        let interrupt = &self.interrupt;
+        let metering = match (interrupt.attach_meter(context), budget) {
+            (Some(true), _) => Metering::Attached,
+            (Some(false), _) | (None, None) => return (f(), None),
+            (None, Some(budget)) => {
+                interrupt.start_meter(context, budget);
+                self.install_interrupt_handler(unsafe { q::JS_GetRuntime(context) });
+                Metering::Started
+            }
+        };
+
+        let mut guard = MeterGuard {
+            interrupt,
+            context,
+            metering: Some(metering),
+        };
+        let ret = f();
+        (ret, guard.stop())
+    }
+
+    fn install_interrupt_handler(&self, runtime: *mut q::JSRuntime) {
+        let interrupt = &self.interrupt as *const InterruptState;
+        unsafe {
+            q::JS_SetInterruptHandler(
+                runtime,
+                Some(js_interrupt_handler),
+                interrupt as *mut c_void,
+            );
+        }
+    }
+}
+
+impl Drop for RuntimeInner {
+    fn drop(&mut self) {
+        // The contexts hold a handle to the runtime, so they are all freed.
+        unsafe {
+            q::JS_FreeRuntime(self.runtime);
+        }
+    }
+}
+
+impl Runtime {
+    /// Create a new runtime, without any context.
+    pub fn new() -> Result<Self, ContextError> {
+        let runtime = unsafe { q::JS_NewRuntime() };
+        if runtime.is_null() {
+            return Err(ContextError::RuntimeCreationFailed);
+        }
+
+        let inner = RuntimeInner {
+            runtime,
+            module_loaders: Box::default(),
+            state: Box::default(),
+        };
+        unsafe {
+            let state = &*inner.state as *const RuntimeState;
+            q::JS_SetRuntimeOpaque(runtime, state as *mut c_void);
+        }
+
+        Ok(Self {
+            inner: Rc::new(inner),
+        })
+    }
+
+    /// Create a new context in the runtime.
+    ///
+    /// Use [Context::builder] with [ContextBuilder::runtime](super::ContextBuilder::runtime)
+    /// to configure the context.
+    pub fn context(&self) -> Result<Context, ContextError> {
+        Context::from_runtime(self.clone(), Intrinsics::default())
+    }
+
+    // Get raw pointer to the underlying QuickJS runtime.
+    pub unsafe fn runtime_raw(&self) -> *mut q::JSRuntime {
+        self.inner.runtime
+    }
+
+    /// Whether both handles refer to the same runtime.
+    pub fn ptr_eq(&self, other: &Runtime) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Set the memory limit of the runtime (in bytes), shared by its
+    /// contexts.
+    pub fn set_memory_limit(&self, max_bytes: usize) {
+        unsafe {
+            q::JS_SetMemoryLimit(self.inner.runtime, max_bytes as _);
+        }
+    }
+
+    /// Set the number of allocated bytes triggering a garbage collection.
+    pub fn set_gc_threshold(&self, bytes: usize) {
+        unsafe {
+            q::JS_SetGCThreshold(self.inner.runtime, bytes);
+        }
+    }
+
+    /// Run the garbage collector.
+    pub fn run_gc(&self) {
+        unsafe {
+            q::JS_RunGC(self.inner.runtime);
+        }
+    }
+
+    /// See [Context::update_stack_top].
+    pub fn update_stack_top(&self) {
+        unsafe {
+            q::JS_UpdateStackTop(self.inner.runtime);
+        }
+    }
+
+    /// See [Context::set_max_stack_size].
+    pub fn set_max_stack_size(&self, size: usize) {
+        unsafe {
+            q::JS_SetMaxStackSize(self.inner.runtime, size);
+        }
+    }
+
+    /// Set the host promise rejection tracker.
+    pub fn set_host_promise_rejection_tracker(
+        &self,
+        func: q::JSHostPromiseRejectionTracker,
+        opaque: *mut c_void,
+    ) {
+        unsafe {
+            q::JS_SetHostPromiseRejectionTracker(self.inner.runtime, func, opaque);
+        }
+    }
+
+    /// Set the interrupt handler.\
+    /// Return != 0 if the JS code needs to be interrupted.
+    ///
+    /// Replaces the handler of [Runtime::set_interrupt], and disables
+    /// deadlines, termination handles and budgets. The raw handler is in turn
+    /// replaced, without notice, by the next call to
+    /// [Runtime::set_interrupt], [Runtime::with_deadline],
+    /// [Runtime::termination_handle] or a metered execution, see
+    /// [Context::set_budget].
+    pub fn set_interrupt_handler(&self, func: q::JSInterruptHandler, opaque: *mut c_void) {
+        self.inner.state.interrupt.reset_reason();
+        unsafe {
+            q::JS_SetInterruptHandler(self.inner.runtime, func, opaque);
+        }
+    }
+
+    /// Set a handler called regularly while Javascript code runs, returning
+    /// true to interrupt it, see [Context::set_interrupt].
+    pub fn set_interrupt<F>(&self, handler: F)
+    where
+        F: FnMut() -> bool + 'static,
+    {
+        self.inner
+            .state
+            .interrupt
+            .set_handler(Some(Box::new(handler)));
+        self.install_interrupt_handler();
+    }
+
+    /// Remove the handler set with [Runtime::set_interrupt].
+    pub fn remove_interrupt(&self) {
+        self.inner.state.interrupt.set_handler(None);
+    }
+
+    /// Run `f`, interrupting the Javascript code it runs once `deadline` is
+    /// reached, see [Context::with_timeout].
+    pub fn with_deadline<R>(&self, deadline: Instant, f: impl FnOnce() -> R) -> R {
+        let interrupt = &self.inner.state.interrupt;
+        self.install_interrupt_handler();
+
+        // Nested deadlines can only shorten the outer one.
+        let previous = interrupt.deadline();
+        let deadline = previous.map_or(deadline, |previous| previous.min(deadline));
+        interrupt.set_deadline(Some(deadline));
+        let _guard = DeadlineGuard {
+            interrupt,
+            previous,
+        };
+        f()
+    }
+
+    /// Run `f`, counting the units of work of the Javascript code it runs in
+    /// `context` against `budget`, see [RuntimeState::with_meter].
+    pub(crate) fn with_meter<R>(
+        &self,
+        context: *mut q::JSContext,
+        budget: Option<u64>,
+        f: impl FnOnce() -> R,
+    ) -> (R, Option<u64>) {
+        self.inner.state.with_meter(context, budget, f)
+    }
+
+    /// A handle terminating the Javascript code running in the runtime from
+    /// another thread, see [TerminationHandle].
+    pub fn termination_handle(&self) -> TerminationHandle {
+        self.install_interrupt_handler();
+        self.inner.state.interrupt.termination().clone()
+    }
+
+    pub(crate) fn ensure_not_terminated(&self) -> Result<(), ExecutionError> {
+        self.inner.state.ensure_not_terminated()
+    }
+
+    /// Install the interrupt handler of the runtime state, replacing the one
+    /// set with [Runtime::set_interrupt_handler], if any.
+    fn install_interrupt_handler(&self) {
+        self.inner
+            .state
+            .install_interrupt_handler(self.inner.runtime);
+    }
+
+    /// Whether jobs of any context of the runtime are pending.
+    pub fn is_job_pending(&self) -> bool {
+        unsafe { q::JS_IsJobPending(self.inner.runtime) }
+    }
+
+    /// Execute the pending jobs of all the contexts of the runtime.
+    pub fn execute_pending_job(&self) -> Result<(), ExecutionError> {
+        let mut pctx = Box::new(std::ptr::null_mut::<JSContext>());
+        unsafe {
+            loop {
+                let err = q::JS_ExecutePendingJob(self.inner.runtime, pctx.as_mut());
+
+                if err <= 0 {
+                    if err < 0 {
+                        ensure_no_excpetion(*pctx)?
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute a single pending job, returning false if there was none.
+    pub(crate) fn execute_one_pending_job(&self) -> Result<bool, ExecutionError> {
+        let mut pctx = std::ptr::null_mut::<JSContext>();
+        let flag = unsafe { q::JS_ExecutePendingJob(self.inner.runtime, &mut pctx) };
+        if flag < 0 {
+            let e = get_exception(pctx)
+                .unwrap_or_else(|| ExecutionError::Internal("Unknown exception".to_string()));
+            return Err(e);
+        }
+        Ok(flag > 0)
+    }
+
+    /// Set the loader of the modules imported by the contexts of the
+    /// runtime, see [Context::set_module_loader].
+    pub fn set_module_loader(&self, loader: impl ModuleLoader + 'static) {
+        self.inner.module_loaders.set_loader(Rc::new(loader));
+        self.install_module_loader();
+    }
+
+    /// Set the resolver of the specifiers of imports to module names, see
+    /// [Context::set_module_resolver].
+    pub fn set_module_resolver(&self, resolver: impl ModuleResolver + 'static) {
+        self.inner.module_loaders.set_resolver(Rc::new(resolver));
+        self.install_module_loader();
+    }
+
+    /// Load modules from the filesystem, see [FsModuleLoader].
+    ///
+    /// Replaces any module loader and resolver set before.
+    pub fn set_fs_module_loader(&self, loader: FsModuleLoader) {
+        self.set_module_resolver(loader.clone());
+        self.set_module_loader(loader);
+    }
+
+    /// Load modules from memory, see [VirtualModuleFs].
+    ///
+    /// Replaces any module loader and resolver set before.
+    pub fn set_virtual_module_fs(&self, fs: VirtualModuleFs) {
+        let fs = Rc::new(fs);
+        self.set_module_resolver(fs.clone());
+        self.set_module_loader(fs);
+    }
+
+    /// Set a hook populating the `import.meta` of modules, see
+    /// [Context::set_import_meta_hook].
+    pub fn set_import_meta_hook<F>(&self, hook: F)
+    where
+        F: Fn(&str, &ImportMeta) -> Result<(), ModuleError> + 'static,
+    {
+        self.inner
+            .module_loaders
+            .set_import_meta_hook(Rc::new(hook));
+    }
+
+    pub(crate) fn import_meta_hook(&self) -> Option<Rc<ImportMetaHook>> {
+        self.inner.module_loaders.import_meta_hook()
+    }
+
+    pub(crate) fn install_module_loader(&self) {
+        let module_loaders = &*self.inner.module_loaders as *const ModuleLoaders as *mut c_void;
+        let module_normalize: q::JSModuleNormalizeFunc = if self.inner.module_loaders.has_resolver()
+        {
+            Some(js_module_normalize)
+        } else {
+            None
+        };
+        unsafe {
+            q::JS_SetModuleLoaderFunc2(
+                self.inner.runtime,
+                module_normalize,
+                Some(js_module_loader),
+                Some(js_module_check_attributes),
+                module_loaders,
+            );
+        }
+    }
+
+    pub(crate) fn classes(&self) -> &ClassRegistry {
+        &self.inner.state.classes
+    }
+
+    pub(crate) fn job_wakers(&self) -> &JobWakers {
+        &self.inner.state.job_wakers
+    }
+}
+
+/// Restores the previous deadline when dropped, also when `f` of
+/// [Runtime::with_deadline] panics.
+struct DeadlineGuard<'a> {
+    interrupt: &'a InterruptState,
+    previous: Option<Instant>,
+}
+
+impl Drop for DeadlineGuard<'_> {
+    fn drop(&mut self) {
+        self.interrupt.set_deadline(self.previous);
+    }
+}
+
+enum Metering {
+    /// The meter was started for the context.
+    Started,
+    /// The context was attached to a running meter.
+    Attached,
+}
+
+/// Stops metering when dropped, also when `f` of [RuntimeState::with_meter]
+/// panics, so that the meter does not outlive it.
+struct MeterGuard<'a> {
+    interrupt: &'a InterruptState,
+    context: *mut JSContext,
+    metering: Option<Metering>,
+}
+
+impl MeterGuard<'_> {
+    /// Stop metering, returning the consumed units if the meter was started.
+    fn stop(&mut self) -> Option<u64> {
+        match self.metering.take()? {
+            Metering::Started => Some(self.interrupt.finish_meter()),
+            Metering::Attached => {
+                self.interrupt.detach_meter(self.context);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for MeterGuard<'_> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
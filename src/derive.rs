@@ -0,0 +1,41 @@
+//! Helpers used by the code generated by the `quickjs-rusty-derive` macros.
+
+use std::convert::TryFrom;
+
+use libquickjs_ng_sys as q;
+
+use crate::utils::create_empty_object;
+use crate::{OwnedJsObject, OwnedJsValue, ToOwnedJsValue, ValueError};
+
+/// Conversion from a Javascript value, with errors converted to [ValueError].
+pub trait FromJsValue: Sized {
+    fn from_js_value(value: OwnedJsValue) -> Result<Self, ValueError>;
+}
+
+impl<T> FromJsValue for T
+where
+    T: TryFrom<OwnedJsValue>,
+    ValueError: From<T::Error>,
+{
+    fn from_js_value(value: OwnedJsValue) -> Result<Self, ValueError> {
+        Ok(T::try_from(value)?)
+    }
+}
+
+pub fn new_object(context: *mut q::JSContext) -> OwnedJsObject {
+    let object = OwnedJsValue::new(context, create_empty_object(context).unwrap());
+    OwnedJsObject::try_from_value(object).unwrap()
+}
+
+pub fn set_field<T: ToOwnedJsValue>(object: &OwnedJsObject, name: &str, value: T) {
+    let value = (object.context(), value).into();
+    object.set_property(name, value).unwrap();
+}
+
+pub fn get_field<T: FromJsValue>(object: &OwnedJsObject, name: &str) -> Result<T, ValueError> {
+    let value = object
+        .property(name)
+        .map_err(|e| ValueError::Internal(e.to_string()))?
+        .unwrap();
+    T::from_js_value(value)
+}
@@ -1,7 +1,13 @@
 mod context_error;
 mod execution_error;
+mod js_error;
+mod js_throw;
+mod module_error;
 mod value_error;
 
 pub use context_error::ContextError;
 pub use execution_error::ExecutionError;
+pub use js_error::{JsError, StackFrame};
+pub use js_throw::{JsErrorKind, JsThrow};
+pub use module_error::ModuleError;
 pub use value_error::ValueError;
@@ -1,8 +1,6 @@
 use std::{error, fmt};
 
-use crate::OwnedJsValue;
-
-use super::ValueError;
+use super::{JsError, ValueError};
 
 /// Error on Javascript execution.
 #[derive(Debug)]
@@ -14,9 +12,20 @@ pub enum ExecutionError {
     /// Internal error.
     Internal(String),
     /// JS Exception was thrown.
-    Exception(OwnedJsValue),
+    Exception(JsError),
     /// JS Runtime exceeded the memory limit.
     OutOfMemory,
+    /// The execution was stopped by the interrupt handler.
+    Interrupted,
+    /// The execution did not complete before its deadline.
+    Timeout,
+    /// The execution was stopped by a termination handle.
+    Terminated,
+    /// The execution used more units of work than its budget.
+    BudgetExceeded,
+    /// A promise did not settle within the maximum number of jobs of the
+    /// [ResolveLimits](crate::ResolveLimits).
+    JobLimitExceeded,
     #[doc(hidden)]
     __NonExhaustive,
 }
@@ -28,14 +37,13 @@ impl fmt::Display for ExecutionError {
             InputWithZeroBytes => write!(f, "Invalid script input: code contains zero byte (\\0)"),
             Conversion(e) => e.fmt(f),
             Internal(e) => write!(f, "Internal error: {}", e),
-            Exception(e) => {
-                if e.is_string() {
-                    write!(f, "{}", e.to_string().unwrap())
-                } else {
-                    write!(f, "JS Exception: {:?}", e)
-                }
-            }
+            Exception(e) => e.fmt(f),
             OutOfMemory => write!(f, "Out of memory: runtime memory limit exceeded"),
+            Interrupted => write!(f, "Execution interrupted"),
+            Timeout => write!(f, "Execution timed out"),
+            Terminated => write!(f, "Execution terminated"),
+            BudgetExceeded => write!(f, "Execution budget exceeded"),
+            JobLimitExceeded => write!(f, "Promise did not settle within the job limit"),
             __NonExhaustive => unreachable!(),
         }
     }
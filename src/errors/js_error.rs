@@ -0,0 +1,206 @@
+use std::fmt;
+
+use libquickjs_ng_sys as q;
+
+use crate::utils::make_cstring;
+use crate::OwnedJsValue;
+
+/// The maximum number of causes that are followed when reading the `cause`
+/// chain, as it may contain cycles.
+const MAX_CAUSE_DEPTH: usize = 32;
+
+/// A value thrown by Javascript code.
+///
+/// Holds the original thrown value, and, if it is an object, its parsed
+/// `name`, `message`, `stack` and `cause` properties.
+///
+/// Displays like the Javascript `String(value)`, e.g. `TypeError: not a function`.
+#[derive(Clone, Debug)]
+pub struct JsError {
+    value: OwnedJsValue,
+    // Boxed to keep `ExecutionError` small.
+    details: Box<Details>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Details {
+    display: String,
+    name: Option<String>,
+    message: Option<String>,
+    stack: Option<String>,
+    frames: Vec<StackFrame>,
+    cause: Option<JsError>,
+}
+
+/// A single frame of a Javascript stack trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    /// The function name, `<anonymous>` for anonymous functions.
+    pub function: String,
+    /// The file name, `None` for native functions.
+    pub file: Option<String>,
+    /// The 1-based line number.
+    pub line: Option<u32>,
+    /// The 1-based column number.
+    pub column: Option<u32>,
+}
+
+impl JsError {
+    /// Create a `JsError` from a thrown value.
+    pub fn from_value(value: OwnedJsValue) -> Self {
+        Self::from_value_with_depth(value, 0)
+    }
+
+    fn from_value_with_depth(value: OwnedJsValue, depth: usize) -> Self {
+        let display = if value.is_string() {
+            value.to_string().unwrap()
+        } else {
+            value
+                .js_to_string()
+                .unwrap_or_else(|_| format!("JS Exception: {:?}", value))
+        };
+
+        let mut details = Details {
+            display,
+            ..Default::default()
+        };
+
+        if value.is_object() {
+            details.name = string_property(&value, "name");
+            details.message = string_property(&value, "message");
+            details.stack = string_property(&value, "stack");
+            details.frames = details
+                .stack
+                .as_deref()
+                .map(parse_stack)
+                .unwrap_or_default();
+
+            if depth < MAX_CAUSE_DEPTH {
+                details.cause = property(&value, "cause")
+                    .filter(|cause| !cause.is_undefined())
+                    .map(|cause| Self::from_value_with_depth(cause, depth + 1));
+            }
+        }
+
+        Self {
+            value,
+            details: Box::new(details),
+        }
+    }
+
+    /// The original thrown value.
+    pub fn value(&self) -> &OwnedJsValue {
+        &self.value
+    }
+
+    /// Consume the error, returning the original thrown value.
+    pub fn into_value(self) -> OwnedJsValue {
+        self.value
+    }
+
+    /// The `name` property, e.g. `TypeError`.
+    pub fn name(&self) -> Option<&str> {
+        self.details.name.as_deref()
+    }
+
+    /// The `message` property.
+    pub fn message(&self) -> Option<&str> {
+        self.details.message.as_deref()
+    }
+
+    /// The `stack` property, unparsed.
+    pub fn stack(&self) -> Option<&str> {
+        self.details.stack.as_deref()
+    }
+
+    /// The frames parsed from the `stack` property, innermost first.
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.details.frames
+    }
+
+    /// The error set as `cause` property, if any.
+    pub fn cause(&self) -> Option<&JsError> {
+        self.details.cause.as_ref()
+    }
+
+    /// Returns true if this is the error QuickJS throws when it runs out of memory.
+    pub fn is_out_of_memory(&self) -> bool {
+        self.name() == Some("InternalError") && self.message() == Some("out of memory")
+    }
+}
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.details.display)
+    }
+}
+
+impl From<OwnedJsValue> for JsError {
+    fn from(value: OwnedJsValue) -> Self {
+        Self::from_value(value)
+    }
+}
+
+/// Read a property, discarding any exception thrown by a getter.
+fn property(value: &OwnedJsValue, name: &str) -> Option<OwnedJsValue> {
+    let context = value.context();
+    let name = make_cstring(name).ok()?;
+    let raw = unsafe { q::JS_GetPropertyStr(context, value.value, name.as_ptr()) };
+    let property = OwnedJsValue::new(context, raw);
+
+    if property.is_exception() {
+        // Dropping the exception clears it.
+        OwnedJsValue::new(context, unsafe { q::JS_GetException(context) });
+        None
+    } else {
+        Some(property)
+    }
+}
+
+fn string_property(value: &OwnedJsValue, name: &str) -> Option<String> {
+    property(value, name)
+        .filter(|property| property.is_string())
+        .and_then(|property| property.to_string().ok())
+}
+
+/// Parse a QuickJS stack trace, made of lines like
+/// `    at f (script.js:3:10)` or `    at parse (native)`.
+fn parse_stack(stack: &str) -> Vec<StackFrame> {
+    stack
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("at "))
+        .map(|frame| {
+            let (function, location) =
+                match frame.strip_suffix(')').and_then(|f| f.rsplit_once(" (")) {
+                    Some((function, location)) => (function, Some(location)),
+                    None => (frame, None),
+                };
+
+            let mut stack_frame = StackFrame {
+                function: function.to_string(),
+                file: None,
+                line: None,
+                column: None,
+            };
+
+            match location {
+                None | Some("native") => {}
+                Some(location) => {
+                    let mut parts = location.rsplitn(3, ':');
+                    let column = parts.next().and_then(|c| c.parse().ok());
+                    let line = parts.next().and_then(|l| l.parse().ok());
+                    match (parts.next(), line, column) {
+                        (Some(file), Some(line), Some(column)) => {
+                            stack_frame.file = Some(file.to_string());
+                            stack_frame.line = Some(line);
+                            stack_frame.column = Some(column);
+                        }
+                        _ => stack_frame.file = Some(location.to_string()),
+                    }
+                }
+            }
+
+            stack_frame
+        })
+        .collect()
+}
@@ -0,0 +1,215 @@
+use std::any::Any;
+use std::{error, fmt};
+
+use libquickjs_ng_sys as q;
+
+use crate::utils::make_cstring;
+use crate::{ExecutionError, OwnedJsValue, ToOwnedJsValue};
+
+/// The built-in Javascript error constructors a [JsThrow] can create.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsErrorKind {
+    Error,
+    TypeError,
+    RangeError,
+    ReferenceError,
+    SyntaxError,
+    InternalError,
+}
+
+type PropertyValue = Box<dyn FnOnce(*mut q::JSContext) -> OwnedJsValue + Send + Sync>;
+
+/// An error to be thrown into Javascript by a Rust callback.
+///
+/// It is materialized as a real Javascript `Error` object of the given kind,
+/// so that `e instanceof TypeError` and `e.stack` work in a `catch` block.
+///
+/// ```rust
+/// use quickjs_rusty::{Context, JsThrow};
+/// let context = Context::builder().build().unwrap();
+///
+/// context
+///     .add_callback("sqrt", |v: f64| {
+///         if v < 0.0 {
+///             Err(JsThrow::range_error("expected a positive number").with_property("value", v))
+///         } else {
+///             Ok(v.sqrt())
+///         }
+///     })
+///     .unwrap();
+///
+/// let caught = context
+///     .eval_as::<String>("try { sqrt(-1) } catch (e) { `${e instanceof RangeError} ${e.value}` }")
+///     .unwrap();
+/// assert_eq!(caught, "true -1");
+/// ```
+///
+/// Callbacks returning another error type throw a plain `Error` with the
+/// error's `Display` output as message.
+pub struct JsThrow {
+    kind: JsErrorKind,
+    name: Option<String>,
+    message: String,
+    properties: Vec<(String, PropertyValue)>,
+}
+
+impl JsThrow {
+    /// Create an error of the given kind.
+    pub fn new(kind: JsErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: None,
+            message: message.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    /// Create an `Error` with a custom `name`, e.g. `ValidationError`.
+    pub fn named(name: impl Into<String>, message: impl Into<String>) -> Self {
+        let mut throw = Self::new(JsErrorKind::Error, message);
+        throw.name = Some(name.into());
+        throw
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(JsErrorKind::Error, message)
+    }
+
+    pub fn type_error(message: impl Into<String>) -> Self {
+        Self::new(JsErrorKind::TypeError, message)
+    }
+
+    pub fn range_error(message: impl Into<String>) -> Self {
+        Self::new(JsErrorKind::RangeError, message)
+    }
+
+    pub fn reference_error(message: impl Into<String>) -> Self {
+        Self::new(JsErrorKind::ReferenceError, message)
+    }
+
+    pub fn syntax_error(message: impl Into<String>) -> Self {
+        Self::new(JsErrorKind::SyntaxError, message)
+    }
+
+    pub fn internal_error(message: impl Into<String>) -> Self {
+        Self::new(JsErrorKind::InternalError, message)
+    }
+
+    /// Set an additional property on the created error object.
+    pub fn with_property<T>(mut self, name: &str, value: T) -> Self
+    where
+        T: ToOwnedJsValue + Send + Sync + 'static,
+    {
+        self.properties.push((
+            name.to_string(),
+            Box::new(move |context| (context, value).into()),
+        ));
+        self
+    }
+
+    pub fn kind(&self) -> JsErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Convert any callback error into a `JsThrow`, keeping it as is if it
+    /// already is one.
+    pub(crate) fn from_error<E: fmt::Display + 'static>(e: E) -> Self {
+        let mut e = Some(e);
+        if let Some(throw) = (&mut e as &mut dyn Any).downcast_mut::<Option<JsThrow>>() {
+            return throw.take().unwrap();
+        }
+        if let Some(e) = (&mut e as &mut dyn Any).downcast_mut::<Option<anyhow::Error>>() {
+            return match e.take().unwrap().downcast::<JsThrow>() {
+                Ok(throw) => throw,
+                Err(e) => Self::error(e.to_string()),
+            };
+        }
+        Self::error(e.unwrap().to_string())
+    }
+
+    /// Convert the payload of a caught panic into an `InternalError`.
+    pub(crate) fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "Callback panicked!".to_string()
+        };
+        Self::internal_error(message)
+    }
+
+    /// Create the Javascript error object, wrapped as an exception.
+    pub(crate) fn into_exception(self, context: *mut q::JSContext) -> ExecutionError {
+        ExecutionError::Exception(self.into_value(context).into())
+    }
+
+    /// Create the Javascript error object.
+    pub(crate) fn into_value(self, context: *mut q::JSContext) -> OwnedJsValue {
+        // Throwing through QuickJS creates the error with the right prototype
+        // and a stack trace; the exception is then taken back out.
+        let format = c"%s".as_ptr();
+        let message = make_cstring(self.message.replace('\0', "")).unwrap();
+        unsafe {
+            match self.kind {
+                JsErrorKind::Error => q::JS_ThrowPlainError(context, format, message.as_ptr()),
+                JsErrorKind::TypeError => q::JS_ThrowTypeError(context, format, message.as_ptr()),
+                JsErrorKind::RangeError => q::JS_ThrowRangeError(context, format, message.as_ptr()),
+                JsErrorKind::ReferenceError => {
+                    q::JS_ThrowReferenceError(context, format, message.as_ptr())
+                }
+                JsErrorKind::SyntaxError => {
+                    q::JS_ThrowSyntaxError(context, format, message.as_ptr())
+                }
+                JsErrorKind::InternalError => {
+                    q::JS_ThrowInternalError(context, format, message.as_ptr())
+                }
+            };
+        }
+        let error = OwnedJsValue::new(context, unsafe { q::JS_GetException(context) });
+
+        let name = self
+            .name
+            .map(|name| ("name".to_string(), OwnedJsValue::from((context, name))));
+        let properties = self
+            .properties
+            .into_iter()
+            .map(|(key, value)| (key, value(context)));
+        if let Ok(object) = error.clone().try_into_object() {
+            for (key, value) in name.into_iter().chain(properties) {
+                let _ = object.set_property(&key, value);
+            }
+        }
+
+        error
+    }
+}
+
+impl fmt::Debug for JsThrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JsThrow")
+            .field("kind", &self.kind)
+            .field("name", &self.name)
+            .field("message", &self.message)
+            .field(
+                "properties",
+                &self.properties.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl fmt::Display for JsThrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}: {}", name, self.message),
+            None => write!(f, "{:?}: {}", self.kind, self.message),
+        }
+    }
+}
+
+impl error::Error for JsThrow {}
@@ -0,0 +1,73 @@
+use std::{error, fmt, io};
+
+use super::{ExecutionError, JsThrow, ValueError};
+
+/// Error returned by a [ModuleLoader](crate::module_loader::ModuleLoader) or
+/// a [ModuleResolver](crate::module_loader::ModuleResolver).
+///
+/// It is thrown into Javascript by the failing `import`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleError {
+    /// The module was not found, thrown as a `ReferenceError`.
+    ///
+    /// A [Chain](crate::module_loader::Chain) tries the next loader or
+    /// resolver on this error.
+    NotFound(String),
+    /// The module name or content is invalid, thrown as a `SyntaxError`.
+    Invalid(String),
+    /// Any other error, thrown as an `InternalError`.
+    Other(String),
+    #[doc(hidden)]
+    __NonExhaustive,
+}
+
+impl ModuleError {
+    /// Create the error for a module that was not found.
+    pub fn not_found(name: &str) -> Self {
+        ModuleError::NotFound(format!("Cannot find module '{}'", name))
+    }
+
+    pub(crate) fn into_throw(self) -> JsThrow {
+        use ModuleError::*;
+        match self {
+            NotFound(e) => JsThrow::reference_error(e),
+            Invalid(e) => JsThrow::syntax_error(e),
+            Other(e) => JsThrow::internal_error(e),
+            __NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ModuleError::*;
+        match self {
+            NotFound(e) | Invalid(e) | Other(e) => f.write_str(e),
+            __NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+impl error::Error for ModuleError {}
+
+impl From<io::Error> for ModuleError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::NotFound {
+            ModuleError::NotFound(e.to_string())
+        } else {
+            ModuleError::Other(e.to_string())
+        }
+    }
+}
+
+impl From<ExecutionError> for ModuleError {
+    fn from(e: ExecutionError) -> Self {
+        ModuleError::Other(e.to_string())
+    }
+}
+
+impl From<ValueError> for ModuleError {
+    fn from(e: ValueError) -> Self {
+        ModuleError::Other(e.to_string())
+    }
+}
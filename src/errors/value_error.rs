@@ -15,6 +15,14 @@ pub enum ValueError {
     BigIntOverflow,
     /// Received an unexpected type that could not be converted.
     UnexpectedType,
+    /// A callback argument could not be converted.
+    InvalidArgument {
+        /// The 0-based index of the argument.
+        index: usize,
+        error: Box<ValueError>,
+    },
+    /// A required callback argument was not passed.
+    MissingArgument(usize),
     #[doc(hidden)]
     __NonExhaustive,
 }
@@ -41,6 +49,10 @@ impl fmt::Display for ValueError {
             Internal(e) => write!(f, "Value conversion failed - internal error: {}", e),
             BigIntOverflow => write!(f, "BigInt overflow"),
             UnexpectedType => write!(f, "Could not convert - received unexpected type"),
+            InvalidArgument { index, error } => {
+                write!(f, "Invalid argument at index {}: {}", index, error)
+            }
+            MissingArgument(index) => write!(f, "Missing argument at index {}", index),
             __NonExhaustive => unreachable!(),
         }
     }
@@ -31,19 +31,28 @@
 // #![deny(missing_docs)]
 
 mod callback;
+mod class;
 pub mod compile;
 pub mod console;
 pub mod context;
+#[doc(hidden)]
+pub mod derive;
 pub mod errors;
 pub mod module_loader;
+pub mod native_module;
 #[cfg(feature = "serde")]
 pub mod serde;
 pub mod utils;
 pub mod value;
 
 pub use libquickjs_ng_sys::{JSContext, JSValue as RawJSValue};
+#[cfg(feature = "derive")]
+pub use quickjs_rusty_derive::{
+    include_modules, js_class, js_function, js_methods, FromJs, IntoJs,
+};
 
 pub use self::callback::*;
+pub use self::class::*;
 pub use self::context::*;
 pub use self::errors::*;
 pub use self::value::*;
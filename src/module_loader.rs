@@ -1,94 +1,634 @@
-use std::ffi::{c_char, c_void, CStr, CString};
+//! Loading of ES modules imported by Javascript code.
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr::null_mut;
+use std::rc::Rc;
+use std::sync::Mutex;
 
-use anyhow::Result;
 use libquickjs_ng_sys as q;
 
-use super::compile::compile_module;
+use super::compile::{compile_module, from_bytecode};
+use crate::context::interrupted_error_value;
+use crate::native_module::NativeModuleRegistry;
+use crate::utils::{get_exception, make_cstring};
+use crate::value::JsModule;
+use crate::{ExecutionError, JsThrow, ModuleError, OwnedJsValue};
+
+pub(crate) mod attributes;
+mod cache;
+pub(crate) mod commonjs;
+mod fs;
+mod import_map;
+mod json;
+mod meta;
+mod virtual_fs;
+
+pub(crate) use attributes::js_module_check_attributes;
+pub use attributes::ImportAttributes;
+pub(crate) use cache::ModuleCache;
+pub use fs::FsModuleLoader;
+pub use import_map::ImportMap;
+pub use meta::ImportMeta;
+pub(crate) use meta::{init_import_meta, ImportMetaHook};
+pub use virtual_fs::{VirtualFile, VirtualModuleFs};
+
+/// Resolves the specifier of an import to the name of the module to load.
+///
+/// See [Context::set_module_resolver](crate::Context::set_module_resolver).
+pub trait ModuleResolver {
+    /// Resolve `specifier`, imported by the module named `base`.
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError>;
+
+    /// Resolve `specifier`, required by the CommonJS module named `base`, see
+    /// [Context::enable_commonjs](crate::Context::enable_commonjs).
+    ///
+    /// Defaults to [resolve](Self::resolve). Resolvers matching the
+    /// conditions of package exports use `"require"` instead of `"import"`.
+    fn resolve_require(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        self.resolve(base, specifier)
+    }
+}
+
+/// Loads a module by the name returned by the [ModuleResolver].
+///
+/// See [Context::set_module_loader](crate::Context::set_module_loader).
+pub trait ModuleLoader {
+    /// Load the module named `name`, imported with `attributes`.
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError>;
+}
+
+impl<T: ModuleResolver + ?Sized> ModuleResolver for Box<T> {
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        (**self).resolve(base, specifier)
+    }
+
+    fn resolve_require(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        (**self).resolve_require(base, specifier)
+    }
+}
+
+impl<T: ModuleLoader + ?Sized> ModuleLoader for Box<T> {
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError> {
+        (**self).load(name, attributes)
+    }
+}
+
+impl<T: ModuleResolver + ?Sized> ModuleResolver for Rc<T> {
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        (**self).resolve(base, specifier)
+    }
+
+    fn resolve_require(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        (**self).resolve_require(base, specifier)
+    }
+}
+
+impl<T: ModuleLoader + ?Sized> ModuleLoader for Rc<T> {
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError> {
+        (**self).load(name, attributes)
+    }
+}
+
+/// A resolver backed by a closure, see [resolver_fn].
+pub struct ResolverFn<F>(F);
+
+/// Create a resolver from a closure, passes (base_module_name, specifier) and
+/// returns the module name.
+pub fn resolver_fn<F>(resolve: F) -> ResolverFn<F>
+where
+    F: Fn(&str, &str) -> Result<String, ModuleError>,
+{
+    ResolverFn(resolve)
+}
+
+impl<F> ModuleResolver for ResolverFn<F>
+where
+    F: Fn(&str, &str) -> Result<String, ModuleError>,
+{
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        (self.0)(base, specifier)
+    }
+}
+
+/// A loader backed by a closure, see [loader_fn].
+pub struct LoaderFn<F>(F);
+
+/// Create a loader from a closure, passes (module_name, import_attributes) and
+/// returns the module.
+///
+/// ```rust
+/// use quickjs_rusty::module_loader::{loader_fn, ModuleSource};
+/// use quickjs_rusty::{Context, ModuleError};
+/// let context = Context::builder().build().unwrap();
+///
+/// context.set_module_loader(loader_fn(|name, _| match name {
+///     "greeting" => Ok(ModuleSource::from("export default 'hello';")),
+///     _ => Err(ModuleError::not_found(name)),
+/// }));
+///
+/// context
+///     .eval_module("import greeting from 'greeting'; globalThis.x = greeting;", false)
+///     .unwrap();
+/// assert_eq!(context.eval_as::<String>("x").unwrap(), "hello");
+///
+/// let err = context.eval_module("import 'missing';", false).unwrap_err();
+/// assert!(err.to_string().starts_with("ReferenceError"));
+/// ```
+pub fn loader_fn<F>(load: F) -> LoaderFn<F>
+where
+    F: Fn(&str, &ImportAttributes) -> Result<ModuleSource, ModuleError>,
+{
+    LoaderFn(load)
+}
+
+impl<F> ModuleLoader for LoaderFn<F>
+where
+    F: Fn(&str, &ImportAttributes) -> Result<ModuleSource, ModuleError>,
+{
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError> {
+        (self.0)(name, attributes)
+    }
+}
+
+/// Tries a loader or resolver, then a second one if the first returns
+/// [ModuleError::NotFound].
+///
+/// ```rust
+/// use quickjs_rusty::module_loader::{
+///     loader_fn, resolver_fn, Chain, ImportMap, ModuleLoader, ModuleSource,
+/// };
+/// use quickjs_rusty::{Context, ModuleError};
+///
+/// fn memory(modules: &'static [(&'static str, &'static str)]) -> impl ModuleLoader {
+///     loader_fn(move |name, _| {
+///         let (_, code) = modules
+///             .iter()
+///             .find(|(n, _)| *n == name)
+///             .ok_or_else(|| ModuleError::not_found(name))?;
+///         Ok(ModuleSource::from(*code))
+///     })
+/// }
+///
+/// // `config` is mapped by the import map, other bare specifiers are not
+/// // found by it and go to the vendor directory.
+/// let import_map =
+///     ImportMap::parse(r#"{ "imports": { "config": "/app/config.js" } }"#, "/app/").unwrap();
+/// let vendor = resolver_fn(|_, specifier| Ok(format!("/vendor/{}.js", specifier)));
+///
+/// let app = memory(&[("/app/config.js", "export const debug = true;")]);
+/// let packages = memory(&[("/vendor/greeting.js", "export default 'hello';")]);
+///
+/// let context = Context::builder().build().unwrap();
+/// context.set_module_resolver(Chain::new(import_map, vendor));
+/// context.set_module_loader(Chain::new(app, packages));
+///
+/// context
+///     .eval_module(
+///         "import { debug } from 'config';
+///          import greeting from 'greeting';
+///          globalThis.debug = debug;
+///          globalThis.greeting = greeting;",
+///         false,
+///     )
+///     .unwrap();
+/// assert!(context.eval_as::<bool>("debug").unwrap());
+/// assert_eq!(context.eval_as::<String>("greeting").unwrap(), "hello");
+/// ```
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chain<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A: ModuleResolver, B: ModuleResolver> ModuleResolver for Chain<A, B> {
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        match self.first.resolve(base, specifier) {
+            Err(ModuleError::NotFound(_)) => self.second.resolve(base, specifier),
+            result => result,
+        }
+    }
+
+    fn resolve_require(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        match self.first.resolve_require(base, specifier) {
+            Err(ModuleError::NotFound(_)) => self.second.resolve_require(base, specifier),
+            result => result,
+        }
+    }
+}
+
+impl<A: ModuleLoader, B: ModuleLoader> ModuleLoader for Chain<A, B> {
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError> {
+        match self.first.load(name, attributes) {
+            Err(ModuleError::NotFound(_)) => self.second.load(name, attributes),
+            result => result,
+        }
+    }
+}
+
+/// A module returned by a module loader.
+///
+/// Bytecode and compiled modules keep the name they were compiled with, which
+/// should be the name the module is imported by.
+///
+/// Modules imported with a `type` attribute, like
+/// `import config from "./config.json" with { type: "json" }`, must be
+/// returned as [Source](Self::Source) or [Bytes](Self::Bytes), the content is
+/// then turned into the default export according to the type, see
+/// [Context::register_module_type](crate::Context::register_module_type).
+///
+/// Modules loaded by `require` are CommonJS modules, they must be returned as
+/// [Source](Self::Source), [CommonJs](Self::CommonJs) or [Bytes](Self::Bytes).
+pub enum ModuleSource {
+    /// Module source code, compiled when loaded.
+    Source(String),
+    /// Module bytecode, written by [compile::module_to_bytecode](crate::compile::module_to_bytecode).
+    Bytecode(Vec<u8>),
+    /// A module compiled in the same context.
+    Module(JsModule),
+    /// Raw module content, UTF-8 source code for Javascript modules.
+    Bytes(Vec<u8>),
+    /// CommonJS module source code. ES modules importing it get
+    /// `module.exports` as default export, see
+    /// [Context::enable_commonjs](crate::Context::enable_commonjs).
+    CommonJs(String),
+}
+
+impl From<String> for ModuleSource {
+    fn from(source: String) -> Self {
+        ModuleSource::Source(source)
+    }
+}
+
+impl From<&str> for ModuleSource {
+    fn from(source: &str) -> Self {
+        ModuleSource::Source(source.to_string())
+    }
+}
+
+impl From<Vec<u8>> for ModuleSource {
+    fn from(bytecode: Vec<u8>) -> Self {
+        ModuleSource::Bytecode(bytecode)
+    }
+}
+
+impl From<JsModule> for ModuleSource {
+    fn from(module: JsModule) -> Self {
+        ModuleSource::Module(module)
+    }
+}
+
+impl ModuleSource {
+    /// Compile or read the module.
+    fn into_module(
+        self,
+        context: *mut q::JSContext,
+        module_name: &str,
+    ) -> Result<OwnedJsValue, ExecutionError> {
+        let value = match self {
+            ModuleSource::Source(code) => compile_module(context, &code, module_name)?,
+            ModuleSource::Bytecode(bytecode) => {
+                if bytecode.is_empty() {
+                    return Err(ExecutionError::Internal(format!(
+                        "Empty bytecode for module {}",
+                        module_name
+                    )));
+                }
+                from_bytecode(context, &bytecode)?
+            }
+            ModuleSource::Module(module) => module.into_value(),
+            ModuleSource::Bytes(bytes) => {
+                let code = String::from_utf8(bytes).map_err(|_| {
+                    ExecutionError::Internal(format!("Module {} is not valid UTF-8", module_name))
+                })?;
+                compile_module(context, &code, module_name)?
+            }
+            ModuleSource::CommonJs(_) => {
+                return Err(ExecutionError::Internal(format!(
+                    "Module {} is a CommonJS module, which is not enabled",
+                    module_name
+                )))
+            }
+        };
+        if !value.is_module() {
+            return Err(ExecutionError::Internal(format!(
+                "Expected a module for {}, got {:?}",
+                module_name,
+                value.tag()
+            )));
+        }
+        Ok(value)
+    }
+
+    /// The content of a module imported with a `type` attribute.
+    fn into_content(self, module_name: &str) -> Result<Vec<u8>, ExecutionError> {
+        match self {
+            ModuleSource::Source(source) | ModuleSource::CommonJs(source) => {
+                Ok(source.into_bytes())
+            }
+            ModuleSource::Bytes(bytes) => Ok(bytes),
+            ModuleSource::Bytecode(_) | ModuleSource::Module(_) => {
+                Err(ExecutionError::Internal(format!(
+                    "Module {} has a type attribute and can not be loaded as bytecode",
+                    module_name
+                )))
+            }
+        }
+    }
 
-/// Custom module loader function, passes (module_name, opaque) and returns module code
-/// If the module code is not found, return None
-pub type JSModuleLoaderFunc = Box<dyn Fn(&str, *mut c_void) -> Result<String>>;
-/// Custom module normalize function, passes (module_base_name, module_name, opaque)
-/// and returns normalized module name (or None if not found)
-pub type JSModuleNormalizeFunc = Box<dyn Fn(&str, &str, *mut c_void) -> Result<String>>;
+    /// Create the module definition, as a native module exporting the
+    /// content as default for modules with a `type` attribute.
+    unsafe fn into_module_def(
+        self,
+        context: *mut q::JSContext,
+        module_name: &str,
+        attributes: &ImportAttributes,
+    ) -> Result<*mut q::JSModuleDef, ExecutionError> {
+        let Some(module_type) = attributes.module_type() else {
+            let module = self.into_module(context, module_name)?;
+            return Ok(q::JS_Ext_GetPtr(module.value) as *mut q::JSModuleDef);
+        };
+        let content = self.into_content(module_name)?;
+        let registry = NativeModuleRegistry::from_context(context).ok_or_else(|| {
+            ExecutionError::Internal("Context has no native module registry".to_string())
+        })?;
+        registry.register_typed(context, module_name, module_type, content)
+    }
+}
 
-pub struct ModuleLoader {
-    pub loader: JSModuleLoaderFunc,
-    pub normalize: Option<JSModuleNormalizeFunc>,
-    pub opaque: *mut c_void,
+/// The module loader, resolver and `import.meta` hook of a runtime, stored as
+/// the module loader opaque.
+#[derive(Default)]
+pub(crate) struct ModuleLoaders {
+    loader: Mutex<Option<Rc<dyn ModuleLoader>>>,
+    resolver: Mutex<Option<Rc<dyn ModuleResolver>>>,
+    import_meta: Mutex<Option<Rc<ImportMetaHook>>>,
 }
 
-pub unsafe extern "C" fn js_module_loader(
+impl ModuleLoaders {
+    pub(crate) fn set_loader(&self, loader: Rc<dyn ModuleLoader>) {
+        *self.loader.lock().unwrap() = Some(loader);
+    }
+
+    pub(crate) fn set_resolver(&self, resolver: Rc<dyn ModuleResolver>) {
+        *self.resolver.lock().unwrap() = Some(resolver);
+    }
+
+    pub(crate) fn set_import_meta_hook(&self, hook: Rc<ImportMetaHook>) {
+        *self.import_meta.lock().unwrap() = Some(hook);
+    }
+
+    pub(crate) fn has_resolver(&self) -> bool {
+        self.resolver.lock().unwrap().is_some()
+    }
+
+    // The loader and resolver are cloned out of the lock, so that they can
+    // import modules themselves.
+
+    fn loader(&self) -> Option<Rc<dyn ModuleLoader>> {
+        self.loader.lock().unwrap().clone()
+    }
+
+    fn resolver(&self) -> Option<Rc<dyn ModuleResolver>> {
+        self.resolver.lock().unwrap().clone()
+    }
+
+    pub(crate) fn import_meta_hook(&self) -> Option<Rc<ImportMetaHook>> {
+        self.import_meta.lock().unwrap().clone()
+    }
+}
+
+pub(crate) unsafe extern "C" fn js_module_loader(
     ctx: *mut q::JSContext,
     module_name: *const c_char,
     opaque: *mut c_void,
+    attributes: q::JSValue,
 ) -> *mut q::JSModuleDef {
-    let wrapper = &*(opaque as *mut ModuleLoader);
-    let opaque = wrapper.opaque;
-    let loader = &wrapper.loader;
-
-    let module_name = CStr::from_ptr(module_name).to_string_lossy().to_string();
-    let module_code = match loader(&module_name, opaque) {
-        Ok(v) => v,
-        Err(err) => {
-            throw_internal_error(ctx, &err.to_string());
-            return null_mut() as *mut q::JSModuleDef;
+    let loaders = &*(opaque as *const ModuleLoaders);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        load_module(ctx, loaders, module_name, &attributes)
+    }));
+    match result {
+        Ok(Ok(module_def)) => module_def,
+        Ok(Err(e)) => {
+            throw(ctx, e);
+            null_mut()
+        }
+        Err(panic) => {
+            throw(ctx, JsThrow::from_panic(panic).into_exception(ctx));
+            null_mut()
         }
+    }
+}
+
+unsafe fn load_module(
+    ctx: *mut q::JSContext,
+    loaders: &ModuleLoaders,
+    module_name: *const c_char,
+    attributes: &q::JSValue,
+) -> Result<*mut q::JSModuleDef, ExecutionError> {
+    let module_name = module_name_str(ctx, module_name)?;
+    let attributes = ImportAttributes::from_raw(ctx, attributes)
+        .map_err(|e| module_error(ctx, ModuleError::Invalid(e.to_string())))?;
+    let loader = loaders
+        .loader()
+        .ok_or_else(|| module_error(ctx, ModuleError::not_found(module_name)))?;
+
+    let module_source = loader
+        .load(module_name, &attributes)
+        .map_err(|e| module_error(ctx, e))?;
+    if attributes.module_type().is_some() {
+        return module_source.into_module_def(ctx, module_name, &attributes);
+    }
+    let registry = NativeModuleRegistry::from_context(ctx).ok_or_else(|| {
+        ExecutionError::Internal("Context has no native module registry".to_string())
+    })?;
+    let module_source = match (module_source, registry.commonjs()) {
+        (ModuleSource::CommonJs(source), Some(state)) => {
+            return commonjs::module_def(ctx, &state, registry, module_name, source);
+        }
+        (module_source, _) => module_source,
     };
+    let module_def = module_source.into_module_def(ctx, module_name, &attributes)?;
+    let hook = loaders.import_meta_hook();
+    init_import_meta(ctx, module_def, module_name, hook.as_deref())?;
+    Ok(module_def)
+}
 
-    match compile_module(ctx, &module_code, &module_name) {
-        Ok(v) => {
-            let module_def = q::JS_Ext_GetPtr(v.value);
-            module_def as *mut q::JSModuleDef
+pub(crate) unsafe extern "C" fn js_module_normalize(
+    ctx: *mut q::JSContext,
+    module_base_name: *const c_char,
+    module_name: *const c_char,
+    opaque: *mut c_void,
+) -> *mut c_char {
+    let loaders = &*(opaque as *const ModuleLoaders);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        normalize_module(ctx, loaders, module_base_name, module_name)
+    }));
+    match result {
+        Ok(Ok(normalized)) => normalized,
+        Ok(Err(e)) => {
+            throw(ctx, e);
+            null_mut()
         }
-        Err(e) => {
-            throw_internal_error(ctx, &e.to_string());
-            null_mut() as *mut q::JSModuleDef
+        Err(panic) => {
+            throw(ctx, JsThrow::from_panic(panic).into_exception(ctx));
+            null_mut()
         }
     }
 }
 
-pub unsafe extern "C" fn js_module_normalize(
+unsafe fn normalize_module(
     ctx: *mut q::JSContext,
+    loaders: &ModuleLoaders,
     module_base_name: *const c_char,
     module_name: *const c_char,
-    opaque: *mut c_void,
-) -> *mut c_char {
-    let wrapper = &*(opaque as *mut ModuleLoader);
-    let opaque = wrapper.opaque;
-    let normalize = &wrapper.normalize;
-
-    let module_base_name = CStr::from_ptr(module_base_name).to_str().unwrap();
-    let module_name = CStr::from_ptr(module_name).to_str().unwrap();
-
-    if let Some(module_normalize_func) = normalize {
-        let mut normalized_module_name =
-            match module_normalize_func(module_base_name, module_name, opaque) {
-                Ok(v) => v,
-                Err(err) => {
-                    throw_internal_error(ctx, &err.to_string());
-                    return null_mut() as *mut c_char;
-                }
-            };
-        normalized_module_name.push('\0');
-        let m = q::js_malloc(ctx, normalized_module_name.len());
-        std::ptr::copy(
-            normalized_module_name.as_ptr(),
-            m as *mut u8,
-            normalized_module_name.len(),
-        );
-        m as *mut c_char
-    } else {
-        log::warn!("module normalize func not set");
-        null_mut() as *mut c_char
+) -> Result<*mut c_char, ExecutionError> {
+    let module_base_name = module_name_str(ctx, module_base_name)?;
+    let module_name = module_name_str(ctx, module_name)?;
+    let resolver = loaders
+        .resolver()
+        .ok_or_else(|| module_error(ctx, ModuleError::not_found(module_name)))?;
+
+    let normalized = resolver
+        .resolve(module_base_name, module_name)
+        .map_err(|e| module_error(ctx, e))?;
+    let normalized = make_cstring(normalized)?;
+
+    // QuickJS frees the name with js_free.
+    let bytes = normalized.as_bytes_with_nul();
+    let m = q::js_malloc(ctx, bytes.len()) as *mut u8;
+    if m.is_null() {
+        return Err(ExecutionError::OutOfMemory);
     }
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), m, bytes.len());
+    Ok(m as *mut c_char)
+}
+
+unsafe fn module_name_str<'a>(
+    ctx: *mut q::JSContext,
+    name: *const c_char,
+) -> Result<&'a str, ExecutionError> {
+    CStr::from_ptr(name).to_str().map_err(|_| {
+        let name = CStr::from_ptr(name).to_string_lossy();
+        module_error(
+            ctx,
+            ModuleError::Invalid(format!("Module name '{}' is not valid UTF-8", name)),
+        )
+    })
 }
 
-#[inline]
-fn throw_internal_error(ctx: *mut q::JSContext, err: &str) {
-    let err = CString::new(err).unwrap();
-    unsafe {
-        q::JS_ThrowInternalError(ctx, err.as_ptr() as *const c_char);
+fn module_error(ctx: *mut q::JSContext, error: ModuleError) -> ExecutionError {
+    error.into_throw().into_exception(ctx)
+}
+
+/// Throw an error, rethrowing Javascript exceptions as is.
+fn throw(ctx: *mut q::JSContext, error: ExecutionError) {
+    let error = match error {
+        ExecutionError::Exception(e) => e.into_value(),
+        ExecutionError::OutOfMemory => {
+            unsafe { q::JS_ThrowOutOfMemory(ctx) };
+            return;
+        }
+        e @ (ExecutionError::Interrupted
+        | ExecutionError::Timeout
+        | ExecutionError::Terminated
+        | ExecutionError::BudgetExceeded) => interrupted_error_value(ctx, &e),
+        e => JsThrow::internal_error(e.to_string()).into_value(ctx),
+    };
+    unsafe { q::JS_Throw(ctx, error.extract()) };
+}
+
+/// Resolve `specifier` imported by the module named `base` like an import
+/// does, with the module resolver if one is set.
+pub(crate) fn normalize_name(
+    ctx: *mut q::JSContext,
+    base: &str,
+    specifier: &str,
+) -> Result<String, ExecutionError> {
+    let base = make_cstring(base)?;
+    let specifier = make_cstring(specifier)?;
+    let resolved = unsafe { q::JS_Ext_NormalizeModuleName(ctx, base.as_ptr(), specifier.as_ptr()) };
+    if resolved.is_null() {
+        return Err(get_exception(ctx)
+            .unwrap_or_else(|| ExecutionError::Internal("Could not resolve module".to_string())));
+    }
+    let name = unsafe { CStr::from_ptr(resolved) }
+        .to_string_lossy()
+        .to_string();
+    unsafe { q::js_free(ctx, resolved as *mut c_void) };
+    Ok(name)
+}
+
+/// Return the result of a native function to Javascript, throwing errors.
+unsafe fn native_return(
+    ctx: *mut q::JSContext,
+    result: std::thread::Result<Result<OwnedJsValue, ExecutionError>>,
+) -> q::JSValue {
+    let error = match result {
+        Ok(Ok(value)) => return value.extract(),
+        Ok(Err(e)) => e,
+        Err(panic) => JsThrow::from_panic(panic).into_exception(ctx),
+    };
+    throw(ctx, error);
+    q::JS_Ext_NewSpecialValue(q::JS_TAG_EXCEPTION, 0)
+}
+
+/// Get the string argument at `index` of a native function.
+unsafe fn string_arg(
+    ctx: *mut q::JSContext,
+    argc: c_int,
+    argv: *mut q::JSValue,
+    index: usize,
+) -> Result<String, ExecutionError> {
+    let arg = (index < argc as usize)
+        .then(|| OwnedJsValue::own(ctx, &*argv.add(index)))
+        .and_then(|arg| arg.to_string().ok());
+    arg.ok_or_else(|| {
+        JsThrow::type_error(format!("Argument {} must be a string", index)).into_exception(ctx)
+    })
+}
+
+/// Whether `specifier` is relative to the importing module.
+fn is_relative(specifier: &str) -> bool {
+    specifier == "."
+        || specifier == ".."
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+}
+
+/// Remove the `.` and `..` segments of a `/` separated path.
+fn normalize_path(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').peekable();
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." | ".." => {
+                if part == ".." && segments.last().is_some_and(|s| *s != "..") {
+                    segments.pop();
+                } else if part == ".." && !absolute {
+                    segments.push("..");
+                }
+                // Keep the trailing slash of `dir/.` and `dir/..`.
+                if last {
+                    segments.push("");
+                }
+            }
+            "" if !last => {}
+            part => segments.push(part),
+        }
+    }
+    let path = segments.join("/");
+    if absolute {
+        format!("/{}", path)
+    } else {
+        path
     }
 }
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void};
+
+use libquickjs_ng_sys as q;
+
+use crate::native_module::NativeModuleRegistry;
+use crate::utils::{get_exception, make_cstring};
+use crate::{ExecutionError, JsThrow, OwnedJsValue, ValueError};
+
+/// The attributes of an import, like `type` in
+/// `import data from "./data.json" with { type: "json" }`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportAttributes(HashMap<String, String>);
+
+impl ImportAttributes {
+    /// Read the attributes object passed by QuickJS, which is `undefined`
+    /// for imports without attributes.
+    pub(crate) fn from_raw(
+        context: *mut q::JSContext,
+        attributes: &q::JSValue,
+    ) -> Result<Self, ValueError> {
+        let attributes = OwnedJsValue::own(context, attributes);
+        if attributes.is_undefined() || attributes.is_null() {
+            Ok(Self::default())
+        } else {
+            Ok(Self(attributes.try_into()?))
+        }
+    }
+
+    /// Get the value of an attribute.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// The `type` attribute, [None] for Javascript modules.
+    pub fn module_type(&self) -> Option<&str> {
+        self.get("type")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for ImportAttributes {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Creates the default export of a module imported with a `type` attribute
+/// from its content, passes (context, module_name, content).
+pub(crate) type ModuleTypeFunc =
+    Box<dyn Fn(*mut q::JSContext, &str, Vec<u8>) -> Result<OwnedJsValue, ExecutionError>>;
+
+/// The module types supported out of the box: `json`, `text` and `bytes`.
+pub(crate) fn builtin_module_types() -> HashMap<String, ModuleTypeFunc> {
+    let mut types: HashMap<String, ModuleTypeFunc> = HashMap::new();
+    types.insert("json".to_string(), Box::new(json_module));
+    types.insert("text".to_string(), Box::new(text_module));
+    types.insert("bytes".to_string(), Box::new(bytes_module));
+    types
+}
+
+fn into_utf8(module_name: &str, content: Vec<u8>) -> Result<String, ExecutionError> {
+    String::from_utf8(content)
+        .map_err(|_| ExecutionError::Internal(format!("Module {} is not valid UTF-8", module_name)))
+}
+
+fn check_value(
+    context: *mut q::JSContext,
+    value: OwnedJsValue,
+) -> Result<OwnedJsValue, ExecutionError> {
+    if value.is_exception() {
+        Err(get_exception(context).unwrap_or_else(|| {
+            ExecutionError::Internal("Could not create module value".to_string())
+        }))
+    } else {
+        Ok(value)
+    }
+}
+
+fn json_module(
+    context: *mut q::JSContext,
+    module_name: &str,
+    content: Vec<u8>,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let json = into_utf8(module_name, content)?;
+    let len = json.len();
+    // JS_ParseJSON needs a zero terminated buffer.
+    let json = make_cstring(json)?;
+    let filename = make_cstring(module_name)?;
+    let value = unsafe { q::JS_ParseJSON(context, json.as_ptr(), len, filename.as_ptr()) };
+    check_value(context, OwnedJsValue::new(context, value))
+}
+
+fn text_module(
+    context: *mut q::JSContext,
+    module_name: &str,
+    content: Vec<u8>,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let text = into_utf8(module_name, content)?;
+    Ok((context, text).into())
+}
+
+fn bytes_module(
+    context: *mut q::JSContext,
+    _module_name: &str,
+    content: Vec<u8>,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let value = unsafe { q::JS_NewUint8ArrayCopy(context, content.as_ptr(), content.len()) };
+    check_value(context, OwnedJsValue::new(context, value))
+}
+
+/// Reject imports with attributes other than a `type` registered in the
+/// context, called by QuickJS when parsing an import.
+pub(crate) unsafe extern "C" fn js_module_check_attributes(
+    ctx: *mut q::JSContext,
+    _opaque: *mut c_void,
+    attributes: q::JSValue,
+) -> c_int {
+    let error = match ImportAttributes::from_raw(ctx, &attributes) {
+        Ok(attributes) => check_attributes(ctx, &attributes),
+        Err(e) => Some(JsThrow::syntax_error(e.to_string())),
+    };
+    match error {
+        Some(error) => {
+            q::JS_Throw(ctx, error.into_value(ctx).extract());
+            -1
+        }
+        None => 0,
+    }
+}
+
+unsafe fn check_attributes(
+    ctx: *mut q::JSContext,
+    attributes: &ImportAttributes,
+) -> Option<JsThrow> {
+    if let Some((key, _)) = attributes.iter().find(|(key, _)| *key != "type") {
+        return Some(JsThrow::syntax_error(format!(
+            "Unsupported import attribute: {}",
+            key
+        )));
+    }
+    let module_type = attributes.module_type()?;
+    let supported = NativeModuleRegistry::from_context(ctx)
+        .is_some_and(|registry| registry.has_module_type(module_type));
+    (!supported).then(|| JsThrow::type_error(format!("Unsupported module type: {}", module_type)))
+}
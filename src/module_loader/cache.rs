@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use libquickjs_ng_sys as q;
+
+use crate::native_module::NativeModuleRegistry;
+use crate::value::module_name;
+use crate::ExecutionError;
+
+/// Tracks the modules invalidated in a context.
+///
+/// QuickJS caches the loaded modules by name, invalidated modules are removed
+/// from that cache so that the next import loads them again. They can still
+/// be referenced by the values they created, and are only freed with the
+/// context.
+#[derive(Default)]
+pub(crate) struct ModuleCache {
+    invalidated: Mutex<Vec<*mut q::JSModuleDef>>,
+}
+
+impl ModuleCache {
+    /// The loaded modules and their names.
+    pub(crate) fn loaded(
+        &self,
+        context: *mut q::JSContext,
+    ) -> Result<Vec<(*mut q::JSModuleDef, String)>, ExecutionError> {
+        let mut modules = Vec::new();
+        let mut def = unsafe { q::JS_Ext_GetNextModule(context, std::ptr::null_mut()) };
+        while !def.is_null() {
+            modules.push((def, module_name(context, def)?));
+            def = unsafe { q::JS_Ext_GetNextModule(context, def) };
+        }
+        Ok(modules)
+    }
+
+    /// Invalidate the modules named `name` and the modules importing them,
+    /// returning the names of the invalidated modules.
+    pub(crate) fn invalidate(
+        &self,
+        context: *mut q::JSContext,
+        registry: &NativeModuleRegistry,
+        name: &str,
+    ) -> Result<Vec<String>, ExecutionError> {
+        let modules = self.loaded(context)?;
+
+        let mut invalidated: HashSet<*mut q::JSModuleDef> = HashSet::new();
+        for (def, _) in modules.iter().filter(|(_, n)| n == name) {
+            if registry.is_native(*def) {
+                return Err(ExecutionError::Internal(format!(
+                    "Native module {} can not be invalidated",
+                    name
+                )));
+            }
+            invalidated.insert(*def);
+        }
+
+        // Add the dependents until there are no more.
+        loop {
+            let dependents = modules
+                .iter()
+                .filter(|(def, _)| !invalidated.contains(def))
+                .filter(|(def, _)| imports(*def).any(|dep| invalidated.contains(&dep)))
+                .map(|(def, _)| *def)
+                .collect::<Vec<_>>();
+            if dependents.is_empty() {
+                break;
+            }
+            invalidated.extend(dependents);
+        }
+
+        let mut names = Vec::new();
+        let mut unlinked = self.invalidated.lock().unwrap();
+        for (def, name) in modules {
+            if invalidated.contains(&def) {
+                registry.forget_loaded(def);
+                unsafe { q::JS_Ext_UnlinkModule(def) };
+                unlinked.push(def);
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Give the invalidated modules back to the context, so that they are
+    /// freed with it.
+    ///
+    /// Must be called before the context is freed.
+    pub(crate) fn relink(&self, context: *mut q::JSContext) {
+        for def in self.invalidated.lock().unwrap().drain(..) {
+            unsafe { q::JS_Ext_RelinkModule(context, def) };
+        }
+    }
+}
+
+/// The modules imported by a module.
+fn imports(def: *mut q::JSModuleDef) -> impl Iterator<Item = *mut q::JSModuleDef> {
+    let count = unsafe { q::JS_Ext_GetModuleRequestCount(def) };
+    (0..count)
+        .map(move |i| unsafe { q::JS_Ext_GetModuleRequest(def, i) })
+        .filter(|dep| !dep.is_null())
+}
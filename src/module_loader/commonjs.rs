@@ -0,0 +1,297 @@
+use std::ffi::c_int;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use libquickjs_ng_sys as q;
+
+use super::{
+    module_error, native_return, normalize_name, string_arg, ImportAttributes, ModuleLoaders,
+    ModuleResolver, ModuleSource,
+};
+use crate::native_module::NativeModuleRegistry;
+use crate::utils::{get_exception, make_cstring};
+use crate::{ExecutionError, ModuleError, OwnedJsObject, OwnedJsValue};
+
+/// Implements `require`, given the native functions resolving, loading and
+/// compiling modules, and parsing JSON, as the `JSON` intrinsic may be left
+/// out.
+///
+/// Modules are cached by name before they are evaluated, so that circular
+/// requires get the exports of the module as they are at that point.
+const REQUIRE: &str = r#"
+(function (resolve, load, compile, parseJson) {
+    const cache = Object.create(null);
+
+    function dirname(name) {
+        const i = name.lastIndexOf('/');
+        return i < 0 ? '.' : i == 0 ? '/' : name.slice(0, i);
+    }
+
+    function createRequire(base) {
+        const require = (specifier) => requireModule(resolve(base, String(specifier)));
+        require.resolve = (specifier) => resolve(base, String(specifier));
+        require.cache = cache;
+        return require;
+    }
+
+    function requireModule(name, source) {
+        const cached = cache[name];
+        if (cached) {
+            return cached.exports;
+        }
+        const module = { id: name, filename: name, exports: {}, loaded: false };
+        module.require = createRequire(name);
+        cache[name] = module;
+        try {
+            if (source === undefined) {
+                source = load(name);
+            }
+            if (name.endsWith('.json')) {
+                module.exports = parseJson(name, source);
+            } else {
+                const wrapper = compile(name, source);
+                wrapper.call(module.exports, module.exports, module.require, module, name, dirname(name));
+            }
+        } catch (e) {
+            delete cache[name];
+            throw e;
+        }
+        module.loaded = true;
+        return module.exports;
+    }
+
+    // The exports of an ES module importing the module: `module.exports` as
+    // default, and its own enumerable properties as named exports.
+    function namespace(name, source) {
+        const exports = requireModule(name, source);
+        const ns = Object.create(null);
+        if ((typeof exports == 'object' && exports !== null) || typeof exports == 'function') {
+            for (const key of Object.keys(exports)) {
+                ns[key] = exports[key];
+            }
+        }
+        ns.default = exports;
+        return ns;
+    }
+
+    function uncache(names) {
+        return names.filter((name) => {
+            const cached = name in cache;
+            delete cache[name];
+            return cached;
+        });
+    }
+
+    return { require: createRequire('.'), namespace, uncache };
+})
+"#;
+
+/// Wraps the code of a CommonJS module in a function.
+const WRAPPER_START: &str = "(function (exports, require, module, __filename, __dirname) { ";
+const WRAPPER_END: &str = "\n})";
+
+/// Create the CommonJS state of a context and install the global `require`.
+pub(crate) fn install(context: *mut q::JSContext) -> Result<OwnedJsObject, ExecutionError> {
+    let factory = eval(context, REQUIRE, "<commonjs>")?;
+    let natives = [
+        native_function(context, js_require_resolve, "resolve", 2)?,
+        native_function(context, js_require_load, "load", 1)?,
+        native_function(context, js_require_compile, "compile", 2)?,
+        native_function(context, js_require_parse_json, "parseJson", 2)?,
+    ];
+    let state = call(context, &factory, natives.to_vec())?.try_into_object()?;
+
+    let global = OwnedJsValue::new(context, unsafe { q::JS_GetGlobalObject(context) });
+    global
+        .try_into_object()?
+        .set_property("require", state.property_require("require")?)?;
+    Ok(state)
+}
+
+/// Evaluate a CommonJS module imported by an ES module, and create the module
+/// definition exporting `module.exports`.
+pub(crate) fn module_def(
+    context: *mut q::JSContext,
+    state: &OwnedJsObject,
+    registry: &NativeModuleRegistry,
+    name: &str,
+    source: String,
+) -> Result<*mut q::JSModuleDef, ExecutionError> {
+    let namespace = state.property_require("namespace")?;
+    let args = vec![(context, name).into(), (context, source).into()];
+    let namespace = call(context, &namespace, args)?.try_into_object()?;
+
+    let mut exports = Vec::new();
+    for key in namespace.properties_iter()?.step_by(2) {
+        let key = key?.to_string()?;
+        let value = namespace.property_require(&key)?;
+        exports.push((key, value));
+    }
+    registry.register_commonjs(context, name, exports)
+}
+
+/// Remove modules from the `require` cache, returning the names of the ones
+/// that were cached.
+pub(crate) fn uncache(
+    context: *mut q::JSContext,
+    state: &OwnedJsObject,
+    names: &[String],
+) -> Result<Vec<String>, ExecutionError> {
+    let uncache = state.property_require("uncache")?;
+    let names = call(context, &uncache, vec![(context, names.to_vec()).into()])?;
+    Ok(names.try_into()?)
+}
+
+/// `parseJson(name, source)`, parses the source of a `.json` module.
+unsafe extern "C" fn js_require_parse_json(
+    ctx: *mut q::JSContext,
+    _this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let name = string_arg(ctx, argc, argv, 0)?;
+        let source = string_arg(ctx, argc, argv, 1)?;
+        let len = source.len();
+        // JS_ParseJSON needs a zero terminated buffer.
+        let source = make_cstring(source)?;
+        let name = make_cstring(name)?;
+        let value = q::JS_ParseJSON(ctx, source.as_ptr(), len, name.as_ptr());
+        check(ctx, OwnedJsValue::new(ctx, value))
+    }));
+    native_return(ctx, result)
+}
+
+fn eval(
+    context: *mut q::JSContext,
+    code: &str,
+    name: &str,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let code_c = make_cstring(code)?;
+    let name_c = make_cstring(name)?;
+    let value = unsafe {
+        q::JS_Eval(
+            context,
+            code_c.as_ptr(),
+            code.len(),
+            name_c.as_ptr(),
+            q::JS_EVAL_TYPE_GLOBAL as i32,
+        )
+    };
+    check(context, OwnedJsValue::new(context, value))
+}
+
+fn call(
+    context: *mut q::JSContext,
+    func: &OwnedJsValue,
+    args: Vec<OwnedJsValue>,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let value = func.clone().try_into_function()?.call(args)?;
+    check(context, value)
+}
+
+fn check(context: *mut q::JSContext, value: OwnedJsValue) -> Result<OwnedJsValue, ExecutionError> {
+    if value.is_exception() {
+        Err(get_exception(context).unwrap_or_else(|| {
+            ExecutionError::Internal("Unknown exception in CommonJS module".to_string())
+        }))
+    } else {
+        Ok(value)
+    }
+}
+
+type NativeFunction =
+    unsafe extern "C" fn(*mut q::JSContext, q::JSValue, c_int, *mut q::JSValue) -> q::JSValue;
+
+fn native_function(
+    context: *mut q::JSContext,
+    func: NativeFunction,
+    name: &str,
+    length: c_int,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let name = make_cstring(name)?;
+    let value = unsafe {
+        q::JS_NewCFunction2(
+            context,
+            Some(func),
+            name.as_ptr(),
+            length,
+            q::JSCFunctionEnum_JS_CFUNC_generic,
+            0,
+        )
+    };
+    check(context, OwnedJsValue::new(context, value))
+}
+
+/// `resolve(base, specifier)`, resolves a `require` of the module `base`
+/// with [ModuleResolver::resolve_require], or like an import without module
+/// resolver.
+unsafe extern "C" fn js_require_resolve(
+    ctx: *mut q::JSContext,
+    _this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let base = string_arg(ctx, argc, argv, 0)?;
+        let specifier = string_arg(ctx, argc, argv, 1)?;
+        let loaders = (q::JS_Ext_GetModuleLoaderOpaque(ctx) as *const ModuleLoaders).as_ref();
+        let name = match loaders.and_then(|loaders| loaders.resolver()) {
+            Some(resolver) => resolver
+                .resolve_require(&base, &specifier)
+                .map_err(|e| module_error(ctx, e))?,
+            None => normalize_name(ctx, &base, &specifier)?,
+        };
+        Ok((ctx, name).into())
+    }));
+    native_return(ctx, result)
+}
+
+/// `load(name)`, loads the source of a module with the module loader.
+unsafe extern "C" fn js_require_load(
+    ctx: *mut q::JSContext,
+    _this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let name = string_arg(ctx, argc, argv, 0)?;
+        let source = load(ctx, &name).map_err(|e| module_error(ctx, e))?;
+        Ok((ctx, source).into())
+    }));
+    native_return(ctx, result)
+}
+
+unsafe fn load(ctx: *mut q::JSContext, name: &str) -> Result<String, ModuleError> {
+    let loaders = (q::JS_Ext_GetModuleLoaderOpaque(ctx) as *const ModuleLoaders).as_ref();
+    let loader = loaders
+        .and_then(|loaders| loaders.loader())
+        .ok_or_else(|| ModuleError::not_found(name))?;
+
+    match loader.load(name, &ImportAttributes::default())? {
+        ModuleSource::Source(source) | ModuleSource::CommonJs(source) => Ok(source),
+        ModuleSource::Bytes(bytes) => String::from_utf8(bytes)
+            .map_err(|_| ModuleError::Invalid(format!("Module '{}' is not valid UTF-8", name))),
+        ModuleSource::Bytecode(_) | ModuleSource::Module(_) => Err(ModuleError::Invalid(format!(
+            "Cannot require ES module '{}'",
+            name
+        ))),
+    }
+}
+
+/// `compile(name, source)`, compiles the source of a module to its wrapper
+/// function.
+unsafe extern "C" fn js_require_compile(
+    ctx: *mut q::JSContext,
+    _this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let name = string_arg(ctx, argc, argv, 0)?;
+        let source = string_arg(ctx, argc, argv, 1)?;
+        // The wrapper starts on the first line, to keep the line numbers.
+        let code = format!("{}{}{}", WRAPPER_START, source, WRAPPER_END);
+        eval(ctx, &code, &name)
+    }));
+    native_return(ctx, result)
+}
@@ -0,0 +1,396 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::json::Json;
+use super::{is_relative, ImportAttributes, ModuleLoader, ModuleResolver, ModuleSource};
+use crate::ModuleError;
+
+/// A module loader reading modules from the filesystem, resolving specifiers
+/// like Node.js does for ES modules.
+///
+/// * relative specifiers (`./util`, `../lib/index.js`) are resolved against
+///   the directory of the importing module
+/// * absolute specifiers are used as is
+/// * bare specifiers (`lodash`, `@scope/pkg/sub`) are looked up in the
+///   `node_modules` directories of the importing module and its ancestors,
+///   honouring the `"exports"` and `"main"` fields of `package.json`
+///
+/// The conditions of `"exports"` are matched in the order of their keys:
+/// `"import"` and `"default"` for imports, `"require"` and `"default"` for
+/// `require`.
+///
+/// A path is tried as is, then with each of the [extensions](Self::extensions),
+/// then as a directory containing an `index` file.
+///
+/// Modules are named by their canonical path. Modules evaluated without a
+/// path, like the ones passed to [Context::eval_module](crate::Context::eval_module),
+/// resolve their imports against the base directory.
+///
+/// Bare specifiers that are not found are passed on unchanged, so that native
+/// modules registered with
+/// [Context::register_native_module](crate::Context::register_native_module)
+/// can still be imported.
+///
+/// Modules imported with a `type` attribute are read as bytes. `.cjs` files,
+/// and `.js` files of packages with `"type": "commonjs"`, are loaded as
+/// CommonJS modules, see [Context::enable_commonjs](crate::Context::enable_commonjs).
+///
+/// ```no_run
+/// use quickjs_rusty::module_loader::FsModuleLoader;
+/// use quickjs_rusty::Context;
+/// let context = Context::builder().build().unwrap();
+///
+/// context.set_fs_module_loader(FsModuleLoader::new("./scripts").jailed());
+/// context.run_module("./main.js").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FsModuleLoader {
+    base: PathBuf,
+    root: Option<PathBuf>,
+    extensions: Vec<String>,
+}
+
+impl FsModuleLoader {
+    /// Create a loader resolving modules without a path against `base`.
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            root: None,
+            extensions: vec!["js".to_string(), "mjs".to_string()],
+        }
+    }
+
+    /// Only allow loading modules inside of `root`.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Only allow loading modules inside of the base directory.
+    pub fn jailed(self) -> Self {
+        let base = self.base.clone();
+        self.root(base)
+    }
+
+    /// Set the extensions tried when a path does not exist, `js` and `mjs`
+    /// by default.
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn resolve_specifier(
+        &self,
+        base_name: &str,
+        specifier: &str,
+        conditions: &[&str],
+    ) -> Result<String, ModuleError> {
+        let base_path = Path::new(base_name);
+        let dir = if base_path.is_absolute() {
+            base_path.parent().unwrap_or(base_path).to_path_buf()
+        } else {
+            self.base.clone()
+        };
+
+        let path = if is_relative(specifier) {
+            self.resolve_path(&dir.join(specifier))
+        } else if Path::new(specifier).is_absolute() {
+            self.resolve_path(Path::new(specifier))
+        } else {
+            match self.resolve_package(&dir, specifier, conditions)? {
+                Some(path) => Some(path),
+                // Leave it to a native module, or to fail when loading.
+                None => return Ok(specifier.to_string()),
+            }
+        };
+        let path = path.ok_or_else(|| {
+            ModuleError::NotFound(format!(
+                "Cannot find module '{}' imported from '{}'",
+                specifier, base_name
+            ))
+        })?;
+
+        let path = self.check_root(&path)?;
+        path.to_str().map(str::to_string).ok_or_else(|| {
+            ModuleError::Invalid(format!("Module path {} is not valid UTF-8", path.display()))
+        })
+    }
+
+    /// Read the content of the module named `name`.
+    fn read(&self, name: &str) -> Result<Vec<u8>, ModuleError> {
+        let path = Path::new(name);
+        if !path.is_absolute() {
+            return Err(ModuleError::not_found(name));
+        }
+        let path = self.check_root(path)?;
+        fs::read(&path)
+            .map_err(|e| ModuleError::Other(format!("Cannot read module '{}': {}", name, e)))
+    }
+
+    /// Canonicalize `path`, and make sure it is inside of the root.
+    fn check_root(&self, path: &Path) -> Result<PathBuf, ModuleError> {
+        let path = path
+            .canonicalize()
+            .map_err(|_| ModuleError::not_found(&path.display().to_string()))?;
+        if let Some(root) = &self.root {
+            let root = root.canonicalize().map_err(|_| {
+                ModuleError::Other(format!("Cannot find module root '{}'", root.display()))
+            })?;
+            if !path.starts_with(&root) {
+                return Err(ModuleError::Other(format!(
+                    "Module '{}' is outside of the module root '{}'",
+                    path.display(),
+                    root.display()
+                )));
+            }
+        }
+        Ok(path)
+    }
+
+    /// Try `path` as a file, with each extension, then as a directory.
+    fn resolve_path(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        if let Some(path) = self.with_extension(path) {
+            return Some(path);
+        }
+        if path.is_dir() {
+            if let Some(main) = read_package_json(path).and_then(|p| main_entry(&p)) {
+                if let Some(path) = self.resolve_file(&path.join(main)) {
+                    return Some(path);
+                }
+            }
+            return self.with_extension(&path.join("index"));
+        }
+        None
+    }
+
+    /// Try `path` as a file, then with each extension.
+    fn resolve_file(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            Some(path.to_path_buf())
+        } else {
+            self.with_extension(path)
+        }
+    }
+
+    fn with_extension(&self, path: &Path) -> Option<PathBuf> {
+        self.extensions.iter().find_map(|ext| {
+            let mut file = path.as_os_str().to_owned();
+            file.push(".");
+            file.push(ext);
+            let file = PathBuf::from(file);
+            file.is_file().then_some(file)
+        })
+    }
+
+    /// Whether the file is a CommonJS module: a `.cjs` file, or a `.js` file
+    /// whose closest `package.json` has `"type": "commonjs"`.
+    fn is_commonjs(&self, path: &Path) -> bool {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("cjs") => true,
+            Some("js") => self.package_type(path).as_deref() == Some("commonjs"),
+            _ => false,
+        }
+    }
+
+    /// The `"type"` field of the closest `package.json`, up to the root.
+    fn package_type(&self, path: &Path) -> Option<String> {
+        let root = self.root.as_ref().and_then(|root| root.canonicalize().ok());
+        for dir in path.ancestors().skip(1) {
+            if let Some(package) = read_package_json(dir) {
+                return package
+                    .get("type")
+                    .and_then(Json::as_str)
+                    .map(str::to_string);
+            }
+            if root.as_ref().is_some_and(|root| dir == root) {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Look up a bare specifier in the `node_modules` directories of `dir`
+    /// and its ancestors, up to the root.
+    fn resolve_package(
+        &self,
+        dir: &Path,
+        specifier: &str,
+        conditions: &[&str],
+    ) -> Result<Option<PathBuf>, ModuleError> {
+        let (name, subpath) = split_package_specifier(specifier);
+        let subpath = subpath.as_str();
+        let root = self.root.as_ref().and_then(|root| root.canonicalize().ok());
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+        for dir in dir.ancestors() {
+            let package_dir = dir.join("node_modules").join(name);
+            if package_dir.is_dir() {
+                return self.resolve_package_entry(&package_dir, specifier, subpath, conditions);
+            }
+            if root.as_ref().is_some_and(|root| dir == root) {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    fn resolve_package_entry(
+        &self,
+        package_dir: &Path,
+        specifier: &str,
+        subpath: &str,
+        conditions: &[&str],
+    ) -> Result<Option<PathBuf>, ModuleError> {
+        let package = read_package_json(package_dir);
+
+        if let Some(exports) = package.as_ref().and_then(|p| p.get("exports")) {
+            let target = resolve_exports(exports, subpath, conditions).ok_or_else(|| {
+                ModuleError::NotFound(format!(
+                    "Package subpath '{}' is not exported by '{}'",
+                    subpath, specifier
+                ))
+            })?;
+            let path = package_dir.join(target);
+            if !path.is_file() {
+                return Err(ModuleError::not_found(&path.display().to_string()));
+            }
+            return Ok(Some(path));
+        }
+
+        if subpath == "." {
+            if let Some(main) = package.as_ref().and_then(main_entry) {
+                if let Some(path) = self.resolve_file(&package_dir.join(main)) {
+                    return Ok(Some(path));
+                }
+            }
+            return Ok(self.with_extension(&package_dir.join("index")));
+        }
+        Ok(self.resolve_path(&package_dir.join(subpath)))
+    }
+}
+
+impl ModuleResolver for FsModuleLoader {
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        self.resolve_specifier(base, specifier, IMPORT_CONDITIONS)
+    }
+
+    fn resolve_require(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        self.resolve_specifier(base, specifier, REQUIRE_CONDITIONS)
+    }
+}
+
+impl ModuleLoader for FsModuleLoader {
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError> {
+        let content = self.read(name)?;
+        if attributes.module_type().is_some() {
+            return Ok(ModuleSource::Bytes(content));
+        }
+        let source = String::from_utf8(content)
+            .map_err(|_| ModuleError::Invalid(format!("Module '{}' is not valid UTF-8", name)))?;
+        if self.is_commonjs(Path::new(name)) {
+            Ok(ModuleSource::CommonJs(source))
+        } else {
+            Ok(ModuleSource::Source(source))
+        }
+    }
+}
+
+/// Split `@scope/pkg/sub/path` into `@scope/pkg` and `./sub/path`.
+fn split_package_specifier(specifier: &str) -> (&str, String) {
+    let separators = if specifier.starts_with('@') { 2 } else { 1 };
+    match specifier.match_indices('/').nth(separators - 1) {
+        Some((i, _)) => (&specifier[..i], format!(".{}", &specifier[i..])),
+        None => (specifier, ".".to_string()),
+    }
+}
+
+fn read_package_json(dir: &Path) -> Option<Json> {
+    let content = fs::read_to_string(dir.join("package.json")).ok()?;
+    Json::parse(&content).ok()
+}
+
+fn main_entry(package: &Json) -> Option<String> {
+    package
+        .get("main")
+        .and_then(Json::as_str)
+        .map(str::to_string)
+}
+
+/// The conditions matched in the `"exports"` field of `package.json` by
+/// imports.
+const IMPORT_CONDITIONS: &[&str] = &["import", "default"];
+
+/// The conditions matched in the `"exports"` field of `package.json` by
+/// `require`.
+const REQUIRE_CONDITIONS: &[&str] = &["require", "default"];
+
+/// Resolve `subpath` (`.` or `./sub/path`) in the `"exports"` field of
+/// `package.json`, returning the target path relative to the package.
+fn resolve_exports(exports: &Json, subpath: &str, conditions: &[&str]) -> Option<String> {
+    let is_subpath_map = exports
+        .as_object()
+        .is_some_and(|map| map.iter().any(|(key, _)| key.starts_with('.')));
+
+    if !is_subpath_map {
+        return if subpath == "." {
+            resolve_target(exports, None, conditions)
+        } else {
+            None
+        };
+    }
+
+    let map = exports.as_object()?;
+    if let Some(target) = exports.get(subpath) {
+        return resolve_target(target, None, conditions);
+    }
+
+    // Subpath patterns like `"./features/*": "./src/features/*.js"`, the
+    // one with the longest prefix wins.
+    let (matched, target) = map
+        .iter()
+        .filter_map(|(key, target)| {
+            let (prefix, suffix) = key.split_once('*')?;
+            let matched = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some((prefix.len(), matched, target))
+        })
+        .max_by_key(|(prefix_len, _, _)| *prefix_len)
+        .map(|(_, matched, target)| (matched, target))?;
+    resolve_target(target, Some(matched), conditions)
+}
+
+/// Resolve an export target, which is a path, an array of targets or an
+/// object of conditions, the first key that is one of `conditions` winning.
+fn resolve_target(
+    target: &Json,
+    pattern_match: Option<&str>,
+    conditions: &[&str],
+) -> Option<String> {
+    match target {
+        Json::String(path) => {
+            let path = match pattern_match {
+                Some(matched) => path.replace('*', matched),
+                None => path.clone(),
+            };
+            // Targets must stay inside of the package.
+            let escapes = Path::new(&path)
+                .components()
+                .any(|c| matches!(c, Component::ParentDir | Component::RootDir));
+            (!escapes).then_some(path)
+        }
+        Json::Array(targets) => targets
+            .iter()
+            .find_map(|target| resolve_target(target, pattern_match, conditions)),
+        Json::Object(entries) => entries
+            .iter()
+            .filter(|(key, _)| conditions.contains(&key.as_str()))
+            .find_map(|(_, target)| resolve_target(target, pattern_match, conditions)),
+        _ => None,
+    }
+}
@@ -0,0 +1,313 @@
+use std::rc::Rc;
+
+use super::json::Json;
+use super::{normalize_path, ModuleResolver};
+use crate::ModuleError;
+
+/// Specifier keys and their targets, sorted by descending key so that the
+/// longest prefix matches first. `None` targets block the specifier.
+type SpecifierMap = Vec<(String, Option<String>)>;
+
+/// A resolver remapping specifiers with an
+/// [import map](https://html.spec.whatwg.org/multipage/webappapis.html#import-maps).
+///
+/// `"imports"` map specifiers to module names, a key ending with `/` maps all
+/// the specifiers it prefixes. `"scopes"` hold mappings only applying to the
+/// modules whose name starts with the scope. Keys and targets that are
+/// relative paths are resolved against the base of the map.
+///
+/// Module names play the role of URLs: a name starting with `/`, `./` or
+/// `../`, or with a scheme like `https:`, is URL-like, other names are bare
+/// specifiers.
+///
+/// Specifiers that are not mapped are passed to the
+/// [fallback](Self::fallback) resolver. Without one, URL-like specifiers are
+/// resolved against the importing module and bare specifiers return
+/// [ModuleError::NotFound], so that the import map can be the first resolver
+/// of a [Chain](super::Chain).
+///
+/// ```rust
+/// use quickjs_rusty::module_loader::{ImportMap, ModuleResolver};
+///
+/// let map = ImportMap::parse(
+///     r#"{
+///         "imports": {
+///             "lodash": "/vendor/lodash/index.js",
+///             "@app/utils/": "./src/utils/"
+///         },
+///         "scopes": {
+///             "/legacy/": { "lodash": "/vendor/lodash-v3/index.js" }
+///         }
+///     }"#,
+///     "/app/",
+/// )
+/// .unwrap();
+///
+/// assert_eq!(map.resolve("/app/main.js", "lodash").unwrap(), "/vendor/lodash/index.js");
+/// assert_eq!(
+///     map.resolve("/app/main.js", "@app/utils/date.js").unwrap(),
+///     "/app/src/utils/date.js"
+/// );
+/// assert_eq!(
+///     map.resolve("/legacy/main.js", "lodash").unwrap(),
+///     "/vendor/lodash-v3/index.js"
+/// );
+/// assert_eq!(map.resolve("/app/main.js", "./lib.js").unwrap(), "/app/lib.js");
+/// assert!(map.resolve("/app/main.js", "react").is_err());
+/// ```
+#[derive(Clone, Default)]
+pub struct ImportMap {
+    imports: SpecifierMap,
+    scopes: Vec<(String, SpecifierMap)>,
+    fallback: Option<Rc<dyn ModuleResolver>>,
+}
+
+impl ImportMap {
+    /// Parse an import map in the JSON format, `base` is the name relative
+    /// keys and targets are resolved against, like the URL of the map.
+    ///
+    /// Invalid entries are ignored with a warning, as browsers do.
+    pub fn parse(json: &str, base: &str) -> Result<Self, ModuleError> {
+        let value = Json::parse(json)
+            .map_err(|e| ModuleError::Invalid(format!("Invalid import map: {}", e)))?;
+        let map = value
+            .as_object()
+            .ok_or_else(|| ModuleError::Invalid("The import map must be an object".to_string()))?;
+
+        let imports = match value.get("imports") {
+            Some(Json::Object(imports)) => parse_specifier_map(imports, base),
+            Some(_) => {
+                return Err(ModuleError::Invalid(
+                    "The \"imports\" of the import map must be an object".to_string(),
+                ))
+            }
+            None => Vec::new(),
+        };
+
+        let mut scopes = match value.get("scopes") {
+            Some(Json::Object(scopes)) => scopes
+                .iter()
+                .map(|(prefix, imports)| {
+                    let imports = imports.as_object().ok_or_else(|| {
+                        ModuleError::Invalid(format!(
+                            "The scope \"{}\" of the import map must be an object",
+                            prefix
+                        ))
+                    })?;
+                    let prefix = resolve_url(prefix, base);
+                    Ok((prefix, parse_specifier_map(imports, base)))
+                })
+                .collect::<Result<Vec<_>, ModuleError>>()?,
+            Some(_) => {
+                return Err(ModuleError::Invalid(
+                    "The \"scopes\" of the import map must be an object".to_string(),
+                ))
+            }
+            None => Vec::new(),
+        };
+        scopes.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        for (key, _) in map
+            .iter()
+            .filter(|(key, _)| key != "imports" && key != "scopes")
+        {
+            log::warn!("Invalid top-level key \"{}\" in the import map", key);
+        }
+
+        Ok(Self {
+            imports,
+            scopes,
+            fallback: None,
+        })
+    }
+
+    /// Resolve the specifiers that are not mapped with `resolver`.
+    pub fn fallback(mut self, resolver: impl ModuleResolver + 'static) -> Self {
+        self.fallback = Some(Rc::new(resolver));
+        self
+    }
+
+    /// Resolve `specifier` with the mappings only, returning `None` if none
+    /// applies.
+    pub fn resolve_mapped(
+        &self,
+        base: &str,
+        specifier: &str,
+    ) -> Result<Option<String>, ModuleError> {
+        let as_url = is_url_like(specifier).then(|| resolve_url(specifier, base));
+        let normalized = as_url.as_deref().unwrap_or(specifier);
+
+        let scopes = self
+            .scopes
+            .iter()
+            .filter(|(prefix, _)| {
+                prefix == base || (prefix.ends_with('/') && base.starts_with(prefix.as_str()))
+            })
+            .map(|(_, imports)| imports);
+        for imports in scopes.chain(std::iter::once(&self.imports)) {
+            if let Some(resolved) = resolve_imports_match(normalized, specifier, imports)? {
+                return Ok(Some(resolved));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl ModuleResolver for ImportMap {
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        if let Some(resolved) = self.resolve_mapped(base, specifier)? {
+            return Ok(resolved);
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.resolve(base, specifier),
+            None => unmapped(base, specifier),
+        }
+    }
+
+    fn resolve_require(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        if let Some(resolved) = self.resolve_mapped(base, specifier)? {
+            return Ok(resolved);
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.resolve_require(base, specifier),
+            None => unmapped(base, specifier),
+        }
+    }
+}
+
+/// Resolve a specifier mapped by no entry of an import map without fallback.
+fn unmapped(base: &str, specifier: &str) -> Result<String, ModuleError> {
+    if is_url_like(specifier) {
+        Ok(resolve_url(specifier, base))
+    } else {
+        Err(ModuleError::NotFound(format!(
+            "Bare specifier '{}' is not mapped by the import map",
+            specifier
+        )))
+    }
+}
+
+fn parse_specifier_map(map: &[(String, Json)], base: &str) -> SpecifierMap {
+    let mut imports = map
+        .iter()
+        .filter_map(|(key, target)| {
+            if key.is_empty() {
+                log::warn!("Invalid empty specifier key in the import map");
+                return None;
+            }
+            let key = if is_url_like(key) {
+                resolve_url(key, base)
+            } else {
+                key.clone()
+            };
+            let target = match target {
+                Json::String(target) if is_url_like(target) => Some(resolve_url(target, base)),
+                Json::Null => None,
+                _ => {
+                    log::warn!("Invalid target {} for \"{}\" in the import map", target, key);
+                    None
+                }
+            };
+            let target = target.filter(|target| {
+                let valid = !key.ends_with('/') || target.ends_with('/');
+                if !valid {
+                    log::warn!(
+                        "Invalid target \"{}\" for the prefix \"{}\" in the import map, it must end with /",
+                        target,
+                        key
+                    );
+                }
+                valid
+            });
+            Some((key, target))
+        })
+        .collect::<Vec<_>>();
+    imports.sort_by(|(a, _), (b, _)| b.cmp(a));
+    imports
+}
+
+fn resolve_imports_match(
+    normalized: &str,
+    specifier: &str,
+    imports: &SpecifierMap,
+) -> Result<Option<String>, ModuleError> {
+    for (key, target) in imports {
+        if key == normalized {
+            return target.clone().map(Some).ok_or_else(|| blocked(specifier));
+        }
+        if !key.ends_with('/') || !normalized.starts_with(key.as_str()) {
+            continue;
+        }
+        let target = target.as_deref().ok_or_else(|| blocked(specifier))?;
+        let resolved = resolve_url(&format!("./{}", &normalized[key.len()..]), target);
+        // The rest of the specifier must not escape the target with `..`.
+        if !resolved.starts_with(target) {
+            return Err(ModuleError::Invalid(format!(
+                "Specifier '{}' backtracks above its prefix '{}' in the import map",
+                specifier, key
+            )));
+        }
+        return Ok(Some(resolved));
+    }
+    Ok(None)
+}
+
+fn blocked(specifier: &str) -> ModuleError {
+    ModuleError::Invalid(format!(
+        "Import of '{}' is blocked by the import map",
+        specifier
+    ))
+}
+
+fn is_url_like(specifier: &str) -> bool {
+    specifier.starts_with('/')
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || scheme_len(specifier).is_some()
+}
+
+/// The length of the `scheme:` prefix of a name, if it has one.
+fn scheme_len(name: &str) -> Option<usize> {
+    let end = name.find(':')?;
+    let scheme = &name[..end];
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(end + 1)
+}
+
+/// Resolve a URL-like name against `base`, removing the `.` and `..`
+/// segments.
+fn resolve_url(name: &str, base: &str) -> String {
+    if scheme_len(name).is_some() {
+        let (origin, path) = split_origin(name);
+        return format!("{}{}", origin, normalize_path(path));
+    }
+    let (origin, base_path) = split_origin(base);
+    let path = if name.starts_with('/') {
+        name.to_string()
+    } else {
+        // Relative to the directory of the base.
+        let dir = base_path.rfind('/').map_or("", |i| &base_path[..=i]);
+        format!("{}{}", dir, name)
+    };
+    format!("{}{}", origin, normalize_path(&path))
+}
+
+/// Split `scheme://host/path` into `scheme://host` and `/path`, and
+/// `scheme:path` into `scheme:` and `path`.
+fn split_origin(name: &str) -> (&str, &str) {
+    let Some(scheme_len) = scheme_len(name) else {
+        return ("", name);
+    };
+    let rest = &name[scheme_len..];
+    match rest.strip_prefix("//") {
+        Some(authority) => {
+            let end = scheme_len + 2 + authority.find('/').unwrap_or(authority.len());
+            name.split_at(end)
+        }
+        None => name.split_at(scheme_len),
+    }
+}
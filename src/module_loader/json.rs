@@ -0,0 +1,379 @@
+use std::fmt;
+
+/// A JSON value of `package.json` or of an import map, keeping the order of
+/// the keys of objects, which decides the condition matched in `"exports"`.
+#[derive(Debug)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Duplicate keys keep the position of the first one and the value of
+    /// the last one, like with `JSON.parse`.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parse a JSON document, the error describes the first syntax error.
+    pub(crate) fn parse(json: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            input: json.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.input.len() {
+            return Err(parser.error("Unexpected trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        self.as_object()?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Nesting deeper than this is rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at position {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", byte as char)))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Too deeply nested"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.parse_object(depth),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'"') => self.parse_string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b't') => self.parse_literal("true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Json::Null),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        if self.input[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("Unexpected character"))
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut entries: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.parse_value(depth + 1)?;
+            match entries.iter_mut().find(|(k, _)| *k == key) {
+                Some(entry) => entry.1 = value,
+                None => entries.push((key, value)),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    // The input is a str and escapes push whole characters.
+                    return Ok(String::from_utf8(bytes).expect("valid UTF-8"));
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = self.parse_escape()?;
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(byte) if byte < 0x20 => {
+                    return Err(self.error("Control character in string"));
+                }
+                Some(byte) => {
+                    bytes.push(byte);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let byte = self
+            .peek()
+            .ok_or_else(|| self.error("Unterminated string"))?;
+        self.pos += 1;
+        let c = match byte {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let unit = self.parse_hex4()?;
+                let code = if (0xD800..0xDC00).contains(&unit)
+                    && self.input[self.pos..].starts_with(b"\\u")
+                {
+                    // A surrogate pair, lone surrogates are replaced below.
+                    let start = self.pos;
+                    self.pos += 2;
+                    let low = self.parse_hex4()?;
+                    if (0xDC00..0xE000).contains(&low) {
+                        0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)
+                    } else {
+                        self.pos = start;
+                        unit
+                    }
+                } else {
+                    unit
+                };
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("Invalid escape"));
+            }
+        };
+        Ok(c)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("Invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.error("Invalid number")),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.expect_digits()?;
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            self.expect_digits()?;
+        }
+        // Only ASCII digits and signs were consumed.
+        let number = std::str::from_utf8(&self.input[start..self.pos]).expect("ASCII");
+        number
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| self.error("Invalid number"))
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_digits(&mut self) -> Result<(), String> {
+        let start = self.pos;
+        self.skip_digits();
+        if self.pos == start {
+            Err(self.error("Invalid number"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keeps_key_order() {
+        let json = Json::parse(r#"{ "b": 1, "a": [true, null, "x"], "b": "last" }"#).unwrap();
+        let keys = json
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["b", "a"]);
+        assert_eq!(json.get("b").and_then(Json::as_str), Some("last"));
+        assert_eq!(json.get("a").unwrap().to_string(), r#"[true,null,"x"]"#);
+    }
+
+    #[test]
+    fn test_parse_strings_and_numbers() {
+        let json = Json::parse(r#"["a\"\\\/\n\u00e9\ud83d\ude00", -1.5e3, 0]"#).unwrap();
+        assert_eq!(json.to_string(), "[\"a\\\"\\\\/\\né😀\",-1500,0]");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for invalid in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "01",
+            "1.",
+            "\"\\x\"",
+            "tru",
+            "{} x",
+        ] {
+            assert!(Json::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
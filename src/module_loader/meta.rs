@@ -0,0 +1,150 @@
+use std::ffi::c_int;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+use libquickjs_ng_sys as q;
+
+use super::{native_return, normalize_name, string_arg};
+use crate::utils::get_exception;
+use crate::{ExecutionError, ModuleError, OwnedJsObject, OwnedJsValue, ToOwnedJsValue};
+
+/// Populates the `import.meta` of a module, passes (module_name, import_meta).
+pub(crate) type ImportMetaHook = dyn Fn(&str, &ImportMeta) -> Result<(), ModuleError>;
+
+/// The `import.meta` object of a module, see
+/// [Context::set_import_meta_hook](crate::Context::set_import_meta_hook).
+pub struct ImportMeta {
+    object: OwnedJsObject,
+}
+
+impl ImportMeta {
+    /// Set a property of `import.meta`.
+    pub fn set<T: ToOwnedJsValue>(&self, key: &str, value: T) -> Result<(), ExecutionError> {
+        let value = (self.object.context(), value).into();
+        self.object.set_property(key, value)
+    }
+
+    /// The `import.meta` object.
+    pub fn object(&self) -> &OwnedJsObject {
+        &self.object
+    }
+}
+
+/// Populate the `import.meta` of a module before it is evaluated.
+///
+/// Sets `url`, `filename` and `dirname` for modules named by an absolute
+/// path, and `resolve()`, then calls the hook.
+pub(crate) fn init_import_meta(
+    context: *mut q::JSContext,
+    def: *mut q::JSModuleDef,
+    name: &str,
+    hook: Option<&ImportMetaHook>,
+) -> Result<(), ExecutionError> {
+    let object = OwnedJsValue::new(context, unsafe { q::JS_GetImportMeta(context, def) });
+    if object.is_exception() {
+        return Err(get_exception(context)
+            .unwrap_or_else(|| ExecutionError::Internal("Could not get import.meta".to_string())));
+    }
+    let meta = ImportMeta {
+        object: object.try_into_object()?,
+    };
+
+    let path = Path::new(name);
+    if path.is_absolute() {
+        meta.set("url", file_url(name))?;
+        meta.set("filename", name)?;
+        if let Some(dirname) = path.parent().and_then(Path::to_str) {
+            meta.set("dirname", dirname)?;
+        }
+    } else {
+        meta.set("url", name)?;
+    }
+    meta.object
+        .set_property("resolve", resolve_function(context, name)?)?;
+
+    if let Some(hook) = hook {
+        hook(name, &meta).map_err(|e| e.into_throw().into_exception(context))?;
+    }
+    Ok(())
+}
+
+/// The `file:` URL of the absolute path `path`, percent-encoding the bytes
+/// that are not allowed in URL paths, like spaces, `#`, `?` and `%`.
+fn file_url(path: &str) -> String {
+    #[cfg(windows)]
+    let path = windows_url_path(path);
+    let mut url = String::from("file://");
+    for &byte in path.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+/// The part of the `file:` URL of a Windows path after `file://`, like
+/// `/C:/dir` for `C:\dir`, or `server/share` for `\\server\share`, the
+/// server being the host of the URL.
+#[cfg(windows)]
+fn windows_url_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    // Verbatim paths, as returned by `fs::canonicalize`.
+    let path = match path.strip_prefix("//?/") {
+        Some(path) => match path.strip_prefix("UNC/") {
+            Some(unc) => format!("//{}", unc),
+            None => path.to_string(),
+        },
+        None => path,
+    };
+    match path.strip_prefix("//") {
+        Some(unc) => unc.to_string(),
+        None => format!("/{}", path),
+    }
+}
+
+/// Create `import.meta.resolve()` for the module `name`, which resolves a
+/// specifier like an import of the module does.
+fn resolve_function(
+    context: *mut q::JSContext,
+    name: &str,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let name: OwnedJsValue = (context, name).into();
+    let mut data = [unsafe { *name.as_inner() }];
+    // The data is duplicated by QuickJS.
+    let raw = unsafe {
+        q::JS_NewCFunctionData(
+            context,
+            Some(js_import_meta_resolve),
+            1,
+            0,
+            1,
+            data.as_mut_ptr(),
+        )
+    };
+    let func = OwnedJsValue::new(context, raw);
+    if func.is_exception() {
+        return Err(get_exception(context).unwrap_or_else(|| {
+            ExecutionError::Internal("Could not create import.meta.resolve".to_string())
+        }));
+    }
+    Ok(func)
+}
+
+unsafe extern "C" fn js_import_meta_resolve(
+    ctx: *mut q::JSContext,
+    _this: q::JSValue,
+    argc: c_int,
+    argv: *mut q::JSValue,
+    _magic: c_int,
+    data: *mut q::JSValue,
+) -> q::JSValue {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let base = OwnedJsValue::own(ctx, &*data).to_string()?;
+        let specifier = string_arg(ctx, argc, argv, 0)?;
+        let name = normalize_name(ctx, &base, &specifier)?;
+        Ok((ctx, name).into())
+    }));
+    native_return(ctx, result)
+}
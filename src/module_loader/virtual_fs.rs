@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{
+    is_relative, normalize_path, ImportAttributes, ModuleLoader, ModuleResolver, ModuleSource,
+};
+use crate::ModuleError;
+
+/// A file of a [VirtualModuleFs].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VirtualFile {
+    /// Module source code, or the content of a file imported with a `type`
+    /// attribute.
+    Source(Cow<'static, str>),
+    /// Module bytecode, written by
+    /// [compile::module_to_bytecode](crate::compile::module_to_bytecode) with
+    /// the path of the file as module name.
+    Bytecode(Cow<'static, [u8]>),
+    /// Raw content, like a binary asset imported with a `type` attribute.
+    Bytes(Cow<'static, [u8]>),
+}
+
+impl VirtualFile {
+    /// The content of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            VirtualFile::Source(source) => source.as_bytes(),
+            VirtualFile::Bytecode(bytes) | VirtualFile::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+impl From<&'static str> for VirtualFile {
+    fn from(source: &'static str) -> Self {
+        VirtualFile::Source(Cow::Borrowed(source))
+    }
+}
+
+impl From<String> for VirtualFile {
+    fn from(source: String) -> Self {
+        VirtualFile::Source(Cow::Owned(source))
+    }
+}
+
+impl From<&'static [u8]> for VirtualFile {
+    fn from(bytecode: &'static [u8]) -> Self {
+        VirtualFile::Bytecode(Cow::Borrowed(bytecode))
+    }
+}
+
+impl From<Vec<u8>> for VirtualFile {
+    fn from(bytecode: Vec<u8>) -> Self {
+        VirtualFile::Bytecode(Cow::Owned(bytecode))
+    }
+}
+
+/// A module loader and resolver serving modules from memory, for scripts
+/// bundled into the binary.
+///
+/// Files are keyed by absolute paths like `/lib/math.js`, directories exist
+/// implicitly through the paths of their files. Specifiers are resolved like
+/// by the [FsModuleLoader](super::FsModuleLoader):
+///
+/// * relative specifiers are resolved against the directory of the importing
+///   module, or against `/` for modules that are not in the filesystem
+/// * a path is tried as is, then with each of the
+///   [extensions](Self::extensions), then as a directory containing an
+///   `index` file
+/// * bare specifiers are passed on unchanged, so that native modules can
+///   still be imported
+///
+/// `.cjs` files are loaded as CommonJS modules, see
+/// [Context::enable_commonjs](crate::Context::enable_commonjs).
+///
+/// ```rust
+/// use quickjs_rusty::module_loader::VirtualModuleFs;
+/// use quickjs_rusty::Context;
+/// let context = Context::builder().build().unwrap();
+///
+/// let mut fs = VirtualModuleFs::new();
+/// fs.insert("/main.js", "import { add } from './lib'; globalThis.result = add(1, 2);")
+///     .insert("/lib/index.js", "export const add = (a, b) => a + b;");
+///
+/// context.set_virtual_module_fs(fs);
+/// context.run_module("/main.js").unwrap();
+/// assert_eq!(context.eval_as::<i32>("result").unwrap(), 3);
+/// ```
+///
+/// The files of a directory can be embedded at compile time with
+/// [include_modules!](crate::include_modules), or read at runtime with
+/// [VirtualModuleFs::from_dir].
+#[derive(Clone, Debug)]
+pub struct VirtualModuleFs {
+    files: BTreeMap<String, VirtualFile>,
+    extensions: Vec<String>,
+}
+
+impl Default for VirtualModuleFs {
+    fn default() -> Self {
+        Self {
+            files: BTreeMap::new(),
+            extensions: vec!["js".to_string(), "mjs".to_string()],
+        }
+    }
+}
+
+impl VirtualModuleFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the files of a directory and its subdirectories, the directory
+    /// becoming the root `/`.
+    ///
+    /// Files that are valid UTF-8 are added as [source](VirtualFile::Source),
+    /// the others as [raw bytes](VirtualFile::Bytes). Bytecode has to be
+    /// [inserted](Self::insert) as [VirtualFile::Bytecode].
+    pub fn from_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut fs = Self::new();
+        fs.insert_dir(dir.as_ref(), "")?;
+        Ok(fs)
+    }
+
+    fn insert_dir(&mut self, dir: &Path, prefix: &str) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let path = format!("{}/{}", prefix, name);
+            if entry.file_type()?.is_dir() {
+                self.insert_dir(&entry.path(), &path)?;
+            } else {
+                let content = fs::read(entry.path())?;
+                match String::from_utf8(content) {
+                    Ok(source) => self.insert(&path, source),
+                    Err(e) => self.insert(&path, VirtualFile::Bytes(Cow::Owned(e.into_bytes()))),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a file, replacing the one at the same path.
+    ///
+    /// Relative paths are taken from the root.
+    pub fn insert(&mut self, path: &str, file: impl Into<VirtualFile>) -> &mut Self {
+        self.files.insert(normalize(path), file.into());
+        self
+    }
+
+    /// Remove a file.
+    pub fn remove(&mut self, path: &str) -> Option<VirtualFile> {
+        self.files.remove(&normalize(path))
+    }
+
+    /// Get a file.
+    pub fn get(&self, path: &str) -> Option<&VirtualFile> {
+        self.files.get(&normalize(path))
+    }
+
+    /// Whether `path` is a file.
+    pub fn is_file(&self, path: &str) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+
+    /// Whether `path` is a directory, containing at least one file.
+    pub fn is_dir(&self, path: &str) -> bool {
+        let mut dir = normalize(path);
+        if !dir.ends_with('/') {
+            dir.push('/');
+        }
+        self.files
+            .range(dir.clone()..)
+            .next()
+            .is_some_and(|(path, _)| path.starts_with(&dir))
+    }
+
+    /// The paths of the files, in order.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// Set the extensions tried when a path does not exist, `js` and `mjs`
+    /// by default.
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Try `path` as a file, with each extension, then as a directory.
+    fn resolve_path(&self, path: &str) -> Option<String> {
+        let path = normalize(path);
+        if self.is_file(&path) {
+            return Some(path);
+        }
+        if let Some(path) = self.with_extension(&path) {
+            return Some(path);
+        }
+        if self.is_dir(&path) {
+            return self.with_extension(&format!("{}/index", path.trim_end_matches('/')));
+        }
+        None
+    }
+
+    fn with_extension(&self, path: &str) -> Option<String> {
+        self.extensions
+            .iter()
+            .map(|ext| format!("{}.{}", path, ext))
+            .find(|file| self.is_file(file))
+    }
+}
+
+impl ModuleResolver for VirtualModuleFs {
+    fn resolve(&self, base: &str, specifier: &str) -> Result<String, ModuleError> {
+        let path = if specifier.starts_with('/') {
+            specifier.to_string()
+        } else if is_relative(specifier) {
+            // Modules outside of the filesystem resolve against the root.
+            let dir = match base.rfind('/') {
+                Some(i) if base.starts_with('/') => &base[..=i],
+                _ => "/",
+            };
+            format!("{}{}", dir, specifier)
+        } else {
+            // Leave it to a native module, or to fail when loading.
+            return Ok(specifier.to_string());
+        };
+
+        self.resolve_path(&path).ok_or_else(|| {
+            ModuleError::NotFound(format!(
+                "Cannot find module '{}' imported from '{}'",
+                specifier, base
+            ))
+        })
+    }
+}
+
+impl ModuleLoader for VirtualModuleFs {
+    fn load(&self, name: &str, attributes: &ImportAttributes) -> Result<ModuleSource, ModuleError> {
+        let file = self
+            .files
+            .get(name)
+            .ok_or_else(|| ModuleError::not_found(name))?;
+        // The content of any file can be imported with a type attribute.
+        if attributes.module_type().is_some() {
+            return Ok(ModuleSource::Bytes(file.as_bytes().to_vec()));
+        }
+        match file {
+            VirtualFile::Source(source) if name.ends_with(".cjs") => {
+                Ok(ModuleSource::CommonJs(source.to_string()))
+            }
+            VirtualFile::Source(source) => Ok(ModuleSource::Source(source.to_string())),
+            VirtualFile::Bytecode(bytecode) => Ok(ModuleSource::Bytecode(bytecode.to_vec())),
+            VirtualFile::Bytes(bytes) => Ok(ModuleSource::Bytes(bytes.to_vec())),
+        }
+    }
+}
+
+/// Normalize a path to an absolute path without `.` and `..` segments.
+fn normalize(path: &str) -> String {
+    if path.starts_with('/') {
+        normalize_path(path)
+    } else {
+        normalize_path(&format!("/{}", path))
+    }
+}
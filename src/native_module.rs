@@ -0,0 +1,277 @@
+//! ES modules whose exports are defined in Rust.
+
+use std::collections::HashMap;
+use std::ffi::{c_int, CString};
+use std::sync::Mutex;
+
+use libquickjs_ng_sys as q;
+
+use crate::callback::{AsyncCallback, Callback};
+use crate::module_loader::attributes::{builtin_module_types, ModuleTypeFunc};
+use crate::utils::make_cstring;
+use crate::{Context, ExecutionError, OwnedJsObject, OwnedJsValue, ToOwnedJsValue};
+
+/// Collects the exports of a native module.
+///
+/// See [Context::register_native_module].
+pub struct NativeModuleBuilder<'a> {
+    context: &'a Context,
+    exports: Vec<(String, OwnedJsValue)>,
+    error: Option<ExecutionError>,
+}
+
+impl<'a> NativeModuleBuilder<'a> {
+    pub(crate) fn new(context: &'a Context) -> Self {
+        Self {
+            context,
+            exports: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Export a value.
+    pub fn export<T: ToOwnedJsValue>(&mut self, name: &str, value: T) -> &mut Self {
+        let value = (unsafe { self.context.context_raw() }, value).into();
+        self.exports.push((name.to_string(), value));
+        self
+    }
+
+    /// Export a function backed by a Rust function or closure.
+    ///
+    /// See [Context::add_callback] for the requirements on the callback.
+    pub fn export_callback<F>(
+        &mut self,
+        name: &str,
+        callback: impl Callback<F> + 'static,
+    ) -> &mut Self {
+        match self.context.create_callback(callback) {
+            Ok(func) => self.export(name, func),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Export a function backed by an async Rust function or closure.
+    ///
+    /// See [Context::add_async_callback] for the requirements on the callback.
+    pub fn export_async_callback<F>(
+        &mut self,
+        name: &str,
+        callback: impl AsyncCallback<F> + 'static,
+    ) -> &mut Self {
+        match self.context.create_async_callback(callback) {
+            Ok(func) => self.export(name, func),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Keep the first error, which is returned by
+    /// [Context::register_native_module].
+    fn fail(&mut self, e: ExecutionError) -> &mut Self {
+        self.error.get_or_insert(e);
+        self
+    }
+
+    pub(crate) fn finish(self) -> Result<Vec<(String, OwnedJsValue)>, ExecutionError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.exports),
+        }
+    }
+}
+
+struct NativeModule {
+    name: String,
+    def: *mut q::JSModuleDef,
+    /// Whether the module was created by the module loader, for an import
+    /// with a `type` attribute or a CommonJS module. These can be invalidated
+    /// and loaded again.
+    loaded: bool,
+    /// The export values, taken when the module is initialized.
+    exports: Option<Vec<(CString, OwnedJsValue)>>,
+}
+
+/// Native modules registered in a context.
+///
+/// A pointer to the registry is stored as the context opaque, so that the
+/// module init function can find the exports of a module.
+///
+/// It also holds the module types that can be imported with a `type`
+/// attribute, which are loaded as native modules with a default export, and
+/// the CommonJS state of the context.
+pub(crate) struct NativeModuleRegistry {
+    modules: Mutex<Vec<NativeModule>>,
+    module_types: Mutex<HashMap<String, ModuleTypeFunc>>,
+    /// The `require` implementation, when CommonJS modules are enabled.
+    commonjs: Mutex<Option<OwnedJsObject>>,
+}
+
+impl Default for NativeModuleRegistry {
+    fn default() -> Self {
+        Self {
+            modules: Mutex::default(),
+            module_types: Mutex::new(builtin_module_types()),
+            commonjs: Mutex::default(),
+        }
+    }
+}
+
+impl NativeModuleRegistry {
+    /// Get the registry stored as the context opaque.
+    pub(crate) unsafe fn from_context<'a>(context: *mut q::JSContext) -> Option<&'a Self> {
+        (q::JS_GetContextOpaque(context) as *const Self).as_ref()
+    }
+
+    /// Create the module definition and declare its exports.
+    pub(crate) fn register(
+        &self,
+        context: *mut q::JSContext,
+        name: &str,
+        exports: Vec<(String, OwnedJsValue)>,
+    ) -> Result<*mut q::JSModuleDef, ExecutionError> {
+        self.insert(context, name, exports, false)
+    }
+
+    fn insert(
+        &self,
+        context: *mut q::JSContext,
+        name: &str,
+        exports: Vec<(String, OwnedJsValue)>,
+        loaded: bool,
+    ) -> Result<*mut q::JSModuleDef, ExecutionError> {
+        let mut modules = self.modules.lock().unwrap();
+        if modules.iter().any(|m| m.name == name) {
+            return Err(ExecutionError::Internal(format!(
+                "Module {} is already registered",
+                name
+            )));
+        }
+
+        let exports = exports
+            .into_iter()
+            .map(|(name, value)| Ok((make_cstring(name)?, value)))
+            .collect::<Result<Vec<_>, ExecutionError>>()?;
+
+        let name_c = make_cstring(name)?;
+        let def = unsafe { q::JS_NewCModule(context, name_c.as_ptr(), Some(native_module_init)) };
+        if def.is_null() {
+            return Err(ExecutionError::Internal(format!(
+                "Could not create module {}",
+                name
+            )));
+        }
+        for (export_name, _) in &exports {
+            if unsafe { q::JS_AddModuleExport(context, def, export_name.as_ptr()) } < 0 {
+                return Err(ExecutionError::Internal(format!(
+                    "Could not add export {:?} to module {}",
+                    export_name, name
+                )));
+            }
+        }
+
+        modules.push(NativeModule {
+            name: name.to_string(),
+            def,
+            loaded,
+            exports: Some(exports),
+        });
+        Ok(def)
+    }
+
+    /// Whether the module was registered with
+    /// [Context::register_native_module].
+    pub(crate) fn is_native(&self, def: *mut q::JSModuleDef) -> bool {
+        let modules = self.modules.lock().unwrap();
+        modules.iter().any(|m| m.def == def && !m.loaded)
+    }
+
+    /// Forget an invalidated module created by the module loader, so that it
+    /// can be registered again.
+    pub(crate) fn forget_loaded(&self, def: *mut q::JSModuleDef) {
+        let mut modules = self.modules.lock().unwrap();
+        modules.retain(|m| !(m.def == def && m.loaded));
+    }
+
+    pub(crate) fn add_module_type(&self, module_type: &str, func: ModuleTypeFunc) {
+        self.module_types
+            .lock()
+            .unwrap()
+            .insert(module_type.to_string(), func);
+    }
+
+    pub(crate) fn has_module_type(&self, module_type: &str) -> bool {
+        self.module_types.lock().unwrap().contains_key(module_type)
+    }
+
+    /// Create a module of the given type, exporting the value created from
+    /// `content` as default.
+    pub(crate) fn register_typed(
+        &self,
+        context: *mut q::JSContext,
+        name: &str,
+        module_type: &str,
+        content: Vec<u8>,
+    ) -> Result<*mut q::JSModuleDef, ExecutionError> {
+        let value = {
+            let module_types = self.module_types.lock().unwrap();
+            let func = module_types.get(module_type).ok_or_else(|| {
+                ExecutionError::Internal(format!("Unsupported module type: {}", module_type))
+            })?;
+            func(context, name, content)?
+        };
+        self.insert(context, name, vec![("default".to_string(), value)], true)
+    }
+
+    /// Create the module wrapping a CommonJS module for ES modules importing
+    /// it.
+    pub(crate) fn register_commonjs(
+        &self,
+        context: *mut q::JSContext,
+        name: &str,
+        exports: Vec<(String, OwnedJsValue)>,
+    ) -> Result<*mut q::JSModuleDef, ExecutionError> {
+        self.insert(context, name, exports, true)
+    }
+
+    fn take_exports(&self, def: *mut q::JSModuleDef) -> Option<Vec<(CString, OwnedJsValue)>> {
+        let mut modules = self.modules.lock().unwrap();
+        let module = modules.iter_mut().find(|m| m.def == def)?;
+        module.exports.take()
+    }
+
+    pub(crate) fn set_commonjs(&self, state: OwnedJsObject) {
+        *self.commonjs.lock().unwrap() = Some(state);
+    }
+
+    pub(crate) fn commonjs(&self) -> Option<OwnedJsObject> {
+        self.commonjs.lock().unwrap().clone()
+    }
+
+    /// Forget all modules, freeing the export values that were not taken, and
+    /// the CommonJS state.
+    ///
+    /// Must be called before the context is freed.
+    pub(crate) fn clear(&self) {
+        self.modules.lock().unwrap().clear();
+        self.commonjs.lock().unwrap().take();
+    }
+}
+
+/// Set the exports of a native module, called by QuickJS when the module is
+/// first imported.
+unsafe extern "C" fn native_module_init(
+    context: *mut q::JSContext,
+    def: *mut q::JSModuleDef,
+) -> c_int {
+    let registry = NativeModuleRegistry::from_context(context);
+    let Some(exports) = registry.and_then(|r| r.take_exports(def)) else {
+        q::JS_ThrowInternalError(context, c"Native module is not registered".as_ptr());
+        return -1;
+    };
+
+    for (name, value) in exports {
+        if q::JS_SetModuleExport(context, def, name.as_ptr(), value.extract()) < 0 {
+            return -1;
+        }
+    }
+    0
+}
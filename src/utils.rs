@@ -296,7 +296,8 @@ macro_rules! owned {
     };
 }
 
-use crate::ExecutionError;
+use crate::context::interrupt_error;
+use crate::{ExecutionError, JsError};
 
 /// Get the last exception from the runtime, and if present, convert it to a ExceptionError.
 pub(crate) fn get_exception(context: *mut q::JSContext) -> Option<ExecutionError> {
@@ -304,28 +305,26 @@ pub(crate) fn get_exception(context: *mut q::JSContext) -> Option<ExecutionError
         return None;
     }
 
-    let value = unsafe {
+    let (value, interrupted) = unsafe {
         let raw = q::JS_GetException(context);
-        OwnedJsValue::new(context, raw)
+        (
+            OwnedJsValue::new(context, raw),
+            q::JS_IsUncatchableError(raw),
+        )
     };
 
     if value.is_exception() {
         Some(ExecutionError::Internal(
             "Could get exception from runtime".into(),
         ))
+    } else if interrupted {
+        Some(interrupt_error(context))
     } else {
-        match value.js_to_string() {
-            Ok(strval) => {
-                if strval.contains("out of memory") {
-                    Some(ExecutionError::OutOfMemory)
-                } else {
-                    Some(ExecutionError::Exception(OwnedJsValue::new(
-                        context,
-                        create_string(context, &strval).unwrap(),
-                    )))
-                }
-            }
-            Err(e) => Some(e),
+        let error = JsError::from_value(value);
+        if error.is_out_of_memory() {
+            Some(ExecutionError::OutOfMemory)
+        } else {
+            Some(ExecutionError::Exception(error))
         }
     }
 }
@@ -10,6 +10,11 @@ impl OwnedJsAtom {
     pub fn new(context: *mut q::JSContext, value: q::JSAtom) -> Self {
         Self { context, value }
     }
+
+    #[inline]
+    pub(crate) fn raw(&self) -> q::JSAtom {
+        self.value
+    }
 }
 
 impl Drop for OwnedJsAtom {
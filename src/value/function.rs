@@ -3,6 +3,7 @@ use std::ops::Deref;
 
 use libquickjs_ng_sys as q;
 
+use crate::context::RuntimeState;
 use crate::{ExecutionError, ValueError};
 
 use super::OwnedJsValue;
@@ -42,6 +43,12 @@ impl JsFunction {
                 qargs.as_mut_ptr(),
             )
         };
+
+        // Settling a promise from Rust, like with the resolve function of
+        // `OwnedJsPromise::with_resolvers`, queues its reactions.
+        if let Some(state) = unsafe { RuntimeState::from_context(self.value.context()) } {
+            state.job_wakers.wake_if_pending(self.value.context());
+        }
         Ok(OwnedJsValue::new(self.value.context(), qres_raw))
     }
 }
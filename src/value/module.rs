@@ -1,6 +1,10 @@
+use std::ffi::CStr;
 use std::ops::Deref;
 
+use libquickjs_ng_sys as q;
+
 use crate::errors::*;
+use crate::utils::{get_exception, make_cstring};
 use crate::value::*;
 
 /// A bytecode compiled module.
@@ -12,7 +16,7 @@ impl JsModule {
     pub fn try_from_value(value: OwnedJsValue) -> Result<Self, ValueError> {
         if !value.is_module() {
             Err(ValueError::Internal(format!(
-                "Expected a compiled function, got {:?}",
+                "Expected a module, got {:?}",
                 value.tag()
             )))
         } else {
@@ -23,6 +27,133 @@ impl JsModule {
     pub fn into_value(self) -> OwnedJsValue {
         self.value
     }
+
+    pub(crate) fn def(&self) -> *mut q::JSModuleDef {
+        unsafe { q::JS_Ext_GetPtr(self.value.value) as *mut q::JSModuleDef }
+    }
+
+    /// The name of the module.
+    pub fn name(&self) -> Result<String, ValueError> {
+        module_name(self.value.context(), self.def())
+    }
+
+    /// The namespace object of the module, holding its exports.
+    ///
+    /// The exports are only initialized once the module is evaluated, see
+    /// [Context::load_module](crate::Context::load_module).
+    pub fn namespace(&self) -> Result<OwnedJsObject, ExecutionError> {
+        let context = self.value.context();
+        let raw = unsafe { q::JS_GetModuleNamespace(context, self.def()) };
+        let namespace = OwnedJsValue::new(context, raw);
+        if namespace.is_exception() {
+            return Err(get_exception(context).unwrap_or_else(|| {
+                ExecutionError::Internal("Could not get the module namespace".to_string())
+            }));
+        }
+        Ok(namespace.try_into_object()?)
+    }
+
+    /// Get an export of the module, converted to a Rust type.
+    ///
+    /// ```rust
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// let module = context
+    ///     .load_module("export const version = 2; export default 'plugin';", "plugin")
+    ///     .unwrap();
+    /// assert_eq!(module.get_export::<i32>("version").unwrap(), 2);
+    /// assert_eq!(module.get_export::<String>("default").unwrap(), "plugin");
+    /// assert!(module.get_export::<i32>("missing").is_err());
+    /// ```
+    pub fn get_export<T>(&self, name: &str) -> Result<T, ExecutionError>
+    where
+        T: TryFrom<OwnedJsValue>,
+        T::Error: Into<ValueError>,
+    {
+        let value = self.export(name)?;
+        T::try_from(value).map_err(|e| e.into().into())
+    }
+
+    /// Call a function exported by the module.
+    ///
+    /// The return value is not resolved if it is a promise, see
+    /// [Context::resolve_value](crate::Context::resolve_value).
+    ///
+    /// ```rust
+    /// use quickjs_rusty::Context;
+    /// let context = Context::builder().build().unwrap();
+    ///
+    /// let module = context
+    ///     .load_module("export function add(a, b) { return a + b; }", "plugin")
+    ///     .unwrap();
+    /// let sum = module.call_export("add", vec![1, 2]).unwrap();
+    /// assert_eq!(sum.to_int(), Ok(3));
+    /// ```
+    pub fn call_export(
+        &self,
+        name: &str,
+        args: impl IntoIterator<Item = impl ToOwnedJsValue>,
+    ) -> Result<OwnedJsValue, ExecutionError> {
+        let context = self.value.context();
+        let func = self.export(name)?.try_into_function()?;
+        let args = args
+            .into_iter()
+            .map(|arg| (context, arg).into())
+            .collect::<Vec<OwnedJsValue>>();
+
+        let ret = func.call(args)?;
+        if ret.is_exception() {
+            return Err(get_exception(context).unwrap_or_else(|| {
+                ExecutionError::Internal(format!("Exception while calling export '{}'", name))
+            }));
+        }
+        Ok(ret)
+    }
+
+    fn export(&self, name: &str) -> Result<OwnedJsValue, ExecutionError> {
+        let context = self.value.context();
+        let namespace = self.namespace()?;
+
+        let name_c = make_cstring(name)?;
+        let atom = OwnedJsAtom::new(context, unsafe { q::JS_NewAtom(context, name_c.as_ptr()) });
+        let has_export = unsafe { q::JS_HasProperty(context, namespace.value, atom.raw()) };
+        if has_export <= 0 {
+            return Err(ExecutionError::Internal(format!(
+                "Module has no export named '{}'",
+                name
+            )));
+        }
+
+        let raw = unsafe { q::JS_GetProperty(context, namespace.value, atom.raw()) };
+        let value = OwnedJsValue::new(context, raw);
+        if value.is_exception() {
+            return Err(get_exception(context).unwrap_or_else(|| {
+                ExecutionError::Internal(format!("Exception while getting export '{}'", name))
+            }));
+        }
+        Ok(value)
+    }
+}
+
+/// Get the name of a module definition.
+pub(crate) fn module_name(
+    context: *mut q::JSContext,
+    def: *mut q::JSModuleDef,
+) -> Result<String, ValueError> {
+    let atom = OwnedJsAtom::new(context, unsafe { q::JS_GetModuleName(context, def) });
+    let name = unsafe { q::JS_AtomToCString(context, atom.raw()) };
+    if name.is_null() {
+        return Err(ValueError::Internal(
+            "Could not get the module name".to_string(),
+        ));
+    }
+    let result = unsafe { CStr::from_ptr(name) }
+        .to_str()
+        .map(str::to_string)
+        .map_err(ValueError::InvalidString);
+    unsafe { q::JS_FreeCString(context, name) };
+    result
 }
 
 /// The result of loading QuickJs bytecode.
@@ -1,9 +1,16 @@
+use std::ffi::c_int;
+use std::future::Future;
 use std::ops::Deref;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll, Waker};
 
 use libquickjs_ng_sys as q;
 
-use crate::utils::ensure_no_excpetion;
-use crate::{Context, ExecutionError, JsFunction, ValueError};
+use crate::class::{instance_cell, new_instance};
+use crate::utils::{create_null, create_undefined, ensure_no_excpetion, get_exception};
+use crate::{ClassBuilder, Context, ExecutionError, JsClass, JsFunction, ValueError};
 
 use super::OwnedJsValue;
 
@@ -186,10 +193,18 @@ impl OwnedJsPromise {
     pub fn with_resolvers(
         context: &Context,
     ) -> Result<(OwnedJsPromise, JsFunction, JsFunction), ExecutionError> {
-        let obj = unsafe { q::JS_Ext_PromiseWithResolvers(context.context) };
-        let obj = OwnedJsValue::new(context.context, obj);
+        Self::with_resolvers_raw(context.context)
+    }
 
-        ensure_no_excpetion(context.context)?;
+    /// [OwnedJsPromise::with_resolvers] for a raw context, used by code
+    /// without a [Context], like async callbacks.
+    pub(crate) fn with_resolvers_raw(
+        context: *mut q::JSContext,
+    ) -> Result<(OwnedJsPromise, JsFunction, JsFunction), ExecutionError> {
+        let obj = unsafe { q::JS_Ext_PromiseWithResolvers(context) };
+        let obj = OwnedJsValue::new(context, obj);
+
+        ensure_no_excpetion(context)?;
 
         let obj = obj.try_into_object()?;
 
@@ -202,6 +217,171 @@ impl OwnedJsPromise {
     }
 }
 
+impl OwnedJsPromise {
+    /// Convert the promise into a [Future] that completes once the promise
+    /// has settled.
+    ///
+    /// While polled, the future drives the event loop and the async callbacks
+    /// of `context`, see [Context::poll_async]. It resolves to the fulfillment
+    /// value, or to `ExecutionError::Exception` with the rejection reason.
+    ///
+    /// The future is also woken when a function called from Rust, like the
+    /// resolve function of [OwnedJsPromise::with_resolvers], queues jobs, so
+    /// that they are executed by the next poll.
+    pub fn into_future(self, context: &Context) -> PromiseFuture<'_> {
+        PromiseFuture {
+            context,
+            promise: self,
+            waker: Arc::new(Mutex::new(None)),
+            subscribed: false,
+        }
+    }
+
+    fn settled_result(&self) -> Option<Result<OwnedJsValue, ExecutionError>> {
+        match self.state() {
+            PromiseState::Pending => None,
+            PromiseState::Fulfilled => Some(Ok(self.result())),
+            PromiseState::Rejected => Some(Err(ExecutionError::Exception(self.result().into()))),
+        }
+    }
+}
+
+/// A [Future] resolving to the settled value of an [OwnedJsPromise].
+///
+/// Created with [OwnedJsPromise::into_future].
+pub struct PromiseFuture<'a> {
+    context: &'a Context,
+    promise: OwnedJsPromise,
+    /// Woken by the reactions registered on the promise.
+    waker: Arc<Mutex<Option<Waker>>>,
+    subscribed: bool,
+}
+
+/// The waker of a [PromiseFuture], held by the reactions registered on the
+/// promise, and dropped once they are garbage collected.
+struct PromiseWaker(Arc<Mutex<Option<Waker>>>);
+
+impl JsClass for PromiseWaker {
+    const NAME: &'static str = "PromiseWaker";
+
+    fn define(_class: &mut ClassBuilder<Self>) {}
+}
+
+impl PromiseFuture<'_> {
+    /// Register reactions on the promise that wake the current task.
+    fn subscribe(&mut self) -> Result<(), ExecutionError> {
+        let context = self.context.context;
+        let runtime = self.context.runtime();
+        let class_id = runtime
+            .classes()
+            .register::<PromiseWaker>(unsafe { runtime.runtime_raw() })?;
+        let waker = new_instance(
+            context,
+            create_null(),
+            class_id,
+            PromiseWaker(self.waker.clone()),
+        )?;
+
+        // The function owns the waker through its data, which is duplicated
+        // by QuickJS, rather than a closure living as long as the context.
+        let mut data = [waker.value];
+        let wake = OwnedJsValue::new(context, unsafe {
+            q::JS_NewCFunctionData(context, Some(js_wake), 0, 0, 1, data.as_mut_ptr())
+        });
+        if wake.is_exception() {
+            return Err(get_exception(context).unwrap_or_else(|| {
+                ExecutionError::Internal("Could not create promise reaction".to_string())
+            }));
+        }
+
+        self.promise.then2(&wake, &wake)?;
+        self.subscribed = true;
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn js_wake(
+    ctx: *mut q::JSContext,
+    _this: q::JSValue,
+    _argc: c_int,
+    _argv: *mut q::JSValue,
+    _magic: c_int,
+    data: *mut q::JSValue,
+) -> q::JSValue {
+    if let Some(cell) = instance_cell::<PromiseWaker>(ctx, *data) {
+        let waker = cell.borrow().0.lock().unwrap().take();
+        if let Some(waker) = waker {
+            // The executor's waker must not unwind into QuickJS.
+            let _ = catch_unwind(AssertUnwindSafe(|| waker.wake()));
+        }
+    }
+    create_undefined()
+}
+
+impl Future for PromiseFuture<'_> {
+    type Output = Result<OwnedJsValue, ExecutionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Some(result) = this.promise.settled_result() {
+            return Poll::Ready(result);
+        }
+
+        *this.waker.lock().unwrap() = Some(cx.waker().clone());
+        if !this.subscribed {
+            if let Err(e) = this.subscribe() {
+                return Poll::Ready(Err(e));
+            }
+        }
+
+        if let Poll::Ready(Err(e)) = this.context.poll_async(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        if let Some(result) = this.promise.settled_result() {
+            return Poll::Ready(result);
+        }
+
+        // Jobs queued meanwhile, e.g. by a waker calling back into Rust, must
+        // run before the reactions can wake the task.
+        let runtime = this.context.runtime();
+        if runtime.is_job_pending() {
+            cx.waker().wake_by_ref();
+        } else {
+            runtime.job_wakers().register(cx.waker());
+        }
+        Poll::Pending
+    }
+}
+
+/// The wakers of the pending [PromiseFuture]s of a runtime, woken when a
+/// function called from Rust leaves jobs to execute.
+///
+/// Those jobs are not run by Javascript, so the reactions waking the futures
+/// would otherwise never be called.
+#[derive(Default)]
+pub(crate) struct JobWakers(Mutex<Vec<Waker>>);
+
+impl JobWakers {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    pub(crate) fn wake_if_pending(&self, context: *mut q::JSContext) {
+        if !unsafe { q::JS_IsJobPending(q::JS_GetRuntime(context)) } {
+            return;
+        }
+        let wakers = std::mem::take(&mut *self.0.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
 impl Deref for OwnedJsPromise {
     type Target = OwnedJsValue;
 
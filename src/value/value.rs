@@ -13,7 +13,7 @@ use crate::utils::create_bigint;
 use crate::utils::create_date;
 use crate::utils::{
     add_array_element, add_object_property, create_bool, create_empty_array, create_empty_object,
-    create_float, create_function, create_int, create_null, create_string,
+    create_float, create_function, create_int, create_null, create_string, create_undefined,
 };
 use crate::OwnedJsPromise;
 use crate::{ExecutionError, ValueError};
@@ -676,7 +676,7 @@ impl<T: TryFrom<OwnedJsValue, Error = ValueError>> TryFrom<OwnedJsValue> for Opt
     type Error = ValueError;
 
     fn try_from(value: OwnedJsValue) -> Result<Self, Self::Error> {
-        if value.is_null() {
+        if value.is_null() || value.is_undefined() {
             return Ok(None);
         }
         Ok(Some(value.try_into()?))
@@ -777,6 +777,12 @@ pub trait ToOwnedJsValue {
     fn to_owned(self, context: *mut q::JSContext) -> OwnedJsValue;
 }
 
+impl ToOwnedJsValue for () {
+    fn to_owned(self, context: *mut q::JSContext) -> OwnedJsValue {
+        OwnedJsValue::new(context, create_undefined())
+    }
+}
+
 impl ToOwnedJsValue for bool {
     fn to_owned(self, context: *mut q::JSContext) -> OwnedJsValue {
         let val = create_bool(context, self);
@@ -920,6 +926,13 @@ impl ToOwnedJsValue for OwnedJsPromise {
     }
 }
 
+impl ToOwnedJsValue for OwnedJsObject {
+    fn to_owned(self, context: *mut q::JSContext) -> OwnedJsValue {
+        let val = unsafe { self.into_value().extract() };
+        OwnedJsValue::new(context, val)
+    }
+}
+
 /// for some cases like HashMap<String, OwnedJsValue>
 impl ToOwnedJsValue for OwnedJsValue {
     fn to_owned(self, _: *mut q::JSContext) -> OwnedJsValue {
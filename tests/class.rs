@@ -0,0 +1,147 @@
+use quickjs_rusty::*;
+
+struct Counter {
+    count: i32,
+}
+
+impl JsClass for Counter {
+    const NAME: &'static str = "Counter";
+
+    fn define(class: &mut ClassBuilder<Self>) {
+        class
+            .constructor(|start: i32| Counter { count: start })
+            .method("increment", |c: &mut Counter, by: i32| {
+                c.count += by;
+                c.count
+            })
+            .method("reset", |c: &mut Counter| c.count = 0)
+            .getter("count", |c: &Counter| c.count)
+            .setter("count", |c: &mut Counter, count: i32| c.count = count);
+    }
+}
+
+struct Token(String);
+
+impl JsClass for Token {
+    const NAME: &'static str = "Token";
+
+    fn define(class: &mut ClassBuilder<Self>) {
+        class.getter("value", |t: &Token| t.0.clone());
+    }
+}
+
+#[test]
+fn test_class_constructor_and_methods() {
+    let c = Context::builder().build().unwrap();
+    c.register_class::<Counter>().unwrap();
+
+    let value = c
+        .eval_as::<i32>("const c = new Counter(5); c.increment(2); c.increment(3)")
+        .unwrap();
+    assert_eq!(value, 10);
+
+    assert!(c.eval_as::<bool>("c instanceof Counter").unwrap());
+    assert!(c
+        .eval_as::<bool>("Object.getPrototypeOf(c) === Counter.prototype")
+        .unwrap());
+    assert_eq!(c.eval_as::<i32>("c.reset(); c.count").unwrap(), 0);
+}
+
+#[test]
+fn test_class_accessors() {
+    let c = Context::builder().build().unwrap();
+    c.register_class::<Counter>().unwrap();
+
+    let value = c
+        .eval_as::<i32>("const c = new Counter(1); c.count = 41; c.increment(1)")
+        .unwrap();
+    assert_eq!(value, 42);
+    assert_eq!(
+        c.eval_as::<i32>("Object.keys(new Counter(0)).length")
+            .unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_class_errors() {
+    let c = Context::builder().build().unwrap();
+    let ctx = unsafe { c.context_raw() };
+    c.register_class::<Counter>().unwrap();
+    c.register_class::<Token>().unwrap();
+
+    assert_eq!(
+        c.eval("Counter(1)", false),
+        Err(ExecutionError::Exception(
+            owned!(
+                ctx,
+                "TypeError: Class constructor Counter cannot be invoked without 'new'"
+            )
+            .into()
+        ))
+    );
+    assert_eq!(
+        c.eval("Counter.call(Counter, 1)", false),
+        Err(ExecutionError::Exception(
+            owned!(
+                ctx,
+                "TypeError: Class constructor Counter cannot be invoked without 'new'"
+            )
+            .into()
+        ))
+    );
+    // Exceptions thrown while reading the prototype of new.target are kept.
+    assert_eq!(
+        c.eval(
+            r#"
+            const target = new Proxy(function () {}, {
+                get(target, key) {
+                    if (key === "prototype") throw new Error("no prototype");
+                    return target[key];
+                },
+            });
+            Reflect.construct(Counter, [1], target);
+            "#,
+            false
+        ),
+        Err(ExecutionError::Exception(
+            owned!(ctx, "Error: no prototype").into()
+        ))
+    );
+    assert_eq!(
+        c.eval("new Token()", false),
+        Err(ExecutionError::Exception(
+            owned!(ctx, "TypeError: Token is not a constructor").into()
+        ))
+    );
+    assert_eq!(
+        c.eval("Counter.prototype.increment.call({}, 1)", false),
+        Err(ExecutionError::Exception(
+            owned!(ctx, "TypeError: Receiver is not an instance of Counter").into()
+        ))
+    );
+}
+
+#[test]
+fn test_class_instance_from_rust() {
+    let c = Context::builder().build().unwrap();
+    c.register_class::<Token>().unwrap();
+    c.register_class::<Counter>().unwrap();
+
+    let token = c
+        .create_class_instance(Token("secret".to_string()))
+        .unwrap();
+    c.set_global("token", token).unwrap();
+    assert_eq!(c.eval_as::<String>("token.value").unwrap(), "secret");
+
+    c.add_callback("bump", |counter: JsClassInstance<Counter>| {
+        counter.borrow_mut().count += 1;
+        counter
+    })
+    .unwrap();
+    let counter = c.eval("bump(new Counter(1))", false).unwrap();
+    let counter = JsClassInstance::<Counter>::try_from_value(counter).unwrap();
+    assert_eq!(counter.borrow().count, 2);
+
+    assert!(c.eval("bump({})", false).is_err());
+}
@@ -0,0 +1,173 @@
+#![cfg(feature = "derive")]
+
+use quickjs_rusty::*;
+
+#[js_function]
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+#[js_function(name = "greet")]
+fn greeting(name: String, greeting: Option<String>) -> String {
+    format!(
+        "{}, {}",
+        greeting.unwrap_or_else(|| "Hello".to_string()),
+        name
+    )
+}
+
+#[js_function]
+async fn double(v: i32) -> i32 {
+    v * 2
+}
+
+#[js_class]
+struct Counter {
+    count: i32,
+    step: i32,
+}
+
+#[js_methods]
+impl Counter {
+    #[js(constructor)]
+    fn new(count: i32, step: Option<i32>) -> Self {
+        Counter {
+            count,
+            step: step.unwrap_or(1),
+        }
+    }
+
+    pub fn increment(&mut self) -> i32 {
+        self.count += self.step;
+        self.count
+    }
+
+    #[js(rename = "isZero")]
+    pub fn is_zero(&self) -> bool {
+        self.count == 0
+    }
+
+    #[js(getter)]
+    fn get_count(&self) -> i32 {
+        self.count
+    }
+
+    #[js(setter)]
+    fn set_count(&mut self, count: i32) {
+        self.count = count;
+    }
+
+    #[js(skip)]
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    fn hidden(&self) -> i32 {
+        self.step
+    }
+}
+
+#[derive(IntoJs, FromJs, Debug, PartialEq)]
+struct Point {
+    x: i32,
+    y: i32,
+    #[js(rename = "displayName")]
+    name: Option<String>,
+    #[js(skip)]
+    cache: Vec<i32>,
+}
+
+#[derive(IntoJs, FromJs, Debug, PartialEq)]
+struct Meters(f64);
+
+#[derive(IntoJs, FromJs, Debug, PartialEq)]
+enum Color {
+    Red,
+    #[js(rename = "green")]
+    Green,
+}
+
+#[test]
+fn test_derive_function() {
+    let c = Context::builder().build().unwrap();
+    c.register_function::<js_add>().unwrap();
+    c.register_function::<js_greeting>().unwrap();
+
+    assert_eq!(c.eval_as::<i32>("add(1, 2)").unwrap(), 3);
+    assert_eq!(c.eval_as::<String>("greet('Ann')").unwrap(), "Hello, Ann");
+    assert_eq!(
+        c.eval_as::<String>("greet('Ann', 'Hi')").unwrap(),
+        "Hi, Ann"
+    );
+    // The Rust function is still usable.
+    assert_eq!(add(2, 3), 5);
+}
+
+#[test]
+fn test_derive_async_function() {
+    let c = Context::builder().build().unwrap();
+    c.register_function::<js_double>().unwrap();
+
+    c.eval("double(21).then(v => globalThis.result = v)", false)
+        .unwrap();
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    assert!(c.poll_async(&mut cx).is_ready());
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 42);
+}
+
+#[test]
+fn test_derive_class() {
+    let c = Context::builder().build().unwrap();
+    c.register_class::<Counter>().unwrap();
+
+    let v = c
+        .eval_as::<i32>("const c = new Counter(1, 2); c.increment(); c.count")
+        .unwrap();
+    assert_eq!(v, 3);
+    assert!(c.eval_as::<bool>("c.count = 0; c.isZero()").unwrap());
+    assert_eq!(c.eval_as::<i32>("new Counter(5).increment()").unwrap(), 6);
+    assert!(c
+        .eval_as::<bool>("c.reset === undefined && c.hidden === undefined")
+        .unwrap());
+    let mut counter = Counter::new(1, None);
+    counter.reset();
+    assert_eq!((counter.count, counter.hidden()), (0, 1));
+}
+
+#[test]
+fn test_derive_convert() {
+    let c = Context::builder().build().unwrap();
+
+    let point = Point {
+        x: 1,
+        y: 2,
+        name: Some("origin".to_string()),
+        cache: vec![1],
+    };
+    c.set_global("point", point).unwrap();
+    assert_eq!(
+        c.eval_as::<String>("JSON.stringify(point)").unwrap(),
+        r#"{"x":1,"y":2,"displayName":"origin"}"#
+    );
+
+    let point = c.eval_as::<Point>("({ x: 3, y: 4 })").unwrap();
+    assert_eq!(
+        point,
+        Point {
+            x: 3,
+            y: 4,
+            name: None,
+            cache: vec![],
+        }
+    );
+    assert!(c.eval_as::<Point>("({ x: 'a', y: 4 })").is_err());
+
+    c.set_global("distance", Meters(2.5)).unwrap();
+    assert_eq!(c.eval_as::<f64>("distance").unwrap(), 2.5);
+    assert_eq!(c.eval_as::<Meters>("1.5").unwrap(), Meters(1.5));
+
+    c.set_global("color", Color::Green).unwrap();
+    assert_eq!(c.eval_as::<String>("color").unwrap(), "green");
+    assert_eq!(c.eval_as::<Color>("'Red'").unwrap(), Color::Red);
+    assert!(c.eval_as::<Color>("'blue'").is_err());
+}
@@ -0,0 +1,4 @@
+exports.name = 'a';
+const b = require('./b.cjs');
+exports.fromB = b.name;
+exports.bSawA = b.sawA;
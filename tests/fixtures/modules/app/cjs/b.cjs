@@ -0,0 +1,3 @@
+const a = require('./a.cjs');
+exports.name = 'b';
+exports.sawA = a.name;
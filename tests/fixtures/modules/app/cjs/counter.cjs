@@ -0,0 +1,6 @@
+let count = 0;
+module.exports = {
+  increment: () => ++count,
+  dirname: __dirname,
+  filename: __filename,
+};
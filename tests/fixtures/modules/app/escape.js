@@ -0,0 +1,3 @@
+import { secret } from '../outside.js';
+
+export default secret;
@@ -0,0 +1,8 @@
+import { add } from './lib/math';
+import { double } from './utils';
+import pkg from 'pkg';
+import tools from '@scope/tools';
+import { sub } from '@scope/tools/sub';
+import { feature } from '@scope/tools/features/a';
+
+export const result = [add(1, 2), double(2), pkg, tools, sub(3, 1), feature].join(',');
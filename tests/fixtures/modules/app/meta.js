@@ -0,0 +1,5 @@
+export const url = import.meta.url;
+export const filename = import.meta.filename;
+export const dirname = import.meta.dirname;
+export const env = import.meta.env;
+export const math = import.meta.resolve('./lib/math');
@@ -0,0 +1,3 @@
+export function double(v) {
+  return v * 2;
+}
@@ -0,0 +1,6 @@
+import { add } from './lib';
+import config from './data/config.json' with { type: 'json' };
+import blob from './data/blob.bin' with { type: 'bytes' };
+
+globalThis.result = add(config.base, 2);
+globalThis.blob = Array.from(blob).join(',');
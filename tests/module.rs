@@ -0,0 +1,688 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use quickjs_rusty::compile::{compile_module, module_to_bytecode};
+use quickjs_rusty::module_loader::{
+    loader_fn, resolver_fn, Chain, FsModuleLoader, ImportMap, ModuleResolver, ModuleSource,
+    VirtualFile, VirtualModuleFs,
+};
+use quickjs_rusty::*;
+
+#[test]
+fn test_native_module() {
+    let c = Context::builder().build().unwrap();
+
+    c.register_native_module("math", |m| {
+        m.export("answer", 42)
+            .export("name", "math")
+            .export_callback("add", |a: i32, b: i32| a + b);
+    })
+    .unwrap();
+
+    c.eval_module(
+        "import { add, answer, name } from 'math'; globalThis.result = `${name}: ${add(answer, 1)}`;",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("result").unwrap(), "math: 43");
+
+    // A second import shares the same module instance.
+    c.eval_module(
+        "import * as math from 'math'; globalThis.keys = Object.keys(math).join(',');",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("keys").unwrap(), "add,answer,name");
+}
+
+#[test]
+fn test_native_module_errors() {
+    let c = Context::builder().build().unwrap();
+
+    c.register_native_module("empty", |_| {}).unwrap();
+    assert!(c.register_native_module("empty", |_| {}).is_err());
+
+    let err = c
+        .eval_module("import { missing } from 'empty';", false)
+        .unwrap_err();
+    assert!(err.to_string().contains("missing"), "{}", err);
+}
+
+#[test]
+fn test_fs_module_loader() {
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+    c.register_native_module("native", |m| {
+        m.export("value", 1);
+    })
+    .unwrap();
+
+    c.eval_module(
+        "import { result } from './main.js'; import { value } from 'native'; globalThis.result = `${result},${value}`;",
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        c.eval_as::<String>("result").unwrap(),
+        "3,4,pkg,tools,2,a,1"
+    );
+
+    // Conditions are matched in the key order of exports.
+    c.eval_module(
+        "import ordered from 'ordered'; globalThis.ordered = ordered;",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("ordered").unwrap(), "default");
+
+    assert!(c
+        .eval_module("import { missing } from './missing.js';", false)
+        .is_err());
+    assert!(c
+        .eval_module("import { sub } from '@scope/tools/esm/sub.js';", false)
+        .is_err());
+}
+
+#[test]
+fn test_fs_module_loader_jailed() {
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app").jailed());
+
+    let err = c
+        .eval_module("import secret from './escape.js';", false)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("outside of the module root"),
+        "{}",
+        err
+    );
+
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+    c.eval_module(
+        "import secret from './escape.js'; globalThis.secret = secret;",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("secret").unwrap(), "outside");
+}
+
+#[test]
+fn test_module_loader_bytecode() {
+    let compiler = Context::builder().build().unwrap();
+    let module = compile_module(
+        unsafe { compiler.context_raw() },
+        "export const add = (a, b) => a + b;",
+        "lib",
+    )
+    .unwrap()
+    .try_into_module()
+    .unwrap();
+    let bytecode = module_to_bytecode(unsafe { compiler.context_raw() }, &module);
+    drop(module);
+
+    let c = Context::builder().build().unwrap();
+    c.set_module_loader(loader_fn(move |name, _| match name {
+        "lib" => Ok(ModuleSource::Bytecode(bytecode.clone())),
+        "broken" => Ok(ModuleSource::Bytecode(vec![0xff, 0x00])),
+        _ => Err(ModuleError::not_found(name)),
+    }));
+
+    c.eval_module(
+        "import { add } from 'lib'; globalThis.result = add(1, 2);",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 3);
+
+    assert!(c.eval_module("import 'broken';", false).is_err());
+}
+
+#[test]
+fn test_module_loader_compiled_module() {
+    let c = Context::builder().build().unwrap();
+    let raw = unsafe { c.context_raw() };
+    c.set_module_loader(loader_fn(move |name, _| {
+        let module = compile_module(raw, "export default 'compiled';", name)?;
+        Ok(ModuleSource::Module(module.try_into_module()?))
+    }));
+
+    c.eval_module("import value from 'lib'; globalThis.result = value;", false)
+        .unwrap();
+    assert_eq!(c.eval_as::<String>("result").unwrap(), "compiled");
+}
+
+#[test]
+fn test_import_attributes() {
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+
+    c.eval_module(
+        r#"
+        import config from './config.json' with { type: 'json' };
+        import text from './message.txt' with { type: 'text' };
+        import bytes from './data.bin' with { type: 'bytes' };
+        globalThis.result = [config.name, config.values.length, text, bytes instanceof Uint8Array, bytes.length].join(',');
+        "#,
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("result").unwrap(), "app,3,hello,true,3");
+
+    let err = c
+        .eval_module("import css from './style.css' with { type: 'css' };", false)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("Unsupported module type"),
+        "{}",
+        err
+    );
+
+    let err = c
+        .eval_module(
+            "import config from './config.json' with { type: 'json', other: 'x' };",
+            false,
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("Unsupported import attribute"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_custom_module_type() {
+    let c = Context::builder().build().unwrap();
+    c.set_module_loader(loader_fn(|name, attributes| {
+        assert_eq!(attributes.module_type(), Some("lines"));
+        Ok(ModuleSource::Bytes(format!("{}\nb\nc", name).into_bytes()))
+    }));
+    c.register_module_type("lines", |_, content| {
+        let content = String::from_utf8(content)?;
+        Ok(content.lines().map(str::to_string).collect::<Vec<_>>())
+    });
+
+    c.eval_module(
+        "import lines from 'a' with { type: 'lines' }; globalThis.result = lines.join('-');",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("result").unwrap(), "a-b-c");
+}
+
+#[test]
+fn test_module_exports() {
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+
+    let module = c
+        .load_module(
+            r#"
+            import { add } from './lib/math.js';
+            export const config = { name: 'plugin' };
+            export const ready = await Promise.resolve(true);
+            export function run(a, b) { return add(a, b); }
+            export function fail() { throw new TypeError('failed'); }
+            "#,
+            "plugin.js",
+        )
+        .unwrap();
+
+    assert_eq!(module.name().unwrap(), "plugin.js");
+    assert!(module.get_export::<bool>("ready").unwrap());
+    let config = module.get_export::<OwnedJsObject>("config").unwrap();
+    assert_eq!(
+        config
+            .property_require("name")
+            .unwrap()
+            .to_string()
+            .unwrap(),
+        "plugin"
+    );
+    assert_eq!(module.call_export("run", [1, 2]).unwrap().to_int(), Ok(3));
+
+    let namespace = module.namespace().unwrap();
+    assert!(namespace.property_require("run").unwrap().is_function());
+
+    let err = module.call_export("fail", Vec::<i32>::new()).unwrap_err();
+    assert!(err.to_string().contains("failed"), "{}", err);
+    assert!(module.call_export("config", [1]).is_err());
+    assert!(module.get_export::<i32>("missing").is_err());
+
+    assert!(c
+        .load_module("import './missing.js';", "broken.js")
+        .is_err());
+}
+
+#[test]
+fn test_module_loader_errors() {
+    let c = Context::builder().build().unwrap();
+    c.set_module_resolver(resolver_fn(|_, specifier| match specifier {
+        "invalid" => Err(ModuleError::Invalid("Invalid specifier".to_string())),
+        _ => Ok(specifier.to_string()),
+    }));
+    c.set_module_loader(loader_fn(|name, _| match name {
+        "syntax" => Ok(ModuleSource::from("export const = 1;")),
+        "panic" => panic!("loader panicked"),
+        "other" => Err(ModuleError::Other("Loader failed".to_string())),
+        _ => Err(ModuleError::not_found(name)),
+    }));
+
+    let error_of = |code: &str| match c.eval_module(code, false).unwrap_err() {
+        ExecutionError::Exception(e) => (e.name().unwrap().to_string(), e.to_string()),
+        e => panic!("unexpected error: {}", e),
+    };
+
+    let (name, message) = error_of("import 'missing';");
+    assert_eq!(name, "ReferenceError");
+    assert!(
+        message.contains("Cannot find module 'missing'"),
+        "{}",
+        message
+    );
+    assert_eq!(error_of("import 'invalid';").0, "SyntaxError");
+    assert_eq!(error_of("import 'syntax';").0, "SyntaxError");
+    assert_eq!(error_of("import 'other';").0, "InternalError");
+    let (name, message) = error_of("import 'panic';");
+    assert_eq!(name, "InternalError");
+    assert!(message.contains("loader panicked"), "{}", message);
+}
+
+#[test]
+fn test_module_loader_chain() {
+    let c = Context::builder().build().unwrap();
+    let fs = FsModuleLoader::new("tests/fixtures/modules/app");
+    let memory = loader_fn(|name, _| match name {
+        "virtual" => Ok(ModuleSource::from("export default 'virtual';")),
+        _ => Err(ModuleError::not_found(name)),
+    });
+    c.set_module_resolver(fs.clone());
+    c.set_module_loader(Chain::new(memory, fs));
+
+    c.eval_module(
+        "import value from 'virtual'; import { add } from './lib/math.js'; globalThis.result = `${value},${add(1, 2)}`;",
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<String>("result").unwrap(), "virtual,3");
+}
+
+#[test]
+fn test_module_reload() {
+    let c = Context::builder().build().unwrap();
+    let sources = Rc::new(RefCell::new(HashMap::from([
+        ("dep", "export const value = 1;"),
+        ("config", r#"{ "factor": 10 }"#),
+        (
+            "main",
+            "import { value } from 'dep'; import config from 'config' with { type: 'json' }; globalThis.result = value * config.factor;",
+        ),
+        ("other", "globalThis.other = (globalThis.other ?? 0) + 1;"),
+    ])));
+    let loader_sources = sources.clone();
+    c.set_module_loader(loader_fn(move |name, _| {
+        let sources = loader_sources.borrow();
+        let source = sources
+            .get(name)
+            .ok_or_else(|| ModuleError::not_found(name))?;
+        Ok(ModuleSource::from(*source))
+    }));
+    c.register_native_module("native", |m| {
+        m.export("value", 1);
+    })
+    .unwrap();
+
+    c.resolve_value(c.run_module("main").unwrap().into_value())
+        .unwrap();
+    c.resolve_value(c.run_module("other").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 10);
+    let loaded = c.loaded_modules().unwrap();
+    for name in ["dep", "config", "main", "other", "native"] {
+        assert!(loaded.iter().any(|m| m == name), "{:?}", loaded);
+    }
+
+    sources
+        .borrow_mut()
+        .insert("dep", "export const value = 2;");
+    sources
+        .borrow_mut()
+        .insert("config", r#"{ "factor": 100 }"#);
+
+    let mut invalidated = c.invalidate_module("dep").unwrap();
+    invalidated.sort();
+    assert_eq!(invalidated, ["dep", "main"]);
+    assert!(!c.loaded_modules().unwrap().iter().any(|m| m == "dep"));
+
+    c.resolve_value(c.reload_module("main").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 20);
+
+    assert_eq!(c.invalidate_module("config").unwrap().len(), 2);
+    c.resolve_value(c.run_module("main").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 200);
+
+    // Untouched modules are not evaluated again.
+    c.resolve_value(c.run_module("other").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<i32>("other").unwrap(), 1);
+
+    assert!(c.invalidate_module("native").is_err());
+    assert!(c.invalidate_module("missing").unwrap().is_empty());
+}
+
+#[test]
+fn test_import_meta() {
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+    c.set_import_meta_hook(|name, meta| {
+        if name.ends_with("meta.js") {
+            meta.set("env", "test")?;
+        }
+        Ok(())
+    });
+
+    let module = c
+        .load_module(
+            r#"
+            export * from './meta.js';
+            export const own = import.meta.url;
+            "#,
+            "plugin.js",
+        )
+        .unwrap();
+
+    let app = std::fs::canonicalize("tests/fixtures/modules/app").unwrap();
+    let app = app.to_str().unwrap();
+    let meta = format!("{}/meta.js", app);
+    assert_eq!(module.get_export::<String>("own").unwrap(), "plugin.js");
+    assert_eq!(
+        module.get_export::<String>("url").unwrap(),
+        format!("file://{}", meta)
+    );
+    assert_eq!(module.get_export::<String>("filename").unwrap(), meta);
+    assert_eq!(module.get_export::<String>("dirname").unwrap(), app);
+    assert_eq!(module.get_export::<String>("env").unwrap(), "test");
+    assert_eq!(
+        module.get_export::<String>("math").unwrap(),
+        format!("{}/lib/math.js", app)
+    );
+
+    // The path is percent-encoded in the URL.
+    let module = c
+        .load_module(
+            "export { default } from './dir with space/a#b%c.js';",
+            "space.js",
+        )
+        .unwrap();
+    assert_eq!(
+        module.get_export::<String>("default").unwrap(),
+        format!("file://{}/dir%20with%20space/a%23b%25c.js", app)
+    );
+
+    c.set_import_meta_hook(|_, _| Err(ModuleError::Other("no meta".to_string())));
+    let err = c.load_module("export {};", "failing.js").err().unwrap();
+    assert!(err.to_string().contains("no meta"), "{}", err);
+}
+
+#[test]
+fn test_commonjs() {
+    let c = Context::builder().build().unwrap();
+    c.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+    c.enable_commonjs().unwrap();
+
+    // Circular requires get the exports as they are at that point.
+    assert_eq!(
+        c.eval_as::<String>("const a = require('./cjs/a.cjs'); `${a.name},${a.fromB},${a.bSawA}`")
+            .unwrap(),
+        "a,b,a"
+    );
+    assert!(c
+        .eval_as::<bool>("require('./cjs/a.cjs') === require('./cjs/a.cjs')")
+        .unwrap());
+    assert_eq!(
+        c.eval_as::<i32>("require('./cjs/data.json').value")
+            .unwrap(),
+        42
+    );
+
+    // JSON modules do not need the JSON intrinsic.
+    let no_json = Context::builder()
+        .intrinsics(Intrinsics {
+            json: false,
+            ..Intrinsics::default()
+        })
+        .build()
+        .unwrap();
+    no_json.set_fs_module_loader(FsModuleLoader::new("tests/fixtures/modules/app"));
+    no_json.enable_commonjs().unwrap();
+    assert_eq!(
+        no_json
+            .eval_as::<i32>("require('./cjs/data.json').value")
+            .unwrap(),
+        42
+    );
+
+    // Packages are resolved with the `require` condition.
+    assert_eq!(
+        c.eval_as::<String>("require('@scope/tools')").unwrap(),
+        "tools (commonjs)"
+    );
+    assert_eq!(
+        c.eval_as::<String>("require('ordered')").unwrap(),
+        "require"
+    );
+
+    let module = c
+        .load_module(
+            r#"
+            import counter, { increment } from './cjs/counter.cjs';
+            import legacy from 'legacy';
+            export const count = increment() + counter.increment();
+            export const legacyName = legacy();
+            export const flag = legacy.flag;
+            "#,
+            "main.js",
+        )
+        .unwrap();
+    assert_eq!(module.get_export::<i32>("count").unwrap(), 3);
+    assert_eq!(module.get_export::<String>("legacyName").unwrap(), "legacy");
+    assert!(module.get_export::<bool>("flag").unwrap());
+
+    // ES modules and require share the module instance.
+    assert_eq!(
+        c.eval_as::<i32>("require('./cjs/counter.cjs').increment()")
+            .unwrap(),
+        3
+    );
+    let cjs = std::fs::canonicalize("tests/fixtures/modules/app/cjs").unwrap();
+    let cjs = cjs.to_str().unwrap();
+    assert_eq!(
+        c.eval_as::<String>("require('./cjs/counter.cjs').dirname")
+            .unwrap(),
+        cjs
+    );
+    let counter = format!("{}/counter.cjs", cjs);
+    assert_eq!(
+        c.eval_as::<String>("require.resolve('./cjs/counter.cjs')")
+            .unwrap(),
+        counter
+    );
+
+    let invalidated = c.invalidate_module(&counter).unwrap();
+    assert!(invalidated.contains(&counter), "{:?}", invalidated);
+    assert_eq!(
+        c.eval_as::<i32>("require('./cjs/counter.cjs').increment()")
+            .unwrap(),
+        1
+    );
+
+    // Failed modules are not cached.
+    for _ in 0..2 {
+        assert!(c
+            .eval_as::<bool>(
+                "try { require('./cjs/broken.cjs'); false } catch (e) { e instanceof SyntaxError }"
+            )
+            .unwrap());
+    }
+    assert!(c
+        .eval_as::<bool>(
+            "try { require('./cjs/missing.cjs'); false } catch (e) { e instanceof ReferenceError }"
+        )
+        .unwrap());
+}
+
+#[test]
+fn test_import_map() {
+    let app = std::fs::canonicalize("tests/fixtures/modules/app").unwrap();
+    let app = app.to_str().unwrap();
+    let fs = FsModuleLoader::new(app);
+    let map = ImportMap::parse(
+        r#"{
+            "imports": {
+                "math": "./lib/math.js",
+                "@utils/": "./utils/",
+                "blocked": null
+            },
+            "scopes": {
+                "./utils/": { "math": "./missing.js" }
+            }
+        }"#,
+        &format!("{}/", app),
+    )
+    .unwrap()
+    .fallback(fs.clone());
+
+    assert_eq!(
+        map.resolve("main.js", "math").unwrap(),
+        format!("{}/lib/math.js", app)
+    );
+    assert_eq!(
+        map.resolve(&format!("{}/utils/index.js", app), "math")
+            .unwrap(),
+        format!("{}/missing.js", app)
+    );
+    assert_eq!(
+        map.resolve("main.js", "@utils/index.js").unwrap(),
+        format!("{}/utils/index.js", app)
+    );
+    assert!(map.resolve("main.js", "@utils/../../outside.js").is_err());
+    assert!(matches!(
+        map.resolve("main.js", "blocked"),
+        Err(ModuleError::Invalid(_))
+    ));
+
+    let c = Context::builder().build().unwrap();
+    c.set_module_resolver(map);
+    c.set_module_loader(fs);
+    c.eval_module(
+        r#"
+        import { add } from 'math';
+        import { double } from '@utils/index.js';
+        import { double as double2 } from './utils';
+        globalThis.result = double(add(1, 2)) + double2(1);
+        "#,
+        false,
+    )
+    .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 8);
+
+    assert!(ImportMap::parse("[]", "/").is_err());
+    assert!(ImportMap::parse(r#"{ "imports": [] }"#, "/").is_err());
+}
+
+#[test]
+fn test_virtual_module_fs() {
+    let compiler = Context::builder().build().unwrap();
+    let module = compile_module(
+        unsafe { compiler.context_raw() },
+        "export const version = 2;",
+        "/vendor/version.js",
+    )
+    .unwrap()
+    .try_into_module()
+    .unwrap();
+    let bytecode = module_to_bytecode(unsafe { compiler.context_raw() }, &module);
+    drop(module);
+
+    let mut fs = VirtualModuleFs::new();
+    fs.insert(
+        "app/main.js",
+        "import { greet } from './util'; import { version } from '../vendor/version.js'; globalThis.result = greet(version);",
+    )
+    .insert("/app/util/index.js", "export const greet = (v) => `v${v}`;")
+    .insert("/vendor/version.js", bytecode)
+    .insert("/app/legacy.cjs", "module.exports = 'legacy';");
+
+    assert!(fs.is_dir("/app"));
+    assert!(fs.is_dir("/app/util/"));
+    assert!(!fs.is_dir("/app/main.js"));
+    assert!(fs.is_file("/app/./util/../main.js"));
+    assert!(matches!(
+        fs.get("/vendor/version.js"),
+        Some(VirtualFile::Bytecode(_))
+    ));
+    assert_eq!(
+        fs.resolve("/app/main.js", "./util").unwrap(),
+        "/app/util/index.js"
+    );
+    assert_eq!(
+        fs.resolve("module.js", "./app/main").unwrap(),
+        "/app/main.js"
+    );
+    assert_eq!(fs.resolve("/app/main.js", "native").unwrap(), "native");
+    assert!(matches!(
+        fs.resolve("/app/main.js", "./missing"),
+        Err(ModuleError::NotFound(_))
+    ));
+
+    let c = Context::builder().build().unwrap();
+    c.set_virtual_module_fs(fs);
+    c.enable_commonjs().unwrap();
+    c.resolve_value(c.run_module("/app/main.js").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<String>("result").unwrap(), "v2");
+    assert_eq!(
+        c.eval_as::<String>("require('./app/legacy.cjs')").unwrap(),
+        "legacy"
+    );
+
+    let fs = VirtualModuleFs::from_dir("tests/fixtures/virtual").unwrap();
+    assert_eq!(
+        fs.paths().collect::<Vec<_>>(),
+        [
+            "/data/blob.bin",
+            "/data/config.json",
+            "/lib/index.js",
+            "/lib/math.mjs",
+            "/main.js"
+        ]
+    );
+    assert!(matches!(
+        fs.get("/data/blob.bin"),
+        Some(VirtualFile::Bytes(_))
+    ));
+    let c = Context::builder().build().unwrap();
+    c.set_virtual_module_fs(fs);
+    c.resolve_value(c.run_module("/main.js").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 42);
+    assert_eq!(c.eval_as::<String>("blob").unwrap(), "255,254,0,128");
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_include_modules() {
+    let fs = include_modules!("tests/fixtures/virtual");
+    assert!(fs.is_file("/lib/math.mjs"));
+
+    let c = Context::builder().build().unwrap();
+    c.set_virtual_module_fs(fs);
+    c.resolve_value(c.run_module("/main.js").unwrap().into_value())
+        .unwrap();
+    assert_eq!(c.eval_as::<i32>("result").unwrap(), 42);
+    assert_eq!(c.eval_as::<String>("blob").unwrap(), "255,254,0,128");
+}
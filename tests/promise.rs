@@ -63,3 +63,386 @@ fn test_promise_complex() {
         "Error: 123 fulfilled reject!!!1abc"
     );
 }
+
+/// A minimal executor, blocking the current thread until `future` completes.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::{Context as TaskContext, Poll, Wake, Waker};
+
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = TaskContext::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// A minimal single-threaded executor, running `tasks` until they complete
+/// and polling a task again only once it was woken.
+fn run_tasks<'a>(tasks: Vec<std::pin::Pin<Box<dyn std::future::Future<Output = ()> + 'a>>>) {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context as TaskContext, Wake, Waker};
+
+    struct TaskWaker {
+        id: usize,
+        queue: Arc<Mutex<VecDeque<usize>>>,
+    }
+
+    impl Wake for TaskWaker {
+        fn wake(self: Arc<Self>) {
+            self.queue.lock().unwrap().push_back(self.id);
+        }
+    }
+
+    let queue = Arc::new(Mutex::new((0..tasks.len()).collect::<VecDeque<_>>()));
+    let mut tasks = tasks.into_iter().map(Some).collect::<Vec<_>>();
+    loop {
+        let Some(id) = queue.lock().unwrap().pop_front() else {
+            break;
+        };
+        let Some(task) = tasks[id].as_mut() else {
+            continue;
+        };
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            queue: queue.clone(),
+        }));
+        if task
+            .as_mut()
+            .poll(&mut TaskContext::from_waker(&waker))
+            .is_ready()
+        {
+            tasks[id] = None;
+        }
+    }
+    assert!(tasks.iter().all(Option::is_none), "a task was never woken");
+}
+
+/// A future that is pending on its first poll.
+async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+#[test]
+fn test_async_callback_resolve() {
+    let context = Context::builder().build().unwrap();
+
+    context
+        .add_async_callback("add", |a: i32, b: i32| async move {
+            yield_now().await;
+            a + b
+        })
+        .unwrap();
+
+    let promise = context
+        .eval("add(1, 2).then(v => add(v, 10))", false)
+        .unwrap()
+        .try_into_promise()
+        .unwrap();
+    assert!(context.has_pending_tasks());
+
+    block_on(context.run_async()).unwrap();
+
+    assert!(!context.has_pending_tasks());
+    assert!(matches!(promise.state(), PromiseState::Fulfilled));
+    assert_eq!(promise.result().to_int().unwrap(), 13);
+}
+
+#[test]
+fn test_async_callback_reject() {
+    let context = Context::builder().build().unwrap();
+
+    context
+        .add_async_callback("fail", |msg: String| async move {
+            yield_now().await;
+            Err::<i32, _>(msg)
+        })
+        .unwrap();
+
+    let promise = context
+        .eval("fail('oops').catch(e => 'caught: ' + e)", false)
+        .unwrap()
+        .try_into_promise()
+        .unwrap();
+
+    block_on(context.run_async()).unwrap();
+
+    assert!(matches!(promise.state(), PromiseState::Fulfilled));
+    assert_eq!(promise.result().to_string().unwrap(), "caught: Error: oops");
+
+    // Conversion errors reject the promise, as for async JS functions.
+    let promise = context
+        .eval("fail(1)", false)
+        .unwrap()
+        .try_into_promise()
+        .unwrap();
+    block_on(context.run_async()).unwrap();
+    assert!(matches!(promise.state(), PromiseState::Rejected));
+}
+
+#[test]
+fn test_async_callback_await_in_js() {
+    let context = Context::builder().build().unwrap();
+
+    context
+        .add_async_callback("double", |v: i32| async move {
+            yield_now().await;
+            v * 2
+        })
+        .unwrap();
+
+    let promise = context
+        .eval(
+            "(async () => { let v = 1; for (let i = 0; i < 3; i++) v = await double(v); return v })()",
+            false,
+        )
+        .unwrap()
+        .try_into_promise()
+        .unwrap();
+
+    block_on(context.run_async()).unwrap();
+
+    assert_eq!(promise.result().to_int().unwrap(), 8);
+}
+
+#[test]
+fn test_async_callback_resolve_value() {
+    let context = Context::builder().build().unwrap();
+
+    context
+        .add_async_callback("add", |a: i32, b: i32| async move {
+            yield_now().await;
+            a + b
+        })
+        .unwrap();
+
+    // Futures waking themselves up are driven while resolving.
+    let value = context
+        .eval("add(1, 2).then(v => add(v, 10))", true)
+        .unwrap();
+    assert_eq!(value.to_int().unwrap(), 13);
+    assert!(!context.has_pending_tasks());
+
+    // Futures waiting for an external event need an executor.
+    context
+        .add_async_callback("never", std::future::pending::<i32>)
+        .unwrap();
+    let err = context.eval("never()", true).unwrap_err();
+    assert!(err.to_string().contains("run_async"));
+    assert!(context.has_pending_tasks());
+}
+
+#[test]
+fn test_promise_resolve_keeps_globals_clean() {
+    let context = Context::builder().build().unwrap();
+
+    let value = context
+        .eval("Promise.resolve(1).then(v => v + 1)", true)
+        .unwrap();
+    assert_eq!(value.to_int().unwrap(), 2);
+
+    let keys = context
+        .eval_as::<String>("Object.keys(globalThis).join(',')")
+        .unwrap();
+    assert_eq!(keys, "");
+}
+
+#[test]
+fn test_promise_reject_value() {
+    let context = Context::builder().build().unwrap();
+
+    let err = context
+        .eval("Promise.reject(new TypeError('bad'))", true)
+        .unwrap_err();
+    let ExecutionError::Exception(reason) = err else {
+        panic!("expected an exception, got {:?}", err);
+    };
+    assert!(reason.value().is_object());
+    assert_eq!(reason.name(), Some("TypeError"));
+    assert_eq!(reason.message(), Some("bad"));
+}
+
+#[test]
+fn test_promise_resolve_nested() {
+    let context = Context::builder().build().unwrap();
+
+    context
+        .add_callback("inner", |v: i32| -> Result<i32, String> { Ok(v * 2) })
+        .unwrap();
+    let value = context
+        .eval(
+            "Promise.resolve(Promise.resolve(3)).then(v => Promise.resolve(inner(v)))",
+            true,
+        )
+        .unwrap();
+    assert_eq!(value.to_int().unwrap(), 6);
+}
+
+#[test]
+fn test_promise_resolve_never_settles() {
+    let context = Context::builder().build().unwrap();
+
+    let res = context.eval("new Promise(() => {})", true);
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_promise_resolve_limits() {
+    let context = Context::builder()
+        .resolve_limits(ResolveLimits {
+            max_jobs: Some(1000),
+            timeout: None,
+        })
+        .build()
+        .unwrap();
+
+    let code = "new Promise(() => { (function spin() { Promise.resolve().then(spin) })() })";
+    assert_eq!(
+        context.eval(code, true),
+        Err(ExecutionError::JobLimitExceeded)
+    );
+
+    let limits = ResolveLimits {
+        max_jobs: None,
+        timeout: Some(std::time::Duration::from_millis(50)),
+    };
+    let promise = context.eval(code, false).unwrap();
+    assert_eq!(
+        context.resolve_value_with_limits(promise, limits),
+        Err(ExecutionError::Timeout)
+    );
+
+    // Settling within the limits is unaffected.
+    let value = context.eval("Promise.resolve(7)", true).unwrap();
+    assert_eq!(value.to_int().unwrap(), 7);
+}
+
+#[test]
+fn test_promise_into_future() {
+    let context = Context::builder().build().unwrap();
+
+    context
+        .add_async_callback("delayed", |v: i32| async move {
+            yield_now().await;
+            v
+        })
+        .unwrap();
+
+    let promise = context
+        .eval(
+            "(async () => (await delayed(20)) + (await delayed(22)))()",
+            false,
+        )
+        .unwrap()
+        .try_into_promise()
+        .unwrap();
+    let value = block_on(promise.into_future(&context)).unwrap();
+    assert_eq!(value.to_int().unwrap(), 42);
+
+    let promise = context
+        .eval(
+            "delayed(1).then(() => { throw new Error('failed') })",
+            false,
+        )
+        .unwrap()
+        .try_into_promise()
+        .unwrap();
+    let err = block_on(promise.into_future(&context)).unwrap_err();
+    assert_eq!(err.to_string(), "Error: failed");
+}
+
+#[test]
+fn test_promise_into_future_resolved_from_rust() {
+    use std::future::Future;
+
+    let context = Context::builder().build().unwrap();
+    let ctx = unsafe { context.context_raw() };
+
+    let (promise, resolve, _) = OwnedJsPromise::with_resolvers(&context).unwrap();
+    let mut future = std::pin::pin!(promise.into_future(&context));
+
+    let waker = std::task::Waker::noop();
+    let mut cx = std::task::Context::from_waker(waker);
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+
+    resolve.call(vec![owned!(ctx, "done")]).unwrap();
+    let std::task::Poll::Ready(value) = future.as_mut().poll(&mut cx) else {
+        panic!("promise should have settled");
+    };
+    assert_eq!(value.unwrap().to_string().unwrap(), "done");
+}
+
+#[test]
+fn test_promise_future_releases_waker() {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context as TaskContext, Wake, Waker};
+
+    struct NoopWake;
+
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let context = Context::builder().build().unwrap();
+    let wake = Arc::new(NoopWake);
+    let waker = Waker::from(wake.clone());
+    let mut cx = TaskContext::from_waker(&waker);
+
+    {
+        let (promise, resolve, reject) = OwnedJsPromise::with_resolvers(&context).unwrap();
+        let mut future = std::pin::pin!(promise.into_future(&context));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(Arc::strong_count(&wake), 3);
+        drop((resolve, reject));
+    }
+
+    // The reactions holding the waker are freed with the promise.
+    context.runtime().run_gc();
+    assert_eq!(Arc::strong_count(&wake), 2);
+}
+
+#[test]
+fn test_promise_future_woken_by_resolve_from_task() {
+    let context = Context::builder().build().unwrap();
+    let ctx = unsafe { context.context_raw() };
+
+    let (promise, resolve, _) = OwnedJsPromise::with_resolvers(&context).unwrap();
+    let result = std::cell::RefCell::new(None);
+    run_tasks(vec![
+        Box::pin(async {
+            *result.borrow_mut() = Some(promise.into_future(&context).await);
+        }),
+        Box::pin(async {
+            yield_now().await;
+            resolve.call(vec![owned!(ctx, "done")]).unwrap();
+        }),
+    ]);
+
+    let value = result
+        .into_inner()
+        .expect("the future should have completed");
+    assert_eq!(value.unwrap().to_string().unwrap(), "done");
+}
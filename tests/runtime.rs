@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use quickjs_rusty::value::*;
 use quickjs_rusty::*;
@@ -202,10 +203,9 @@ fn test_eval_syntax_error() {
         "#,
             false
         ),
-        Err(ExecutionError::Exception(owned!(
-            ctx,
-            "SyntaxError: unexpected token in expression: \'\'"
-        )))
+        Err(ExecutionError::Exception(
+            owned!(ctx, "SyntaxError: unexpected token in expression: \'\'").into()
+        ))
     );
 }
 
@@ -223,10 +223,66 @@ fn test_eval_exception() {
         "#,
             false
         ),
-        Err(ExecutionError::Exception(owned!(ctx, "Error: My Error")))
+        Err(ExecutionError::Exception(
+            owned!(ctx, "Error: My Error").into()
+        ))
     );
 }
 
+#[test]
+fn test_eval_exception_details() {
+    let c = Context::builder().build().unwrap();
+
+    let err = c
+        .eval(
+            "function f() {\n  throw new RangeError('out', { cause: new TypeError('inner') });\n}\nf();",
+            false,
+        )
+        .unwrap_err();
+    let ExecutionError::Exception(e) = err else {
+        panic!("expected an exception, got {:?}", err);
+    };
+
+    assert_eq!(e.to_string(), "RangeError: out");
+    assert_eq!(e.name(), Some("RangeError"));
+    assert_eq!(e.message(), Some("out"));
+
+    let frames = e.frames();
+    assert!(frames.len() >= 2);
+    assert_eq!(frames[0].function, "f");
+    assert_eq!(frames[0].file.as_deref(), Some("script.js"));
+    assert_eq!(frames[0].line, Some(2));
+    assert_eq!(frames[1].line, Some(4));
+
+    let cause = e.cause().unwrap();
+    assert_eq!(cause.name(), Some("TypeError"));
+    assert_eq!(cause.message(), Some("inner"));
+    assert!(cause.cause().is_none());
+}
+
+#[test]
+fn test_eval_exception_thrown_value() {
+    let c = Context::builder().build().unwrap();
+
+    let err = c.eval("throw { code: 42 }", false).unwrap_err();
+    let ExecutionError::Exception(e) = err else {
+        panic!("expected an exception, got {:?}", err);
+    };
+    assert_eq!(e.name(), None);
+
+    let value = e.into_value().try_into_object().unwrap();
+    assert_eq!(value.property_require("code").unwrap().to_int(), Ok(42));
+
+    // Cyclic causes are not followed forever.
+    let err = c
+        .eval("const e = new Error('loop'); e.cause = e; throw e", false)
+        .unwrap_err();
+    let ExecutionError::Exception(e) = err else {
+        panic!("expected an exception, got {:?}", err);
+    };
+    assert_eq!(e.cause().unwrap().message(), Some("loop"));
+}
+
 #[test]
 fn eval_async() {
     let c = Context::builder().build().unwrap();
@@ -391,7 +447,7 @@ fn call_async() {
     let res = c.call_function("asyncErr", vec![owned!(ctx, true)]);
     assert_eq!(
         res,
-        Err(ExecutionError::Exception(owned!(ctx, "Failed...")))
+        Err(ExecutionError::Exception(owned!(ctx, "Failed...").into()))
     );
 }
 
@@ -480,7 +536,7 @@ fn test_callback_argn_variants() {
 
                     let code = format!("{}( {} )", name, "1,".repeat($len));
                     let res = c.eval(&code,false);
-                    assert_eq!(res, Err(ExecutionError::Exception(owned!(ctx, "error"))));
+                    assert_eq!(res, Err(ExecutionError::Exception(owned!(ctx, "Error: error").into())));
                 }
             )*
         }
@@ -531,6 +587,185 @@ fn test_callback_varargs() {
     .unwrap();
 }
 
+#[test]
+fn test_callback_throw_error_objects() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback("fail", |kind: String| -> Result<i32, JsThrow> {
+        Err(match kind.as_str() {
+            "type" => JsThrow::type_error("bad type"),
+            "range" => JsThrow::range_error("bad range").with_property("limit", 10),
+            "custom" => JsThrow::named("ValidationError", "bad input"),
+            _ => JsThrow::error("plain"),
+        })
+    })
+    .unwrap();
+    c.add_callback("fail_str", || -> Result<i32, String> {
+        Err("message".to_string())
+    })
+    .unwrap();
+
+    let check = |code: &str| c.eval_as::<String>(code).unwrap();
+    assert_eq!(
+        check("try { fail('type') } catch (e) { `${e instanceof TypeError} ${e.message}` }"),
+        "true bad type"
+    );
+    assert_eq!(
+        check("try { fail('range') } catch (e) { `${e instanceof RangeError} ${e.limit}` }"),
+        "true 10"
+    );
+    assert_eq!(
+        check("try { fail('custom') } catch (e) { `${e instanceof Error} ${e}` }"),
+        "true ValidationError: bad input"
+    );
+    assert_eq!(
+        check("try { fail_str() } catch (e) { `${e instanceof Error} ${typeof e.stack} ${e}` }"),
+        "true string Error: message"
+    );
+
+    let err = c.eval("fail('type')", false).unwrap_err();
+    let ExecutionError::Exception(e) = err else {
+        panic!("expected an exception, got {:?}", err);
+    };
+    assert_eq!(e.name(), Some("TypeError"));
+}
+
+#[test]
+fn test_callback_panic_internal_error() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback("boom", || -> i32 { panic!("boom went the callback") })
+        .unwrap();
+
+    let caught = c
+        .eval_as::<String>(
+            "try { boom() } catch (e) { `${e instanceof InternalError} ${e.message}` }",
+        )
+        .unwrap();
+    assert_eq!(caught, "true boom went the callback");
+}
+
+#[test]
+fn test_callback_conversion_type_error() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback("square", |v: i32| v * v).unwrap();
+
+    let caught = c
+        .eval_as::<bool>("try { square('a') } catch (e) { e instanceof TypeError }")
+        .unwrap();
+    assert!(caught);
+}
+
+#[test]
+fn test_callback_this() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback("describe", |this: This<OwnedJsObject>, prefix: String| {
+        let name = this.property_require("name")?.to_string()?;
+        Ok::<_, ExecutionError>(format!("{}{}", prefix, name))
+    })
+    .unwrap();
+
+    let v = c
+        .eval_as::<String>("const o = { name: 'obj', describe }; o.describe('my ')")
+        .unwrap();
+    assert_eq!(v, "my obj");
+
+    // `this` is undefined for plain calls.
+    assert!(c.eval("describe('x')", false).is_err());
+}
+
+#[test]
+fn test_callback_ctx() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback("makePair", |ctx: Ctx, a: i32, b: i32| {
+        let obj = ctx.new_object()?;
+        obj.set_property("a", ctx.value(a))?;
+        obj.set_property("b", ctx.value(b))?;
+        Ok::<_, ExecutionError>(obj)
+    })
+    .unwrap();
+    c.add_callback("callGlobal", |ctx: Ctx, name: String| {
+        let func = ctx.global()?.property_require(&name)?.try_into_function()?;
+        func.call(vec![ctx.value(20)])
+    })
+    .unwrap();
+    c.eval("function double(v) { return v * 2 }", false)
+        .unwrap();
+
+    let v = c
+        .eval_as::<i32>("const p = makePair(1, 2); p.a + p.b")
+        .unwrap();
+    assert_eq!(v, 3);
+    assert_eq!(c.eval_as::<i32>("callGlobal('double')").unwrap(), 40);
+    // Extractors do not count as Javascript arguments.
+    assert_eq!(c.eval_as::<i32>("makePair.length").unwrap(), 2);
+}
+
+#[test]
+fn test_callback_ctx_eval() {
+    let c = Context::builder().build().unwrap();
+    let handle = c.termination_handle();
+    let terminate = handle.clone();
+    c.add_callback("evalIn", |ctx: Ctx, code: String| ctx.eval(&code))
+        .unwrap();
+    c.add_callback("terminate", move || terminate.terminate())
+        .unwrap();
+
+    assert_eq!(c.eval_as::<i32>("evalIn('1 + 1')").unwrap(), 2);
+
+    // Code evaluated by callbacks is metered and stopped like the calling code.
+    c.set_budget(Some(10_000));
+    assert_eq!(
+        c.eval("evalIn('for (;;) {}')", false),
+        Err(ExecutionError::BudgetExceeded)
+    );
+    c.set_budget(None);
+
+    assert_eq!(
+        c.eval("terminate(); try { evalIn('1') } catch (e) {}", false),
+        Err(ExecutionError::Terminated)
+    );
+    handle.reset();
+    assert_eq!(c.eval_as::<i32>("evalIn('1 + 1')").unwrap(), 2);
+}
+
+#[test]
+fn test_callback_constructor() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_constructor(
+        "Vec2",
+        |ctx: Ctx, target: NewTarget, x: f64, y: f64| -> Result<OwnedJsObject, ExecutionError> {
+            if !target.is_construct_call() {
+                return Err(ExecutionError::Internal("Vec2 requires new".to_string()));
+            }
+            let obj = target.new_object(ctx)?;
+            obj.set_property("len", ctx.value((x * x + y * y).sqrt()))?;
+            Ok(obj)
+        },
+    )
+    .unwrap();
+
+    let v = c
+        .eval_as::<bool>("const v = new Vec2(3, 4); v instanceof Vec2 && v.len === 5")
+        .unwrap();
+    assert!(v);
+    assert!(c.eval("Vec2(3, 4)", false).is_err());
+    // A constructor as receiver does not make a construct call.
+    assert!(c.eval("Vec2.call(Vec2, 3, 4)", false).is_err());
+    assert!(c.eval("({ Vec2 }).Vec2(3, 4)", false).is_err());
+    assert!(c
+        .eval_as::<bool>("Reflect.construct(Vec2, [3, 4]).len === 5")
+        .unwrap());
+
+    // Regular callbacks can not be called with new.
+    c.add_callback("plain", || 1).unwrap();
+    assert!(c.eval("new plain()", false).is_err());
+}
+
 #[test]
 fn test_callback_invalid_argcount() {
     let c = Context::builder().build().unwrap();
@@ -540,10 +775,115 @@ fn test_callback_invalid_argcount() {
 
     assert_eq!(
         c.eval(" cb(5) ", false),
-        Err(ExecutionError::Exception(owned!(
-            ctx,
-            "Invalid argument count: Expected 2, got 1"
-        ))),
+        Err(ExecutionError::Exception(
+            owned!(ctx, "TypeError: Missing argument at index 1").into()
+        )),
+    );
+    // Extra arguments are ignored.
+    assert_eq!(c.eval_as::<i32>(" cb(1, 2, 3) ").unwrap(), 3);
+}
+
+#[test]
+fn test_callback_optional_args() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback("greet", |name: String, greeting: Option<String>| {
+        format!(
+            "{}, {}",
+            greeting.unwrap_or_else(|| "Hello".to_string()),
+            name
+        )
+    })
+    .unwrap();
+
+    assert_eq!(c.eval_as::<String>("greet('Ann')").unwrap(), "Hello, Ann");
+    assert_eq!(
+        c.eval_as::<String>("greet('Ann', undefined)").unwrap(),
+        "Hello, Ann"
+    );
+    assert_eq!(
+        c.eval_as::<String>("greet('Ann', 'Hi')").unwrap(),
+        "Hi, Ann"
+    );
+}
+
+#[test]
+fn test_callback_rest_args() {
+    let c = Context::builder().build().unwrap();
+    let ctx = unsafe { c.context_raw() };
+
+    c.add_callback("join", |sep: String, parts: Rest<String>| parts.join(&sep))
+        .unwrap();
+
+    assert_eq!(c.eval_as::<String>("join('-')").unwrap(), "");
+    assert_eq!(
+        c.eval_as::<String>("join('-', 'a', 'b', 'c')").unwrap(),
+        "a-b-c"
+    );
+    assert_eq!(c.eval_as::<i32>("join.length").unwrap(), 1);
+    assert_eq!(
+        c.eval("join('-', 'a', 2)", false),
+        Err(ExecutionError::Exception(
+            owned!(
+                ctx,
+                "TypeError: Invalid argument at index 2: Could not convert - received unexpected type"
+            )
+            .into()
+        )),
+    );
+}
+
+#[test]
+fn test_callback_many_args() {
+    let c = Context::builder().build().unwrap();
+
+    c.add_callback(
+        "sum16",
+        |a1: i32,
+         a2: i32,
+         a3: i32,
+         a4: i32,
+         a5: i32,
+         a6: i32,
+         a7: i32,
+         a8: i32,
+         a9: i32,
+         a10: i32,
+         a11: i32,
+         a12: i32,
+         a13: i32,
+         a14: i32,
+         a15: i32,
+         a16: i32| {
+            a1 + a2 + a3 + a4 + a5 + a6 + a7 + a8 + a9 + a10 + a11 + a12 + a13 + a14 + a15 + a16
+        },
+    )
+    .unwrap();
+
+    let v = c
+        .eval_as::<i32>("sum16(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16)")
+        .unwrap();
+    assert_eq!(v, 136);
+    assert_eq!(c.eval_as::<i32>("sum16.length").unwrap(), 16);
+}
+
+#[test]
+fn test_callback_argument_error_index() {
+    let c = Context::builder().build().unwrap();
+    let ctx = unsafe { c.context_raw() };
+
+    c.add_callback("repeat", |s: String, n: i32| s.repeat(n as usize))
+        .unwrap();
+
+    assert_eq!(
+        c.eval("repeat('a', 'b')", false),
+        Err(ExecutionError::Exception(
+            owned!(
+                ctx,
+                "TypeError: Invalid argument at index 1: Could not convert - received unexpected type"
+            )
+            .into()
+        )),
     );
 }
 
@@ -603,6 +943,380 @@ fn context_reset() {
     assert!(err_msg.contains("ReferenceError"));
 }
 
+#[test]
+fn shared_runtime() {
+    let runtime = Runtime::new().unwrap();
+    let a = runtime.context().unwrap();
+    let b = Context::builder().runtime(runtime.clone()).build().unwrap();
+    assert!(a.runtime().ptr_eq(b.runtime()));
+
+    // Each context has its own globals.
+    a.eval(" var x = 1; ", false).unwrap();
+    assert!(b.eval_as::<bool>(" typeof x === 'undefined' ").unwrap());
+
+    // Values can be passed between the contexts.
+    let counter = a.eval(" ({ count: 1 }) ", false).unwrap();
+    b.set_global("counter", counter.clone()).unwrap();
+    b.eval(" counter.count += 1; ", false).unwrap();
+    let counter = counter.try_into_object().unwrap();
+    assert_eq!(counter.property_require("count").unwrap().to_int(), Ok(2));
+    drop(counter);
+
+    // The job queue is shared, jobs of `b` run when resolving in `a`.
+    b.eval(
+        " Promise.resolve().then(() => { counter.count = 3; }); ",
+        false,
+    )
+    .unwrap();
+    let value = a.eval(" Promise.resolve(1) ", true).unwrap();
+    assert_eq!(value.to_int(), Ok(1));
+    assert_eq!(b.eval_as::<i32>(" counter.count ").unwrap(), 3);
+
+    // The runtime outlives its handle while contexts use it.
+    drop(runtime);
+    drop(value);
+    drop(a);
+    assert_eq!(b.eval_as::<i32>(" counter.count ").unwrap(), 3);
+}
+
+#[test]
+fn shared_runtime_memory_limit() {
+    let runtime = Runtime::new().unwrap();
+    let a = runtime.context().unwrap();
+    let b = runtime.context().unwrap();
+    runtime.set_memory_limit(300_000);
+
+    assert_eq!(
+        a.eval("  'abc'.repeat(200_000) ", false),
+        Err(ExecutionError::OutOfMemory),
+    );
+    assert_eq!(
+        b.eval("  'abc'.repeat(200_000) ", false),
+        Err(ExecutionError::OutOfMemory),
+    );
+}
+
+#[test]
+fn context_intrinsics() {
+    let c = Context::builder()
+        .intrinsics(Intrinsics {
+            eval: false,
+            proxy: false,
+            date: false,
+            ..Intrinsics::default()
+        })
+        .build()
+        .unwrap();
+
+    assert!(c
+        .eval_as::<bool>(" typeof Proxy === 'undefined' && typeof Date === 'undefined' ")
+        .unwrap());
+    assert!(c.eval_as::<bool>(" typeof Map === 'function' ").unwrap());
+
+    // Code generation from strings throws, evaluating code from Rust does not.
+    for code in [
+        " eval('1') ",
+        " (0, eval)('1') ",
+        " new Function('return 1') ",
+        " (function () {}).constructor('return 1') ",
+        " Object.getPrototypeOf(function* () {}).constructor('yield 1') ",
+        " Object.getPrototypeOf(async function () {}).constructor('return 1') ",
+    ] {
+        let err = c.eval(code, false).unwrap_err().to_string();
+        assert!(err.contains("EvalError"), "{}: {}", code, err);
+    }
+    assert_eq!(c.eval_as::<i32>(" 1 + 1 ").unwrap(), 2);
+    assert!(c
+        .eval_as::<bool>(
+            " (() => {}) instanceof Function && Function.prototype.constructor === Function "
+        )
+        .unwrap());
+
+    // The replacements can not be overwritten nor deleted.
+    for code in [
+        " eval = (code) => 1; ",
+        " delete globalThis.eval ",
+        " Function = Object; ",
+        " Object.defineProperty(Function.prototype, 'constructor', { value: Object }) ",
+    ] {
+        let _ = c.eval(code, false);
+        for check in [" eval('1') ", " new Function('return 1') "] {
+            let err = c.eval(check, false).unwrap_err().to_string();
+            assert!(err.contains("EvalError"), "{}: {}", code, err);
+        }
+    }
+    assert!(!c
+        .eval_as::<bool>(" delete globalThis.eval || delete globalThis.Function ")
+        .unwrap());
+
+    // The intrinsics are kept by a reset.
+    let c = c.reset().unwrap();
+    assert!(c.eval_as::<bool>(" typeof Proxy === 'undefined' ").unwrap());
+    assert!(c.eval(" eval('1') ", false).is_err());
+
+    // The console and JSON conversions do not need the JSON intrinsic.
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = logged.clone();
+    let c = Context::builder()
+        .intrinsics(Intrinsics {
+            json: false,
+            ..Intrinsics::default()
+        })
+        .console(move |_, args: Vec<OwnedJsValue>| {
+            let args = args.iter().map(|arg| arg.to_string().unwrap());
+            log.lock().unwrap().extend(args);
+        })
+        .build()
+        .unwrap();
+    c.eval(" console.log('hi', { a: 1 }.a) ", false).unwrap();
+    assert_eq!(
+        *logged.lock().unwrap(),
+        vec!["hi".to_string(), "1".to_string()]
+    );
+    let value = c.eval(" ({ a: [1, 2] }) ", false).unwrap();
+    assert_eq!(value.to_json_string(0).unwrap(), r#"{"a":[1,2]}"#);
+    drop(value);
+
+    let c = Context::builder()
+        .intrinsics(Intrinsics::none())
+        .build()
+        .unwrap();
+    assert!(c
+        .eval_as::<bool>(" typeof JSON === 'undefined' && typeof Promise === 'undefined' ")
+        .unwrap());
+    assert_eq!(
+        c.eval_as::<i32>(" [1, 2, 3].reduce((a, b) => a + b) ")
+            .unwrap(),
+        6
+    );
+}
+
+#[test]
+fn interrupt_handler() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let c = Context::builder().build().unwrap();
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    c.set_interrupt(move || {
+        counter.set(counter.get() + 1);
+        counter.get() > 3
+    });
+
+    // The interruption can not be caught by Javascript code.
+    let result = c.eval(" try { for (;;) {} } catch (e) {} ", false);
+    assert_eq!(result, Err(ExecutionError::Interrupted));
+    assert_eq!(calls.get(), 4);
+
+    // Interruptions go through callbacks calling back into Javascript.
+    calls.set(0);
+    c.add_callback("run", |f: JsFunction| f.call(vec![]))
+        .unwrap();
+    let result = c.eval(
+        " try { run(() => { for (;;) {} }); } catch (e) {} 1 ",
+        false,
+    );
+    assert_eq!(result, Err(ExecutionError::Interrupted));
+
+    c.runtime().remove_interrupt();
+    assert_eq!(c.eval_as::<i32>(" 1 + 1 ").unwrap(), 2);
+}
+
+#[test]
+fn interrupt_handler_replaces_itself() {
+    let c = Context::builder().build().unwrap();
+
+    let runtime = c.runtime().clone();
+    c.set_interrupt(move || {
+        // Replaced by a handler interrupting at its first call.
+        runtime.set_interrupt(|| true);
+        false
+    });
+    let result = c.eval(" for (;;) {} ", false);
+    assert_eq!(result, Err(ExecutionError::Interrupted));
+
+    let runtime = c.runtime().clone();
+    c.set_interrupt(move || {
+        runtime.remove_interrupt();
+        true
+    });
+    let result = c.eval(" for (;;) {} ", false);
+    assert_eq!(result, Err(ExecutionError::Interrupted));
+
+    // The handler removed itself.
+    c.eval(" for (let i = 0; i < 100000; i++) {} ", false)
+        .unwrap();
+}
+
+#[test]
+fn eval_with_timeout() {
+    use std::time::{Duration, Instant};
+
+    let c = Context::builder().build().unwrap();
+    assert_eq!(
+        c.eval_with_timeout(" 1 + 2 ", Duration::from_secs(10))
+            .unwrap()
+            .to_int(),
+        Ok(3)
+    );
+
+    let start = Instant::now();
+    let result = c.eval_with_timeout(
+        " try { for (;;) {} } finally { 1 } ",
+        Duration::from_millis(50),
+    );
+    assert_eq!(result, Err(ExecutionError::Timeout));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // Nested timeouts can only shorten the outer one.
+    c.eval(" function spin() { for (;;) {} } ", false).unwrap();
+    let result = c.with_timeout(Duration::from_millis(50), || {
+        c.with_timeout(Duration::from_secs(60), || {
+            c.call_function("spin", Vec::<i32>::new())
+        })
+    });
+    assert_eq!(result, Err(ExecutionError::Timeout));
+
+    // The deadline does not outlive the call.
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(
+        c.eval_as::<i32>(" let n = 0; for (let i = 0; i < 100000; i++) n++; n ")
+            .unwrap(),
+        100000
+    );
+
+    // Nor a panic.
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        c.with_timeout(Duration::ZERO, || panic!("boom"))
+    }));
+    assert!(panicked.is_err());
+    assert_eq!(
+        c.eval_as::<i32>(" let m = 0; for (let i = 0; i < 100000; i++) m++; m ")
+            .unwrap(),
+        100000
+    );
+}
+
+#[test]
+fn termination_handle() {
+    use std::time::Duration;
+
+    fn assert_send_sync<T: Send + Sync + Clone>(_: &T) {}
+
+    let c = Context::builder().build().unwrap();
+    let handle = c.termination_handle();
+    assert_send_sync(&handle);
+    assert!(!handle.is_terminated());
+
+    let watchdog = handle.clone();
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        watchdog.terminate();
+    });
+    let result = c.eval(" try { for (;;) {} } catch (e) {} ", false);
+    thread.join().unwrap();
+    assert_eq!(result, Err(ExecutionError::Terminated));
+    assert!(handle.is_terminated());
+
+    // Code keeps failing until the handle is reset, even if it would
+    // complete before the next interrupt check.
+    assert_eq!(c.eval(" 1 + 1 ", false), Err(ExecutionError::Terminated));
+    assert_eq!(
+        c.call_function("parseInt", vec!["1"]),
+        Err(ExecutionError::Terminated)
+    );
+    assert_eq!(
+        c.eval_module(" globalThis.x = 1; ", false),
+        Err(ExecutionError::Terminated)
+    );
+
+    handle.reset();
+    assert!(!c.termination_handle().is_terminated());
+    assert_eq!(c.eval_as::<i32>(" 1 + 1 ").unwrap(), 2);
+    assert!(!c.eval_as::<bool>(" 'x' in globalThis ").unwrap());
+}
+
+#[test]
+fn execution_budget() {
+    let c = Context::builder().build().unwrap();
+    c.eval(
+        " function work(n) { let s = 0; for (let i = 0; i < n; i++) { s += i; } return s; } ",
+        false,
+    )
+    .unwrap();
+
+    c.set_budget(Some(100_000));
+    c.call_function("work", vec![1000]).unwrap();
+    let units = c.consumed_units();
+    assert!((1000..100_000).contains(&units));
+
+    // The same work consumes the same units.
+    c.call_function("work", vec![1000]).unwrap();
+    assert_eq!(c.consumed_units(), units);
+    c.eval(" work(1000) ", false).unwrap();
+    let eval_units = c.consumed_units();
+    c.eval(" work(1000) ", false).unwrap();
+    assert_eq!(c.consumed_units(), eval_units);
+
+    // Spanning several interrupt counter resets.
+    c.call_function("work", vec![50_000]).unwrap();
+    let large = c.consumed_units();
+    c.call_function("work", vec![50_000]).unwrap();
+    assert_eq!(c.consumed_units(), large);
+
+    let result = c.eval(" try { for (;;) {} } catch (e) {} ", false);
+    assert_eq!(result, Err(ExecutionError::BudgetExceeded));
+    assert_eq!(c.consumed_units(), 100_000);
+
+    // The budget is per execution.
+    assert_eq!(c.eval_as::<i32>(" work(10) ").unwrap(), 45);
+
+    c.set_budget(None);
+    c.eval(" for (let i = 0; i < 200000; i++) {} ", false)
+        .unwrap();
+}
+
+#[test]
+fn execution_budget_nested_context() {
+    let runtime = Runtime::new().unwrap();
+    let a = runtime.context().unwrap();
+    let b = Rc::new(runtime.context().unwrap());
+    b.eval(
+        " function work(n) { let s = 0; for (let i = 0; i < n; i++) { s += i; } return s; } ",
+        false,
+    )
+    .unwrap();
+    b.set_budget(Some(100_000));
+    b.call_function("work", vec![1000]).unwrap();
+    let units = b.consumed_units();
+    b.set_budget(None);
+
+    let inner = b.clone();
+    a.add_callback("work", move |n: i32| {
+        inner.call_function("work", vec![n]).is_ok()
+    })
+    .unwrap();
+    let inner = b.clone();
+    a.add_callback("spin", move || inner.eval(" for (;;) {} ", false).is_ok())
+        .unwrap();
+
+    // The units of the other context are counted by the outer execution.
+    a.set_budget(Some(100_000));
+    assert!(a.eval_as::<bool>(" work(1000) ").unwrap());
+    assert!(a.consumed_units() > units);
+
+    // The nested execution exceeds the budget of the outer one.
+    let result = a.eval(" spin(); for (;;) {} ", false);
+    assert_eq!(result, Err(ExecutionError::BudgetExceeded));
+    assert_eq!(a.consumed_units(), 100_000);
+
+    // The other context is not metered afterwards.
+    assert!(b
+        .eval(" for (let i = 0; i < 200000; i++) {} ", false)
+        .is_ok());
+}
+
 #[inline(never)]
 fn build_context() -> Context {
     let ctx = Context::builder().build().unwrap();
@@ -2,7 +2,7 @@ use std::fmt::Debug;
 
 use libquickjs_ng_sys::JSContext;
 use quickjs_rusty::serde::{from_js, to_js};
-use quickjs_rusty::{value::OwnedJsValue, Context};
+use quickjs_rusty::{value::OwnedJsValue, Context, Intrinsics};
 use serde_json::{json, Value};
 
 #[test]
@@ -435,6 +435,26 @@ fn serde_de_newtype_variant() {
     );
 }
 
+#[test]
+fn serde_without_json_intrinsic() {
+    let context = Context::builder()
+        .intrinsics(Intrinsics {
+            json: false,
+            ..Intrinsics::default()
+        })
+        .build()
+        .unwrap();
+    let ctx = unsafe { context.context_raw() };
+
+    let value = SimpleStruct { a: 1, b: 2 };
+    let js_value = to_js(ctx, &value).unwrap();
+    assert_eq!(js_value.to_json_string(0).unwrap(), r#"{"a":1,"b":2}"#);
+    assert_eq!(
+        parse_from_js_borrowed::<SimpleStruct>(ctx, &js_value),
+        value
+    );
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
 struct SimpleUnitStruct;
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]